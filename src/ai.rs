@@ -0,0 +1,789 @@
+use crate::component_system::components::Brain;
+use crate::map::TileMap;
+use crate::rng::Rng;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// A cell of the map as a column and row, row 0 being the top of the map
+pub type Cell = (i32, i32);
+
+/// Chasers that share one Dijkstra map instead of each searching with A*
+pub const DIJKSTRA_CHASERS: usize = 3;
+
+/// Furthest a wandering entity strays from home, in steps
+pub const WANDER_RADIUS: u32 = 4;
+
+/// Furthest a brain can see, in cells
+pub const MAX_SIGHT: u32 = 100;
+
+/// Steps to the four neighbouring cells, in the order they are tried
+const NEIGHBOURS: [Cell; 4] = [(1, 0), (-1, 0), (0, -1), (0, 1)];
+
+/// What a brain is currently doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behaviour {
+    /// Stands still
+    Idle,
+    /// Steps at random near home
+    Wander,
+    /// Heads for the player to attack them
+    Chase,
+    /// Runs from the player
+    Flee,
+    /// Walks back home after losing sight of the player
+    ReturnHome
+}
+
+impl Behaviour {
+    /// Parses a behaviour from its name in a save file
+    pub fn from_name(name: &str) -> Option<Behaviour> {
+        match name {
+            "idle" => Some(Behaviour::Idle),
+            "wander" => Some(Behaviour::Wander),
+            "chase" => Some(Behaviour::Chase),
+            "flee" => Some(Behaviour::Flee),
+            "return_home" => Some(Behaviour::ReturnHome),
+            _ => None
+        }
+    }
+
+    /// The name of the behaviour in a save file
+    pub fn name(self) -> &'static str {
+        match self {
+            Behaviour::Idle => "idle",
+            Behaviour::Wander => "wander",
+            Behaviour::Chase => "chase",
+            Behaviour::Flee => "flee",
+            Behaviour::ReturnHome => "return_home"
+        }
+    }
+}
+
+/// Which cells can be walked on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    width: u32,
+    height: u32,
+    walkable: Vec<bool>
+}
+
+impl Grid {
+    /// Creates a grid where every cell can be walked on
+    pub fn new(width: u32, height: u32) -> Grid {
+        Grid {
+            width,
+            height,
+            walkable: vec![true; (width * height) as usize]
+        }
+    }
+
+    /// Creates a grid of the cells of a map without solid tiles
+    pub fn from_map(map: &TileMap) -> Grid {
+        let mut grid = Grid::new(map.width, map.height);
+        for row in 0..map.height as i32 {
+            for column in 0..map.width as i32 {
+                grid.set_walkable((column, row), !map.is_solid(column, row));
+            }
+        }
+        grid
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Sets if a cell can be walked on, ignoring cells outside the grid
+    pub fn set_walkable(&mut self, cell: Cell, walkable: bool) {
+        if let Some(index) = self.index(cell) {
+            self.walkable[index] = walkable;
+        }
+    }
+
+    /// Returns true if a cell can be walked on, cells outside the grid never can
+    pub fn is_walkable(&self, cell: Cell) -> bool {
+        self.index(cell).is_some_and(|index| self.walkable[index])
+    }
+
+    /// The walkable cells next to a cell
+    pub fn neighbours(&self, cell: Cell) -> impl Iterator<Item = Cell> + '_ {
+        NEIGHBOURS
+            .iter()
+            .map(move |(dx, dy)| (cell.0 + dx, cell.1 + dy))
+            .filter(move |neighbour| self.is_walkable(*neighbour))
+    }
+
+    fn index(&self, (column, row): Cell) -> Option<usize> {
+        if column < 0 || row < 0 || column >= self.width as i32 || row >= self.height as i32 {
+            return None;
+        }
+        Some((row as u32 * self.width + column as u32) as usize)
+    }
+
+    fn cell(&self, index: usize) -> Cell {
+        (
+            (index as u32 % self.width) as i32,
+            (index as u32 / self.width) as i32
+        )
+    }
+}
+
+/// Steps between two cells moving only along rows and columns
+pub fn manhattan(a: Cell, b: Cell) -> u32 {
+    (a.0 - b.0).unsigned_abs() + (a.1 - b.1).unsigned_abs()
+}
+
+/// Finds a shortest path between two cells with A*
+/// Returns the cells to step through, ending with the goal and not including the start, or
+/// `None` if the goal can not be reached.
+/// # Arguments
+/// * `grid` - The cells that can be walked on
+/// * `start` - The cell the path starts from
+/// * `goal` - The cell to reach, which may be occupied
+/// * `occupied` - Cells holding something that blocks the way
+pub fn find_path(grid: &Grid, start: Cell, goal: Cell, occupied: &[Cell]) -> Option<Vec<Cell>> {
+    let start_index = grid.index(start)?;
+    let goal_index = grid.index(goal)?;
+    if !grid.is_walkable(goal) {
+        return None;
+    }
+
+    let mut cost = vec![u32::MAX; grid.walkable.len()];
+    let mut came_from: Vec<Option<usize>> = vec![None; grid.walkable.len()];
+    let mut open = BinaryHeap::new();
+
+    // Ties go to the cell closest to the goal, then the lowest index, so paths are repeatable
+    cost[start_index] = 0;
+    open.push(Reverse((
+        manhattan(start, goal),
+        manhattan(start, goal),
+        start_index
+    )));
+
+    while let Some(Reverse((_, _, index))) = open.pop() {
+        if index == goal_index {
+            let mut path = vec![];
+            let mut current = index;
+            while current != start_index {
+                path.push(grid.cell(current));
+                current = came_from[current]?;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let cell = grid.cell(index);
+        for neighbour in grid.neighbours(cell) {
+            if neighbour != goal && occupied.contains(&neighbour) {
+                continue;
+            }
+
+            let neighbour_index = grid.index(neighbour)?;
+            let neighbour_cost = cost[index] + 1;
+            if neighbour_cost < cost[neighbour_index] {
+                cost[neighbour_index] = neighbour_cost;
+                came_from[neighbour_index] = Some(index);
+
+                let estimate = manhattan(neighbour, goal);
+                open.push(Reverse((
+                    neighbour_cost + estimate,
+                    estimate,
+                    neighbour_index
+                )));
+            }
+        }
+    }
+
+    None
+}
+
+/// Steps from every cell to the nearest of a set of goals
+/// One map guides any number of entities towards the goals by stepping downhill, or away from
+/// them by stepping uphill.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DijkstraMap {
+    grid: Grid,
+    distances: Vec<u32>
+}
+
+impl DijkstraMap {
+    /// Measures the distance from each cell to the nearest goal
+    /// # Arguments
+    /// * `grid` - The cells that can be walked on
+    /// * `goals` - The cells being measured from
+    pub fn new(grid: &Grid, goals: &[Cell]) -> DijkstraMap {
+        let mut distances = vec![u32::MAX; grid.walkable.len()];
+        let mut frontier = std::collections::VecDeque::new();
+
+        for goal in goals {
+            if let Some(index) = grid.index(*goal) {
+                distances[index] = 0;
+                frontier.push_back(*goal);
+            }
+        }
+
+        // Every step costs the same, so a breadth first search finds the shortest distances
+        while let Some(cell) = frontier.pop_front() {
+            let distance = distances[grid.index(cell).unwrap()];
+            for neighbour in grid.neighbours(cell) {
+                let index = grid.index(neighbour).unwrap();
+                if distance + 1 < distances[index] {
+                    distances[index] = distance + 1;
+                    frontier.push_back(neighbour);
+                }
+            }
+        }
+
+        DijkstraMap {
+            grid: grid.clone(),
+            distances
+        }
+    }
+
+    /// Steps from a cell to the nearest goal, or `None` if no goal can be reached
+    pub fn distance(&self, cell: Cell) -> Option<u32> {
+        self.grid
+            .index(cell)
+            .map(|index| self.distances[index])
+            .filter(|distance| *distance != u32::MAX)
+    }
+
+    /// The neighbouring cell closest to a goal, if any is closer than the cell itself
+    /// # Arguments
+    /// * `cell` - The cell to step from
+    /// * `occupied` - Cells that can not be stepped into, unless they are a goal
+    pub fn downhill(&self, cell: Cell, occupied: &[Cell]) -> Option<Cell> {
+        let mut best = (self.distance(cell)?, None);
+        for neighbour in self.grid.neighbours(cell) {
+            let distance = match self.distance(neighbour) {
+                Some(distance) => distance,
+                None => continue
+            };
+
+            if distance < best.0 && (distance == 0 || !occupied.contains(&neighbour)) {
+                best = (distance, Some(neighbour));
+            }
+        }
+        best.1
+    }
+
+    /// The neighbouring cell furthest from every goal, if any is further than the cell itself
+    /// # Arguments
+    /// * `cell` - The cell to step from
+    /// * `occupied` - Cells that can not be stepped into
+    pub fn uphill(&self, cell: Cell, occupied: &[Cell]) -> Option<Cell> {
+        let mut best = (self.distance(cell)?, None);
+        for neighbour in self.grid.neighbours(cell) {
+            let distance = match self.distance(neighbour) {
+                Some(distance) => distance,
+                None => continue
+            };
+
+            if distance > best.0 && !occupied.contains(&neighbour) {
+                best = (distance, Some(neighbour));
+            }
+        }
+        best.1
+    }
+}
+
+/// Returns true if nothing solid lies on the straight line between two cells within a range
+/// # Arguments
+/// * `grid` - Cells that can not be walked on block sight
+/// * `from` - The cell looking
+/// * `to` - The cell looked at
+/// * `range` - Furthest distance that can be seen, in cells
+pub fn can_see(grid: &Grid, from: Cell, to: Cell, range: u32) -> bool {
+    let (dx, dy) = ((to.0 - from.0).abs(), (to.1 - from.1).abs());
+    if (dx as u64).pow(2) + (dy as u64).pow(2) > u64::from(range).pow(2) {
+        return false;
+    }
+
+    // Walk the line with Bresenham's algorithm, only the cells between the ends can block it
+    let step = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let mut error = dx - dy;
+    let mut cell = from;
+    while cell != to {
+        if cell != from && !grid.is_walkable(cell) {
+            return false;
+        }
+
+        let doubled = 2 * error;
+        if doubled > -dy {
+            error -= dy;
+            cell.0 += step.0;
+        }
+        if doubled < dx {
+            error += dx;
+            cell.1 += step.1;
+        }
+    }
+
+    true
+}
+
+/// Picks what a brain does next
+/// # Arguments
+/// * `current` - What the brain is doing now
+/// * `wanders` - If the brain wanders when it has nothing to do, instead of standing still
+/// * `sees_player` - If the player is in sight
+/// * `low_health` - If health is low enough to run away
+/// * `at_home` - If the brain's entity is standing on its home cell
+pub fn choose_behaviour(
+    current: Behaviour,
+    wanders: bool,
+    sees_player: bool,
+    low_health: bool,
+    at_home: bool
+) -> Behaviour {
+    match (sees_player, low_health, current) {
+        (true, true, _) => Behaviour::Flee,
+        (true, false, _) => Behaviour::Chase,
+        // Losing sight of the player sends the brain home
+        (false, _, Behaviour::Chase)
+        | (false, _, Behaviour::Flee)
+        | (false, _, Behaviour::ReturnHome)
+            if !at_home =>
+        {
+            Behaviour::ReturnHome
+        },
+        _ if wanders => Behaviour::Wander,
+        _ => Behaviour::Idle
+    }
+}
+
+/// Plans steps for every brain during one system run
+/// Dijkstra maps of the distance to the player are only made when they are needed and are
+/// shared by every brain using them.
+pub struct Navigator {
+    grid: Grid,
+    player: Option<Cell>,
+    /// Chasers use the shared map rather than A* when there are many of them
+    shared_chase: bool,
+    to_player: Option<DijkstraMap>
+}
+
+impl Navigator {
+    /// Creates a navigator
+    /// # Arguments
+    /// * `grid` - The cells that can be walked on
+    /// * `player` - The player's cell, if there is a player
+    /// * `chasers` - How many brains are chasing the player
+    pub fn new(grid: Grid, player: Option<Cell>, chasers: usize) -> Navigator {
+        Navigator {
+            grid,
+            player,
+            shared_chase: chasers > DIJKSTRA_CHASERS,
+            to_player: None
+        }
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn player(&self) -> Option<Cell> {
+        self.player
+    }
+
+    /// Moves the player, throwing away maps measured from where they were
+    pub fn set_player(&mut self, player: Option<Cell>) {
+        if self.player != player {
+            self.player = player;
+            self.to_player = None;
+        }
+    }
+
+    /// The distance of every cell from the player
+    fn player_map(&mut self) -> Option<&DijkstraMap> {
+        let player = self.player?;
+        let grid = &self.grid;
+        Some(
+            self.to_player
+                .get_or_insert_with(|| DijkstraMap::new(grid, &[player]))
+        )
+    }
+
+    /// Returns the cell to step into next for a behaviour, or `None` to stay put
+    /// # Arguments
+    /// * `behaviour` - What the entity is doing
+    /// * `at` - The entity's cell
+    /// * `home` - The cell the entity returns to
+    /// * `occupied` - Cells of other entities that block movement
+    /// * `rng` - Picks wandering steps
+    pub fn next_step(
+        &mut self,
+        behaviour: Behaviour,
+        at: Cell,
+        home: Cell,
+        occupied: &[Cell],
+        rng: &mut Rng
+    ) -> Option<Cell> {
+        match behaviour {
+            Behaviour::Idle => None,
+            Behaviour::Wander => {
+                let (dx, dy) = match rng.range(0, 5) {
+                    0 => (1, 0),
+                    1 => (-1, 0),
+                    2 => (0, 1),
+                    3 => (0, -1),
+                    _ => return None
+                };
+                let to = (at.0 + dx, at.1 + dy);
+
+                let free = self.grid.is_walkable(to) && !occupied.contains(&to);
+                (free && manhattan(to, home) <= WANDER_RADIUS).then_some(to)
+            },
+            Behaviour::Chase => {
+                let player = self.player?;
+                if self.shared_chase {
+                    self.player_map()?.downhill(at, occupied)
+                } else {
+                    find_path(&self.grid, at, player, occupied)?
+                        .first()
+                        .copied()
+                }
+            },
+            Behaviour::Flee => self.player_map()?.uphill(at, occupied),
+            Behaviour::ReturnHome => find_path(&self.grid, at, home, occupied)?.first().copied()
+        }
+    }
+}
+
+/// Updates a brain's behaviour and returns the cell it wants to step into next
+/// # Arguments
+/// * `brain` - The brain, given a home at its cell if it has none
+/// * `at` - The brain's cell
+/// * `health` - The entity's health as a fraction of its maximum, if it has health
+/// * `navigator` - Plans the step
+/// * `occupied` - Cells of other entities that block movement
+/// * `rng` - Picks wandering steps
+pub fn think(
+    brain: &mut Brain,
+    at: Cell,
+    health: Option<f32>,
+    navigator: &mut Navigator,
+    occupied: &[Cell],
+    rng: &mut Rng
+) -> Option<Cell> {
+    let home = *brain.home.get_or_insert(at);
+    let sees_player = navigator
+        .player()
+        .is_some_and(|player| can_see(navigator.grid(), at, player, brain.sight));
+    let low_health = health.is_some_and(|health| health < brain.flee_below);
+
+    brain.behaviour = choose_behaviour(
+        brain.behaviour,
+        brain.wanders,
+        sees_player,
+        low_health,
+        at == home
+    );
+    navigator.next_step(brain.behaviour, at, home, occupied, rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5 by 5 grid split by a wall down column 2, open only at the bottom row
+    fn walled() -> Grid {
+        let mut grid = Grid::new(5, 5);
+        for row in 0..4 {
+            grid.set_walkable((2, row), false);
+        }
+        grid
+    }
+
+    /// Checks a path takes single steps over walkable cells from next to the start to the goal
+    fn assert_walkable_path(grid: &Grid, start: Cell, goal: Cell, path: &[Cell]) {
+        assert_eq!(path.last(), Some(&goal));
+        let mut previous = start;
+        for cell in path {
+            assert_eq!(
+                manhattan(previous, *cell),
+                1,
+                "{:?} to {:?}",
+                previous,
+                cell
+            );
+            assert!(grid.is_walkable(*cell), "{:?} is solid", cell);
+            previous = *cell;
+        }
+    }
+
+    #[test]
+    fn finds_straight_paths() {
+        let grid = Grid::new(5, 1);
+
+        let path = find_path(&grid, (0, 0), (4, 0), &[]);
+
+        assert_eq!(path, Some(vec![(1, 0), (2, 0), (3, 0), (4, 0)]));
+    }
+
+    #[test]
+    fn paths_detour_around_walls() {
+        let grid = walled();
+
+        let path = find_path(&grid, (0, 0), (4, 0), &[]).unwrap();
+
+        // Down to the gap, through it and back up
+        assert_eq!(path.len(), 12);
+        assert!(path.contains(&(2, 4)));
+        assert_walkable_path(&grid, (0, 0), (4, 0), &path);
+    }
+
+    #[test]
+    fn paths_are_repeatable() {
+        let grid = Grid::new(6, 6);
+
+        let path = find_path(&grid, (0, 0), (5, 5), &[]);
+
+        assert_eq!(path, find_path(&grid, (0, 0), (5, 5), &[]));
+        assert_eq!(path.map(|path| path.len()), Some(10));
+    }
+
+    #[test]
+    fn paths_avoid_occupied_cells_except_the_goal() {
+        let grid = Grid::new(3, 2);
+
+        let path = find_path(&grid, (0, 0), (2, 0), &[(1, 0), (2, 0)]).unwrap();
+
+        assert_eq!(path, [(0, 1), (1, 1), (2, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn no_path_to_a_walled_off_goal() {
+        let mut grid = walled();
+        grid.set_walkable((2, 4), false);
+
+        assert_eq!(find_path(&grid, (0, 0), (4, 0), &[]), None);
+        // Other entities can wall a goal off too
+        assert_eq!(find_path(&walled(), (0, 0), (4, 0), &[(2, 4)]), None);
+    }
+
+    #[test]
+    fn no_path_to_a_solid_goal() {
+        let grid = walled();
+
+        assert_eq!(find_path(&grid, (0, 0), (2, 0), &[]), None);
+        assert_eq!(find_path(&grid, (0, 0), (9, 0), &[]), None);
+        assert_eq!(find_path(&grid, (-1, 0), (1, 0), &[]), None);
+    }
+
+    #[test]
+    fn a_path_to_the_start_is_empty() {
+        let grid = Grid::new(3, 3);
+
+        assert_eq!(find_path(&grid, (1, 1), (1, 1), &[]), Some(vec![]));
+    }
+
+    #[test]
+    fn measures_distances_around_walls() {
+        let map = DijkstraMap::new(&walled(), &[(0, 0)]);
+
+        assert_eq!(map.distance((0, 0)), Some(0));
+        assert_eq!(map.distance((1, 0)), Some(1));
+        assert_eq!(map.distance((4, 0)), Some(12));
+        assert_eq!(map.distance((2, 0)), None);
+        assert_eq!(map.distance((5, 0)), None);
+    }
+
+    #[test]
+    fn measures_from_the_nearest_goal() {
+        let map = DijkstraMap::new(&Grid::new(7, 1), &[(0, 0), (6, 0)]);
+
+        let distances: Vec<Option<u32>> = (0..7).map(|column| map.distance((column, 0))).collect();
+        assert_eq!(
+            distances,
+            [
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(2),
+                Some(1),
+                Some(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn steps_downhill_towards_the_goal() {
+        let grid = walled();
+        let map = DijkstraMap::new(&grid, &[(4, 0)]);
+
+        // Following the map walks the shortest path
+        let mut cell = (0, 0);
+        let mut path = vec![];
+        while let Some(next) = map.downhill(cell, &[]) {
+            path.push(next);
+            cell = next;
+        }
+
+        assert_eq!(path.len(), 12);
+        assert_walkable_path(&grid, (0, 0), (4, 0), &path);
+        assert_eq!(map.downhill((4, 0), &[]), None);
+    }
+
+    #[test]
+    fn downhill_avoids_occupied_cells_except_the_goal() {
+        let map = DijkstraMap::new(&Grid::new(3, 1), &[(2, 0)]);
+
+        assert_eq!(map.downhill((0, 0), &[(1, 0)]), None);
+        assert_eq!(map.downhill((1, 0), &[(2, 0)]), Some((2, 0)));
+    }
+
+    #[test]
+    fn steps_uphill_away_from_the_goal() {
+        let map = DijkstraMap::new(&Grid::new(5, 1), &[(0, 0)]);
+
+        assert_eq!(map.uphill((0, 0), &[]), Some((1, 0)));
+        assert_eq!(map.uphill((2, 0), &[]), Some((3, 0)));
+        assert_eq!(map.uphill((2, 0), &[(3, 0)]), None);
+        // Cornered at the far end
+        assert_eq!(map.uphill((4, 0), &[]), None);
+    }
+
+    #[test]
+    fn no_steps_from_unreachable_cells() {
+        let mut grid = walled();
+        grid.set_walkable((2, 4), false);
+        let map = DijkstraMap::new(&grid, &[(0, 0)]);
+
+        assert_eq!(map.downhill((4, 0), &[]), None);
+        assert_eq!(map.uphill((4, 0), &[]), None);
+    }
+
+    #[test]
+    fn sees_across_open_ground() {
+        let grid = Grid::new(7, 7);
+
+        assert!(can_see(&grid, (0, 0), (6, 6), 10));
+        assert!(can_see(&grid, (6, 6), (0, 0), 10));
+        assert!(can_see(&grid, (3, 3), (3, 3), 0));
+    }
+
+    #[test]
+    fn walls_block_sight() {
+        let grid = walled();
+
+        assert!(!can_see(&grid, (0, 1), (4, 1), 10));
+        assert!(!can_see(&grid, (4, 0), (0, 3), 10));
+        // The gap lets the bottom rows see each other
+        assert!(can_see(&grid, (0, 4), (4, 4), 10));
+        // The cell looked at does not block its own sight
+        assert!(can_see(&grid, (0, 0), (2, 0), 10));
+    }
+
+    #[test]
+    fn sight_has_a_range() {
+        let grid = Grid::new(10, 10);
+
+        assert!(can_see(&grid, (0, 0), (3, 4), 5));
+        assert!(!can_see(&grid, (0, 0), (3, 4), 4));
+        // Squaring the range or distance must not overflow
+        assert!(can_see(&grid, (0, 0), (3, 4), u32::MAX));
+        assert!(!can_see(&grid, (0, 0), (100_000, 0), 99_999));
+    }
+
+    #[test]
+    fn chooses_behaviours() {
+        use Behaviour::*;
+
+        assert_eq!(choose_behaviour(Wander, true, true, false, true), Chase);
+        assert_eq!(choose_behaviour(Chase, true, true, true, false), Flee);
+        assert_eq!(
+            choose_behaviour(Chase, true, false, false, false),
+            ReturnHome
+        );
+        assert_eq!(
+            choose_behaviour(Flee, false, false, true, false),
+            ReturnHome
+        );
+        assert_eq!(
+            choose_behaviour(ReturnHome, true, false, false, true),
+            Wander
+        );
+        assert_eq!(
+            choose_behaviour(ReturnHome, false, false, false, true),
+            Idle
+        );
+        assert_eq!(choose_behaviour(Idle, false, false, false, false), Idle);
+    }
+
+    #[test]
+    fn behaviour_names_round_trip() {
+        use Behaviour::*;
+
+        for behaviour in [Idle, Wander, Chase, Flee, ReturnHome] {
+            assert_eq!(Behaviour::from_name(behaviour.name()), Some(behaviour));
+        }
+        assert_eq!(Behaviour::from_name("sleep"), None);
+    }
+
+    #[test]
+    fn brains_chase_a_visible_player_and_return_home() {
+        let mut rng = Rng::new(0);
+        let mut brain = Brain::new(6, 0.25, false, 1.0);
+        let mut navigator = Navigator::new(Grid::new(8, 1), Some((5, 0)), 1);
+
+        assert_eq!(
+            think(&mut brain, (1, 0), Some(1.0), &mut navigator, &[], &mut rng),
+            Some((2, 0))
+        );
+        assert_eq!(brain.behaviour, Behaviour::Chase);
+        assert_eq!(brain.home, Some((1, 0)));
+
+        // Hurt badly enough, it runs
+        assert_eq!(
+            think(&mut brain, (2, 0), Some(0.1), &mut navigator, &[], &mut rng),
+            Some((1, 0))
+        );
+        assert_eq!(brain.behaviour, Behaviour::Flee);
+
+        navigator.set_player(None);
+        assert_eq!(
+            think(&mut brain, (3, 0), Some(1.0), &mut navigator, &[], &mut rng),
+            Some((2, 0))
+        );
+        assert_eq!(brain.behaviour, Behaviour::ReturnHome);
+        assert_eq!(
+            think(&mut brain, (1, 0), Some(1.0), &mut navigator, &[], &mut rng),
+            None
+        );
+        assert_eq!(brain.behaviour, Behaviour::Idle);
+    }
+
+    #[test]
+    fn many_chasers_share_a_map() {
+        let mut rng = Rng::new(0);
+        let mut navigator = Navigator::new(walled(), Some((4, 0)), DIJKSTRA_CHASERS + 1);
+
+        let step = navigator
+            .next_step(Behaviour::Chase, (0, 0), (0, 0), &[], &mut rng)
+            .unwrap();
+
+        let to_player = DijkstraMap::new(&walled(), &[(4, 0)]);
+        assert_eq!(to_player.distance(step), Some(11));
+    }
+
+    #[test]
+    fn wandering_stays_near_home() {
+        let mut rng = Rng::new(11);
+        let mut navigator = Navigator::new(Grid::new(20, 20), None, 0);
+        let home = (10, 10);
+
+        let mut at = home;
+        for _ in 0..500 {
+            if let Some(next) = navigator.next_step(Behaviour::Wander, at, home, &[], &mut rng) {
+                assert_eq!(manhattan(at, next), 1);
+                at = next;
+            }
+            assert!(manhattan(at, home) <= WANDER_RADIUS);
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use std::time::Duration;
+
+/// How a clip continues once it reaches its last frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayMode {
+    /// Starts again from the first frame
+    Loop,
+    /// Holds the last frame
+    Once,
+    /// Plays backwards to the first frame, then forwards again
+    PingPong
+}
+
+impl PlayMode {
+    /// Parses a play mode from its name in a config file
+    pub fn from_name(name: &str) -> Option<PlayMode> {
+        match name {
+            "loop" => Some(PlayMode::Loop),
+            "once" => Some(PlayMode::Once),
+            "ping_pong" => Some(PlayMode::PingPong),
+            _ => None
+        }
+    }
+
+    /// The name of the play mode in a config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayMode::Loop => "loop",
+            PlayMode::Once => "once",
+            PlayMode::PingPong => "ping_pong"
+        }
+    }
+}
+
+/// Chooses the clip to switch to as an entity starts or stops moving
+/// Only idle and walk clips are switched between so actions such as attacks play out. A hurt
+/// clip returns to them once it has finished.
+/// # Arguments
+/// * `current` - The name of the clip currently playing
+/// * `moving` - If the entity has a non-zero velocity
+/// * `finished` - If the current clip has played to its end
+pub fn locomotion_clip(current: &str, moving: bool, finished: bool) -> Option<&'static str> {
+    match (current, moving) {
+        ("idle", true) => Some("walk"),
+        ("walk", false) => Some("idle"),
+        ("hurt", true) if finished => Some("walk"),
+        ("hurt", false) if finished => Some("idle"),
+        _ => None
+    }
+}
+
+/// A single frame of a clip
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    /// Index of the frame in the owner's frame list
+    pub index: usize,
+    /// How long the frame is shown for
+    pub duration: Duration
+}
+
+/// A named sequence of frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    pub name: String,
+    pub keyframes: Vec<Keyframe>,
+    pub mode: PlayMode
+}
+
+/// Where an animator is in its current clip, kept so it can be saved and restored
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    /// Index of the clip playing
+    pub clip: usize,
+    /// Index of the keyframe showing in the clip
+    pub keyframe: usize,
+    /// Time the keyframe has been showing
+    pub elapsed: Duration,
+    /// False while a ping pong clip plays backwards
+    pub forward: bool,
+    pub finished: bool
+}
+
+/// Tracks which frame of which clip is showing
+/// Only frame indices are stored here, so it can be used without a GPU.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animator {
+    clips: Vec<Clip>,
+    clip: usize,
+    keyframe: usize,
+    elapsed: Duration,
+    forward: bool,
+    finished: bool
+}
+
+impl Animator {
+    /// Creates an animator playing the named clip
+    /// # Arguments
+    /// * `clips` - The clips that can be played, each must have at least one keyframe
+    /// * `initial` - The name of the clip to start playing
+    pub fn new(clips: Vec<Clip>, initial: &str) -> Option<Animator> {
+        if clips.iter().any(|clip| clip.keyframes.is_empty()) {
+            return None;
+        }
+
+        let clip = clips.iter().position(|clip| clip.name == initial)?;
+
+        Some(Animator {
+            clips,
+            clip,
+            keyframe: 0,
+            elapsed: Duration::new(0, 0),
+            forward: true,
+            finished: false
+        })
+    }
+
+    /// Creates an animator part way through a clip
+    /// Returns None if a clip has no keyframes or the playback is outside of the clips.
+    /// # Arguments
+    /// * `clips` - The clips that can be played, each must have at least one keyframe
+    /// * `playback` - The clip and keyframe to continue from
+    pub fn resume(clips: Vec<Clip>, playback: Playback) -> Option<Animator> {
+        if clips.iter().any(|clip| clip.keyframes.is_empty()) {
+            return None;
+        }
+        if playback.keyframe >= clips.get(playback.clip)?.keyframes.len() {
+            return None;
+        }
+
+        Some(Animator {
+            clips,
+            clip: playback.clip,
+            keyframe: playback.keyframe,
+            elapsed: playback.elapsed,
+            forward: playback.forward,
+            finished: playback.finished
+        })
+    }
+
+    /// Every clip the animator can play
+    pub fn clips(&self) -> &[Clip] {
+        &self.clips
+    }
+
+    /// Where the animator is in its current clip
+    pub fn playback(&self) -> Playback {
+        Playback {
+            clip: self.clip,
+            keyframe: self.keyframe,
+            elapsed: self.elapsed,
+            forward: self.forward,
+            finished: self.finished
+        }
+    }
+
+    /// Switches to the named clip, restarting it only if it is not already playing
+    /// Returns false if there is no clip with the name.
+    /// # Arguments
+    /// * `name` - The name of the clip to play
+    pub fn play(&mut self, name: &str) -> bool {
+        if self.clip().name == name {
+            return true;
+        }
+
+        self.restart(name)
+    }
+
+    /// Plays the named clip from its first frame
+    /// Returns false if there is no clip with the name.
+    /// # Arguments
+    /// * `name` - The name of the clip to play
+    pub fn restart(&mut self, name: &str) -> bool {
+        match self.clips.iter().position(|clip| clip.name == name) {
+            Some(clip) => {
+                self.clip = clip;
+                self.keyframe = 0;
+                self.elapsed = Duration::new(0, 0);
+                self.forward = true;
+                self.finished = false;
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Returns true if a clip with the name exists
+    pub fn has_clip(&self, name: &str) -> bool {
+        self.clips.iter().any(|clip| clip.name == name)
+    }
+
+    /// The clip currently playing
+    pub fn clip(&self) -> &Clip {
+        &self.clips[self.clip]
+    }
+
+    /// Index of the frame currently showing in the owner's frame list
+    pub fn frame(&self) -> usize {
+        self.clip().keyframes[self.keyframe].index
+    }
+
+    /// True once a clip played with `PlayMode::Once` has reached its end
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Advances the current clip by a time step
+    /// Returns the number of times the clip reached its end during the step. Looping clips finish
+    /// once per cycle, clips played once finish a single time.
+    /// # Arguments
+    /// * `delta` - The time step
+    pub fn advance(&mut self, delta: Duration) -> u32 {
+        if self.finished {
+            return 0;
+        }
+
+        // Clips without any duration can not advance
+        let total: Duration = self.clip().keyframes.iter().map(|k| k.duration).sum();
+        if total == Duration::new(0, 0) {
+            return 0;
+        }
+
+        let mut completed = 0;
+        self.elapsed += delta;
+
+        while !self.finished {
+            let duration = self.clip().keyframes[self.keyframe].duration;
+            if self.elapsed < duration {
+                break;
+            }
+            self.elapsed -= duration;
+
+            if self.step() {
+                completed += 1;
+            }
+        }
+
+        completed
+    }
+
+    /// Moves to the next keyframe, returning true if the clip reached its end
+    fn step(&mut self) -> bool {
+        let last = self.clip().keyframes.len() - 1;
+
+        match self.clip().mode {
+            PlayMode::Loop => {
+                if self.keyframe == last {
+                    self.keyframe = 0;
+                    true
+                } else {
+                    self.keyframe += 1;
+                    false
+                }
+            },
+            PlayMode::Once => {
+                if self.keyframe == last {
+                    self.finished = true;
+                    self.elapsed = Duration::new(0, 0);
+                    true
+                } else {
+                    self.keyframe += 1;
+                    false
+                }
+            },
+            PlayMode::PingPong => {
+                if last == 0 {
+                    return true;
+                }
+
+                if self.forward && self.keyframe < last {
+                    self.keyframe += 1;
+                    return false;
+                }
+
+                // Turn around at the last frame and finish back at the first
+                self.forward = false;
+                self.keyframe -= 1;
+                if self.keyframe == 0 {
+                    self.forward = true;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// A clip showing frames 0 to `frames - 1` for 100ms each
+    fn clip(name: &str, frames: usize, mode: PlayMode) -> Clip {
+        Clip {
+            name: String::from(name),
+            keyframes: (0..frames)
+                .map(|index| Keyframe {
+                    index,
+                    duration: millis(100)
+                })
+                .collect(),
+            mode
+        }
+    }
+
+    /// Frames shown after each of a number of 100ms steps
+    fn frames(animator: &mut Animator, steps: usize) -> Vec<usize> {
+        (0..steps)
+            .map(|_| {
+                animator.advance(millis(100));
+                animator.frame()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_wraps_to_the_first_frame() {
+        let mut animator = Animator::new(vec![clip("idle", 3, PlayMode::Loop)], "idle").unwrap();
+
+        assert_eq!(animator.frame(), 0);
+        assert_eq!(frames(&mut animator, 5), [1, 2, 0, 1, 2]);
+        assert!(!animator.is_finished());
+    }
+
+    #[test]
+    fn once_holds_the_last_frame() {
+        let mut animator = Animator::new(vec![clip("hurt", 3, PlayMode::Once)], "hurt").unwrap();
+
+        assert_eq!(frames(&mut animator, 4), [1, 2, 2, 2]);
+        assert!(animator.is_finished());
+    }
+
+    #[test]
+    fn ping_pong_turns_around_at_each_end() {
+        let mut animator = Animator::new(vec![clip("bob", 3, PlayMode::PingPong)], "bob").unwrap();
+
+        assert_eq!(frames(&mut animator, 6), [1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn partial_steps_accumulate() {
+        let mut animator = Animator::new(vec![clip("idle", 2, PlayMode::Loop)], "idle").unwrap();
+
+        animator.advance(millis(60));
+        assert_eq!(animator.frame(), 0);
+        animator.advance(millis(60));
+        assert_eq!(animator.frame(), 1);
+    }
+
+    #[test]
+    fn reports_each_time_a_clip_finishes() {
+        let mut looping = Animator::new(vec![clip("idle", 2, PlayMode::Loop)], "idle").unwrap();
+        assert_eq!(looping.advance(millis(100)), 0);
+        assert_eq!(looping.advance(millis(100)), 1);
+        // A long step can finish several cycles
+        assert_eq!(looping.advance(millis(450)), 2);
+
+        let mut once = Animator::new(vec![clip("hurt", 2, PlayMode::Once)], "hurt").unwrap();
+        assert_eq!(once.advance(millis(1000)), 1);
+        assert_eq!(once.advance(millis(1000)), 0);
+
+        let mut ping_pong = Animator::new(vec![clip("bob", 3, PlayMode::PingPong)], "bob").unwrap();
+        assert_eq!(ping_pong.advance(millis(300)), 0);
+        assert_eq!(ping_pong.advance(millis(100)), 1);
+    }
+
+    #[test]
+    fn play_only_restarts_other_clips() {
+        let clips = vec![
+            clip("idle", 3, PlayMode::Loop),
+            clip("walk", 3, PlayMode::Loop),
+        ];
+        let mut animator = Animator::new(clips, "idle").unwrap();
+        animator.advance(millis(100));
+
+        assert!(animator.play("idle"));
+        assert_eq!(animator.frame(), 1);
+
+        assert!(animator.play("walk"));
+        assert_eq!(animator.clip().name, "walk");
+        assert_eq!(animator.frame(), 0);
+
+        assert!(!animator.play("jump"));
+        assert_eq!(animator.clip().name, "walk");
+    }
+
+    #[test]
+    fn locomotion_switches_between_idle_and_walk() {
+        assert_eq!(locomotion_clip("idle", true, false), Some("walk"));
+        assert_eq!(locomotion_clip("walk", false, false), Some("idle"));
+        assert_eq!(locomotion_clip("idle", false, false), None);
+        assert_eq!(locomotion_clip("walk", true, false), None);
+        assert_eq!(locomotion_clip("attack", true, false), None);
+    }
+
+    #[test]
+    fn locomotion_waits_for_hurt_to_finish() {
+        let clips = vec![
+            clip("idle", 2, PlayMode::Loop),
+            clip("walk", 2, PlayMode::Loop),
+            clip("hurt", 2, PlayMode::Once),
+        ];
+        let mut animator = Animator::new(clips, "idle").unwrap();
+        animator.restart("hurt");
+
+        let switch = |animator: &Animator, moving| {
+            locomotion_clip(&animator.clip().name, moving, animator.is_finished())
+        };
+
+        assert_eq!(switch(&animator, true), None);
+        animator.advance(millis(200));
+        assert_eq!(switch(&animator, true), Some("walk"));
+        assert_eq!(switch(&animator, false), Some("idle"));
+
+        animator.play("walk");
+        assert_eq!(animator.clip().name, "walk");
+        assert!(!animator.is_finished());
+    }
+
+    #[test]
+    fn rejects_empty_clips_and_unknown_initial_clips() {
+        assert!(Animator::new(vec![clip("idle", 0, PlayMode::Loop)], "idle").is_none());
+        assert!(Animator::new(vec![clip("idle", 1, PlayMode::Loop)], "walk").is_none());
+    }
+}
@@ -0,0 +1,582 @@
+extern crate gl;
+extern crate image;
+
+use crate::atlas::{Atlas, AtlasError};
+use crate::audio::{AudioError, Sound};
+use crate::error::Error;
+use crate::gl_util::{self, Filter};
+use crate::shader::Shader;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Errors that can occur while loading an asset
+#[derive(Debug)]
+pub enum AssetError {
+    /// No file exists at the path
+    NotFound(String),
+    /// An image could not be read
+    Image { path: String, message: String },
+    /// A sprite sheet descriptor could not be loaded
+    Atlas { path: String, error: AtlasError },
+    /// A shader failed to compile, or its type can not be told from its extension
+    Shader { path: String, message: String },
+    /// A sound could not be decoded
+    Sound(AudioError)
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::NotFound(path) => write!(f, "No asset found at '{}'", path),
+            AssetError::Image { path, message } => {
+                write!(f, "Image '{}' could not be read: {}", path, message)
+            },
+            AssetError::Atlas { path, error } => write!(f, "Atlas '{}': {}", path, error),
+            AssetError::Shader { path, message } => {
+                write!(f, "Shader '{}' could not be built: {}", path, message)
+            },
+            AssetError::Sound(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+/// A texture uploaded to the GPU
+#[derive(Debug, Clone, PartialEq)]
+pub struct Texture {
+    pub id: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+/// A shared reference to a loaded asset
+/// The asset stays loaded while any handle to it exists.
+pub struct Handle<T> {
+    asset: Arc<(String, T)>
+}
+
+impl<T> Handle<T> {
+    fn new(path: String, asset: T) -> Handle<T> {
+        Handle {
+            asset: Arc::new((path, asset))
+        }
+    }
+
+    /// The resolved path the asset was loaded from
+    pub fn path(&self) -> &str {
+        &self.asset.0
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle {
+            asset: Arc::clone(&self.asset)
+        }
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.asset.1
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    /// Handles are equal if they refer to the same loaded asset
+    fn eq(&self, other: &Handle<T>) -> bool {
+        Arc::ptr_eq(&self.asset, &other.asset)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Handle")
+            .field(&self.asset.0)
+            .field(&self.asset.1)
+            .finish()
+    }
+}
+
+/// Reads assets from storage and frees the GPU objects they own
+pub trait Backend: Send + Sync {
+    /// Turns a path into the key an asset is stored under, so different spellings of the same
+    /// path share one asset
+    fn resolve(&self, path: &str) -> Result<String, AssetError>;
+
+    fn load_texture(&mut self, path: &str) -> Result<Texture, AssetError>;
+
+    fn release_texture(&mut self, texture: &Texture);
+
+    fn load_atlas(&mut self, path: &str) -> Result<Atlas, AssetError>;
+
+    fn load_shader(&mut self, path: &str) -> Result<Shader, AssetError>;
+
+    fn load_sound(&mut self, path: &str) -> Result<Sound, AssetError>;
+}
+
+/// Loads assets from disk and uploads them with OpenGL
+/// Must only be used on the thread owning the OpenGL context.
+#[derive(Default)]
+pub struct GlBackend {
+    /// Uploaded textures by id, deleted when released
+    textures: HashMap<u32, gl_util::Texture>
+}
+
+impl GlBackend {
+    pub fn new() -> GlBackend {
+        GlBackend::default()
+    }
+}
+
+impl Backend for GlBackend {
+    fn resolve(&self, path: &str) -> Result<String, AssetError> {
+        fs::canonicalize(path)
+            .map(|path| path.to_string_lossy().into_owned())
+            .map_err(|_| AssetError::NotFound(String::from(path)))
+    }
+
+    fn load_texture(&mut self, path: &str) -> Result<Texture, AssetError> {
+        let image = match image::open(path) {
+            Ok(image) => image.flipv().into_rgba(),
+            Err(message) => {
+                return Err(AssetError::Image {
+                    path: String::from(path),
+                    message: message.to_string()
+                })
+            },
+        };
+
+        let uploaded = gl_util::Texture::from_rgba(
+            image.width(),
+            image.height(),
+            &image,
+            Filter::Linear,
+            Filter::Nearest
+        );
+        let texture = Texture {
+            id: uploaded.id(),
+            width: uploaded.width(),
+            height: uploaded.height()
+        };
+
+        self.textures.insert(texture.id, uploaded);
+        Ok(texture)
+    }
+
+    fn release_texture(&mut self, texture: &Texture) {
+        // Dropping the texture deletes it from the GPU
+        self.textures.remove(&texture.id);
+    }
+
+    fn load_atlas(&mut self, path: &str) -> Result<Atlas, AssetError> {
+        Atlas::load(path).map_err(|error| AssetError::Atlas {
+            path: String::from(path),
+            error
+        })
+    }
+
+    fn load_shader(&mut self, path: &str) -> Result<Shader, AssetError> {
+        let kind = shader_kind(path)?;
+        Shader::new_from_file(path, kind).map_err(|error| AssetError::Shader {
+            path: String::from(path),
+            message: match error {
+                Error::ShaderCompile { log, .. } => log,
+                error => error.to_string()
+            }
+        })
+    }
+
+    fn load_sound(&mut self, path: &str) -> Result<Sound, AssetError> {
+        Sound::load(path).map_err(AssetError::Sound)
+    }
+}
+
+/// Tells the type of a shader from its file extension
+pub fn shader_kind(path: &str) -> Result<gl::types::GLenum, AssetError> {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("vert") => Ok(gl::VERTEX_SHADER),
+        Some("frag") => Ok(gl::FRAGMENT_SHADER),
+        Some("geom") => Ok(gl::GEOMETRY_SHADER),
+        Some("comp") => Ok(gl::COMPUTE_SHADER),
+        _ => Err(AssetError::Shader {
+            path: String::from(path),
+            message: String::from("expected a .vert, .frag, .geom or .comp extension")
+        })
+    }
+}
+
+/// Removes `.` and resolves `..` components of a path without touching the file system
+pub fn normalize(path: &str) -> String {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {},
+            // Only step back over a named directory, `..` itself can not be undone
+            Component::ParentDir => {
+                if normalized.file_name().is_some() {
+                    normalized.pop();
+                } else {
+                    normalized.push("..");
+                }
+            },
+            component => normalized.push(component)
+        }
+    }
+    normalized.to_string_lossy().into_owned()
+}
+
+/// Loaded assets of one type by resolved path
+struct Cache<T> {
+    assets: HashMap<String, Handle<T>>
+}
+
+impl<T> Cache<T> {
+    fn new() -> Cache<T> {
+        Cache {
+            assets: HashMap::new()
+        }
+    }
+
+    /// Returns the loaded asset for a resolved path, loading it if needed
+    fn get_or_load<F>(&mut self, path: String, load: F) -> Result<Handle<T>, AssetError>
+    where
+        F: FnOnce(&str) -> Result<T, AssetError>
+    {
+        if let Some(handle) = self.assets.get(&path) {
+            return Ok(handle.clone());
+        }
+
+        let handle = Handle::new(path.clone(), load(&path)?);
+        self.assets.insert(path, handle.clone());
+        Ok(handle)
+    }
+
+    /// Removes the assets only the cache still refers to
+    fn collect(&mut self) -> Vec<Handle<T>> {
+        let unused: Vec<String> = self
+            .assets
+            .iter()
+            .filter(|(_, handle)| Arc::strong_count(&handle.asset) == 1)
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        unused
+            .iter()
+            .filter_map(|path| self.assets.remove(path))
+            .collect()
+    }
+}
+
+/// Loads each asset once and shares it through handles
+/// Assets are freed by `collect` once no handles to them remain. `collect` must run on the
+/// thread owning the OpenGL context since it deletes GPU objects.
+pub struct AssetManager {
+    backend: Box<dyn Backend>,
+    textures: Cache<Texture>,
+    atlases: Cache<Atlas>,
+    shaders: Cache<Shader>,
+    sounds: Cache<Sound>
+}
+
+impl AssetManager {
+    /// Creates an asset manager
+    /// # Arguments
+    /// * `backend` - Where assets are loaded from
+    pub fn new(backend: Box<dyn Backend>) -> AssetManager {
+        AssetManager {
+            backend,
+            textures: Cache::new(),
+            atlases: Cache::new(),
+            shaders: Cache::new(),
+            sounds: Cache::new()
+        }
+    }
+
+    /// Loads an image into a texture
+    pub fn load_texture(&mut self, path: &str) -> Result<Handle<Texture>, AssetError> {
+        let path = self.backend.resolve(path)?;
+        let backend = &mut self.backend;
+        self.textures
+            .get_or_load(path, |path| backend.load_texture(path))
+    }
+
+    /// Loads a sprite sheet descriptor
+    /// The sheet's image is not uploaded, load it with `load_texture`.
+    pub fn load_atlas(&mut self, path: &str) -> Result<Handle<Atlas>, AssetError> {
+        let path = self.backend.resolve(path)?;
+        let backend = &mut self.backend;
+        self.atlases
+            .get_or_load(path, |path| backend.load_atlas(path))
+    }
+
+    /// Compiles a shader, its type is told from its extension
+    pub fn load_shader(&mut self, path: &str) -> Result<Handle<Shader>, AssetError> {
+        let path = self.backend.resolve(path)?;
+        let backend = &mut self.backend;
+        self.shaders
+            .get_or_load(path, |path| backend.load_shader(path))
+    }
+
+    /// Decodes a sound
+    pub fn load_sound(&mut self, path: &str) -> Result<Handle<Sound>, AssetError> {
+        let path = self.backend.resolve(path)?;
+        let backend = &mut self.backend;
+        self.sounds
+            .get_or_load(path, |path| backend.load_sound(path))
+    }
+
+    /// Number of assets currently loaded
+    pub fn loaded(&self) -> usize {
+        self.textures.assets.len()
+            + self.atlases.assets.len()
+            + self.shaders.assets.len()
+            + self.sounds.assets.len()
+    }
+
+    /// Frees every asset no handle refers to any more and returns how many were freed
+    pub fn collect(&mut self) -> usize {
+        let textures = self.textures.collect();
+        for texture in &textures {
+            self.backend.release_texture(texture);
+        }
+
+        // Shaders delete themselves when dropped
+        textures.len()
+            + self.atlases.collect().len()
+            + self.shaders.collect().len()
+            + self.sounds.collect().len()
+    }
+}
+
+/// In memory assets for tests
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Calls made to a `MockBackend`, shared so they can be checked after the backend is boxed
+    #[derive(Debug, Default)]
+    pub struct MockLog {
+        /// Resolved paths in the order they were loaded
+        pub loads: Vec<String>,
+        /// Ids of released textures
+        pub released: Vec<u32>
+    }
+
+    /// Serves assets from memory without a GPU or file system
+    /// Textures get increasing ids, sounds are silent and shaders can not be built.
+    #[derive(Default)]
+    pub struct MockBackend {
+        /// File contents by normalized path
+        pub files: HashMap<String, String>,
+        /// Image dimensions by normalized path
+        pub images: HashMap<String, (u32, u32)>,
+        pub log: Arc<Mutex<MockLog>>,
+        next_id: u32
+    }
+
+    impl MockBackend {
+        pub fn new() -> MockBackend {
+            MockBackend::default()
+        }
+
+        /// Adds a text file, such as an atlas descriptor
+        pub fn add_file(&mut self, path: &str, contents: &str) {
+            self.files.insert(normalize(path), String::from(contents));
+        }
+
+        /// Adds an image of the given size
+        pub fn add_image(&mut self, path: &str, width: u32, height: u32) {
+            self.images.insert(normalize(path), (width, height));
+        }
+
+        fn record(&self, path: &str) {
+            self.log.lock().unwrap().loads.push(String::from(path));
+        }
+    }
+
+    impl Backend for MockBackend {
+        fn resolve(&self, path: &str) -> Result<String, AssetError> {
+            let path = normalize(path);
+            if self.files.contains_key(&path) || self.images.contains_key(&path) {
+                Ok(path)
+            } else {
+                Err(AssetError::NotFound(path))
+            }
+        }
+
+        fn load_texture(&mut self, path: &str) -> Result<Texture, AssetError> {
+            let (width, height) = *self.images.get(path).ok_or_else(|| AssetError::Image {
+                path: String::from(path),
+                message: String::from("not an image")
+            })?;
+
+            self.record(path);
+            self.next_id += 1;
+            Ok(Texture {
+                id: self.next_id,
+                width,
+                height
+            })
+        }
+
+        fn release_texture(&mut self, texture: &Texture) {
+            self.log.lock().unwrap().released.push(texture.id);
+        }
+
+        fn load_atlas(&mut self, path: &str) -> Result<Atlas, AssetError> {
+            let source = self.files.get(path).ok_or_else(|| AssetError::Atlas {
+                path: String::from(path),
+                error: AtlasError::Io(String::from("not a text file"))
+            })?;
+
+            let images = &self.images;
+            let atlas = Atlas::parse(source, path, |image| {
+                images
+                    .get(&normalize(image))
+                    .copied()
+                    .ok_or_else(|| String::from("not an image"))
+            })
+            .map_err(|error| AssetError::Atlas {
+                path: String::from(path),
+                error
+            })?;
+
+            self.record(path);
+            Ok(atlas)
+        }
+
+        fn load_shader(&mut self, path: &str) -> Result<Shader, AssetError> {
+            Err(AssetError::Shader {
+                path: String::from(path),
+                message: String::from("shaders need an OpenGL context")
+            })
+        }
+
+        fn load_sound(&mut self, path: &str) -> Result<Sound, AssetError> {
+            self.record(path);
+            Ok(Sound::from_samples(vec![]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{MockBackend, MockLog};
+    use super::*;
+    use std::sync::Mutex;
+
+    fn manager() -> (AssetManager, Arc<Mutex<MockLog>>) {
+        let mut backend = MockBackend::new();
+        backend.add_image("x.png", 32, 16);
+        backend.add_image("sprites/sheet.png", 64, 64);
+        backend.add_file(
+            "sprites/sheet.toml",
+            "image = \"sheet.png\"\n\n[[frame]]\nname = \"idle\"\nx = 0\ny = 0\nwidth = 16\nheight = 16\n"
+        );
+        let log = Arc::clone(&backend.log);
+        (AssetManager::new(Box::new(backend)), log)
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(normalize("./a/../x.png"), "x.png");
+        assert_eq!(normalize("a/./b/../c.png"), "a/c.png");
+        assert_eq!(normalize("../x.png"), "../x.png");
+        assert_eq!(normalize("a/../../x.png"), "../x.png");
+    }
+
+    #[test]
+    fn spellings_of_a_path_share_one_asset() {
+        let (mut assets, log) = manager();
+
+        let first = assets.load_texture("./a/../x.png").unwrap();
+        let second = assets.load_texture("x.png").unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(first.path(), "x.png");
+        assert_eq!((first.width, first.height), (32, 16));
+        assert_eq!(assets.loaded(), 1);
+        assert_eq!(log.lock().unwrap().loads, ["x.png"]);
+    }
+
+    #[test]
+    fn collect_waits_for_the_last_handle() {
+        let (mut assets, log) = manager();
+
+        let first = assets.load_texture("x.png").unwrap();
+        let second = first.clone();
+        let id = first.id;
+
+        drop(first);
+        assert_eq!(assets.collect(), 0);
+        assert!(log.lock().unwrap().released.is_empty());
+
+        drop(second);
+        assert_eq!(assets.collect(), 1);
+        assert_eq!(log.lock().unwrap().released, [id]);
+        assert_eq!(assets.loaded(), 0);
+
+        // Loading it again after it was freed reloads it
+        let reloaded = assets.load_texture("x.png").unwrap();
+        assert_ne!(reloaded.id, id);
+        assert_eq!(log.lock().unwrap().loads, ["x.png", "x.png"]);
+    }
+
+    #[test]
+    fn collect_keeps_assets_in_use() {
+        let (mut assets, _) = manager();
+
+        let _texture = assets.load_texture("x.png").unwrap();
+        drop(assets.load_atlas("sprites/sheet.toml").unwrap());
+
+        assert_eq!(assets.collect(), 1);
+        assert_eq!(assets.loaded(), 1);
+    }
+
+    #[test]
+    fn loads_atlases() {
+        let (mut assets, _) = manager();
+
+        let atlas = assets.load_atlas("sprites/../sprites/sheet.toml").unwrap();
+
+        assert_eq!(atlas.path(), "sprites/sheet.toml");
+        assert!(atlas.frame("idle").is_ok());
+    }
+
+    #[test]
+    fn missing_paths_are_not_found() {
+        let (mut assets, log) = manager();
+
+        match assets.load_texture("./missing.png") {
+            Err(AssetError::NotFound(path)) => assert_eq!(path, "missing.png"),
+            other => panic!("expected NotFound, got {:?}", other)
+        }
+        assert_eq!(assets.loaded(), 0);
+        assert!(log.lock().unwrap().loads.is_empty());
+    }
+
+    #[test]
+    fn wrong_kinds_of_file_fail_to_load() {
+        let (mut assets, _) = manager();
+
+        assert!(matches!(
+            assets.load_texture("sprites/sheet.toml"),
+            Err(AssetError::Image { .. })
+        ));
+        assert!(matches!(
+            assets.load_atlas("x.png"),
+            Err(AssetError::Atlas { .. })
+        ));
+        assert_eq!(assets.loaded(), 0);
+    }
+}
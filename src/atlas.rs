@@ -0,0 +1,370 @@
+extern crate image;
+extern crate toml;
+
+use crate::gl_util;
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Errors that can occur while loading a sprite sheet descriptor
+#[derive(Debug)]
+pub enum AtlasError {
+    /// The descriptor file could not be read
+    Io(String),
+    /// The descriptor is not valid TOML
+    Parse(String),
+    /// A required field is missing
+    MissingField(String),
+    /// A field is present but has the wrong type or value
+    InvalidField {
+        field: String,
+        expected: &'static str
+    },
+    /// The sprite sheet image could not be read
+    Image { path: String, message: String },
+    /// A frame rectangle lies outside of the image
+    OutOfBounds(String),
+    /// No frame exists with the requested name
+    UnknownFrame(String)
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasError::Io(message) => write!(f, "Failed to read atlas: {}", message),
+            AtlasError::Parse(message) => write!(f, "Failed to parse atlas: {}", message),
+            AtlasError::MissingField(field) => write!(f, "Atlas is missing field '{}'", field),
+            AtlasError::InvalidField { field, expected } => {
+                write!(
+                    f,
+                    "Atlas has invalid field '{}', expected {}",
+                    field, expected
+                )
+            },
+            AtlasError::Image { path, message } => {
+                write!(f, "Sprite sheet '{}' could not be read: {}", path, message)
+            },
+            AtlasError::OutOfBounds(frame) => {
+                write!(f, "Frame '{}' lies outside of the sprite sheet", frame)
+            },
+            AtlasError::UnknownFrame(frame) => write!(f, "No frame named '{}'", frame)
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+/// A single rectangle on a sprite sheet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: String,
+    /// Pixel offset of the left edge from the left of the image
+    pub x: u32,
+    /// Pixel offset of the top edge from the top of the image
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Texture coordinates for the two triangles of a quad
+    pub uv: Vec<f32>
+}
+
+impl Frame {
+    /// Returns the quad vertices for this frame normalized to (-1, 1)
+    pub fn vertices(&self) -> Vec<f32> {
+        gl_util::create_rectangle(self.width as f32, self.height as f32)
+    }
+}
+
+/// A sprite sheet image and the frames it contains
+#[derive(Debug, Clone, PartialEq)]
+pub struct Atlas {
+    /// Path of the sprite sheet image
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<Frame>
+}
+
+impl Atlas {
+    /// Loads a sprite sheet descriptor and reads the dimensions of its image
+    /// # Arguments
+    /// * `path` - The path of the descriptor file
+    pub fn load(path: &str) -> Result<Atlas, AtlasError> {
+        let source = fs::read_to_string(path).map_err(|e| AtlasError::Io(e.to_string()))?;
+        Atlas::parse(&source, path, |image| {
+            image::image_dimensions(image).map_err(|e| e.to_string())
+        })
+    }
+
+    /// Parses a sprite sheet descriptor
+    /// # Arguments
+    /// * `source` - The contents of the descriptor file
+    /// * `path` - The path of the descriptor file, the image is located relative to it
+    /// * `image_dimensions` - Returns the width and height of an image, or why it can't be read
+    pub fn parse<F>(source: &str, path: &str, image_dimensions: F) -> Result<Atlas, AtlasError>
+    where
+        F: FnOnce(&str) -> Result<(u32, u32), String>
+    {
+        let root = source
+            .parse::<toml::Value>()
+            .map_err(|e| AtlasError::Parse(e.to_string()))?;
+
+        // The image is located relative to the descriptor
+        let image = match root.get("image") {
+            Some(toml::Value::String(image)) => image,
+            Some(_) => return Err(invalid("image", "a string")),
+            None => return Err(AtlasError::MissingField(String::from("image")))
+        };
+        let image = Path::new(path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(image)
+            .to_string_lossy()
+            .into_owned();
+
+        let (width, height) = image_dimensions(&image).map_err(|message| AtlasError::Image {
+            path: image.clone(),
+            message
+        })?;
+
+        Atlas::from_descriptor(&root, image, width, height)
+    }
+
+    /// Builds an atlas from a parsed descriptor and known image dimensions
+    /// # Arguments
+    /// * `descriptor` - The parsed descriptor
+    /// * `image` - Path of the sprite sheet image
+    /// * `width` - Width of the image in pixels
+    /// * `height` - Height of the image in pixels
+    pub fn from_descriptor(
+        descriptor: &toml::Value,
+        image: String,
+        width: u32,
+        height: u32
+    ) -> Result<Atlas, AtlasError> {
+        let mut frames = Vec::new();
+
+        // Frames laid out on a regular grid, named by their index
+        if let Some(grid) = descriptor.get("grid") {
+            let columns = integer(grid, "grid.columns", "columns", Some(1))?;
+            let rows = integer(grid, "grid.rows", "rows", Some(1))?;
+            if columns == 0 || rows == 0 {
+                return Err(invalid("grid", "at least one column and row"));
+            }
+            let frame_width = integer(grid, "grid.width", "width", Some(width / columns))?;
+            let frame_height = integer(grid, "grid.height", "height", Some(height / rows))?;
+
+            if frame_width == 0 || frame_height == 0 {
+                return Err(invalid("grid", "a non-empty grid"));
+            }
+
+            // Checked before making any frames so a huge grid can not overflow or exhaust memory
+            let fits = |count: u32, size: u32, limit: u32| {
+                count
+                    .checked_mul(size)
+                    .is_some_and(|extent| extent <= limit)
+            };
+            if !fits(columns, frame_width, width) || !fits(rows, frame_height, height) {
+                return Err(AtlasError::OutOfBounds(String::from("grid")));
+            }
+
+            for row in 0..rows {
+                for column in 0..columns {
+                    frames.push((
+                        (row * columns + column).to_string(),
+                        column * frame_width,
+                        row * frame_height,
+                        frame_width,
+                        frame_height
+                    ));
+                }
+            }
+        }
+
+        // Explicitly named frame rectangles
+        match descriptor.get("frame") {
+            Some(toml::Value::Array(rectangles)) => {
+                for rectangle in rectangles {
+                    let name = match rectangle.get("name") {
+                        Some(toml::Value::String(name)) => name.clone(),
+                        Some(_) => return Err(invalid("frame.name", "a string")),
+                        None => return Err(AtlasError::MissingField(String::from("frame.name")))
+                    };
+
+                    frames.push((
+                        name,
+                        integer(rectangle, "frame.x", "x", None)?,
+                        integer(rectangle, "frame.y", "y", None)?,
+                        integer(rectangle, "frame.width", "width", None)?,
+                        integer(rectangle, "frame.height", "height", None)?
+                    ));
+                }
+            },
+            Some(_) => return Err(invalid("frame", "an array of tables")),
+            None => {}
+        }
+
+        if frames.is_empty() {
+            return Err(AtlasError::MissingField(String::from("grid")));
+        }
+
+        let frames = frames
+            .into_iter()
+            .map(|(name, x, y, frame_width, frame_height)| {
+                let right = x.checked_add(frame_width);
+                let bottom = y.checked_add(frame_height);
+                if right.is_none_or(|right| right > width)
+                    || bottom.is_none_or(|bottom| bottom > height)
+                {
+                    return Err(AtlasError::OutOfBounds(name));
+                }
+
+                Ok(Frame {
+                    uv: quad_coordinates(x, y, frame_width, frame_height, width, height),
+                    name,
+                    x,
+                    y,
+                    width: frame_width,
+                    height: frame_height
+                })
+            })
+            .collect::<Result<Vec<Frame>, AtlasError>>()?;
+
+        Ok(Atlas {
+            image,
+            width,
+            height,
+            frames
+        })
+    }
+
+    /// Finds a frame by name
+    /// # Arguments
+    /// * `name` - The name of the frame
+    pub fn frame(&self, name: &str) -> Result<&Frame, AtlasError> {
+        self.frames
+            .iter()
+            .find(|frame| frame.name == name)
+            .ok_or_else(|| AtlasError::UnknownFrame(String::from(name)))
+    }
+
+    /// Finds several frames by name, preserving their order
+    /// # Arguments
+    /// * `names` - The names of the frames
+    pub fn select(&self, names: &[String]) -> Result<Vec<Frame>, AtlasError> {
+        names.iter().map(|name| self.frame(name).cloned()).collect()
+    }
+}
+
+/// Computes texture coordinates of a pixel rectangle for the two triangles of a quad
+/// Images are flipped when uploaded so the v axis points up.
+/// # Arguments
+/// * `x`, `y` - Pixel offset of the top left corner of the rectangle
+/// * `width`, `height` - Size of the rectangle in pixels
+/// * `image_width`, `image_height` - Size of the whole image in pixels
+pub fn quad_coordinates(
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    image_width: u32,
+    image_height: u32
+) -> Vec<f32> {
+    let left = x as f32 / image_width as f32;
+    let right = (x + width) as f32 / image_width as f32;
+    let bottom = 1.0 - (y + height) as f32 / image_height as f32;
+    let top = 1.0 - y as f32 / image_height as f32;
+
+    vec![
+        left, bottom, right, top, left, top, left, bottom, right, bottom, right, top,
+    ]
+}
+
+fn invalid(field: &str, expected: &'static str) -> AtlasError {
+    AtlasError::InvalidField {
+        field: String::from(field),
+        expected
+    }
+}
+
+/// Reads a non-negative integer from a table, falling back to a default if it is absent
+fn integer(
+    table: &toml::Value,
+    path: &str,
+    field: &str,
+    default: Option<u32>
+) -> Result<u32, AtlasError> {
+    match (table.get(field), default) {
+        (Some(toml::Value::Integer(value)), _) => {
+            u32::try_from(*value).map_err(|_| invalid(path, "a 32 bit non-negative integer"))
+        },
+        (Some(_), _) => Err(invalid(path, "a 32 bit non-negative integer")),
+        (None, Some(default)) => Ok(default),
+        (None, None) => Err(AtlasError::MissingField(String::from(path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Atlas, AtlasError> {
+        let descriptor = source.parse::<toml::Value>().unwrap();
+        Atlas::from_descriptor(&descriptor, String::from("sheet.png"), 64, 32)
+    }
+
+    #[test]
+    fn grid_frames_are_named_by_index() {
+        let atlas = parse("[grid]\ncolumns = 4\nrows = 2").unwrap();
+
+        assert_eq!(atlas.frames.len(), 8);
+        let frame = atlas.frame("5").unwrap();
+        assert_eq!(
+            (frame.x, frame.y, frame.width, frame.height),
+            (16, 16, 16, 16)
+        );
+    }
+
+    #[test]
+    fn rejects_frames_outside_the_image() {
+        let source = "[[frame]]\nname = \"wide\"\nx = 60\ny = 0\nwidth = 8\nheight = 8";
+        assert!(matches!(parse(source), Err(AtlasError::OutOfBounds(name)) if name == "wide"));
+    }
+
+    #[test]
+    fn rejects_frames_that_overflow() {
+        let source = format!(
+            "[[frame]]\nname = \"huge\"\nx = {}\ny = 0\nwidth = 2\nheight = 8",
+            u32::MAX
+        );
+        assert!(matches!(parse(&source), Err(AtlasError::OutOfBounds(name)) if name == "huge"));
+
+        let source = format!("[grid]\ncolumns = {}\nwidth = 2", u32::MAX);
+        assert!(matches!(parse(&source), Err(AtlasError::OutOfBounds(_))));
+    }
+
+    #[test]
+    fn rejects_integers_too_large_for_u32() {
+        let source = format!(
+            "[[frame]]\nname = \"big\"\nx = {}\ny = 0\nwidth = 8\nheight = 8",
+            u32::MAX as i64 + 1
+        );
+        assert!(matches!(
+            parse(&source),
+            Err(AtlasError::InvalidField { field, .. }) if field == "frame.x"
+        ));
+    }
+
+    #[test]
+    fn rejects_grids_without_columns_or_rows() {
+        for source in ["[grid]\ncolumns = 0", "[grid]\nrows = 0"] {
+            assert!(
+                matches!(parse(source), Err(AtlasError::InvalidField { field, .. }) if field == "grid"),
+                "{} was accepted",
+                source
+            );
+        }
+    }
+}
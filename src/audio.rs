@@ -0,0 +1,784 @@
+extern crate lewton;
+extern crate sdl2;
+extern crate toml;
+
+use sdl2::audio::{
+    AudioCVT, AudioCallback, AudioDevice, AudioFormat, AudioSpecDesired, AudioSpecWAV
+};
+use sdl2::AudioSubsystem;
+
+use crate::assets::{AssetError, AssetManager, Handle};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Samples per second played by the output device
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Sounds are mixed and stored as interleaved stereo
+const CHANNELS: usize = 2;
+
+/// Sound effects playing at once, the oldest is cut off to make room
+const MAX_VOICES: usize = 16;
+
+/// Errors that can occur while loading sounds or opening the audio device
+#[derive(Debug)]
+pub enum AudioError {
+    /// A file could not be read
+    Io { path: String, message: String },
+    /// The audio config is not valid TOML
+    Parse(String),
+    /// A field of the audio config has the wrong type
+    InvalidField {
+        field: String,
+        expected: &'static str
+    },
+    /// A sound file could not be decoded
+    Decode { path: String, message: String },
+    /// The sound file has an extension other than `wav` or `ogg`
+    UnsupportedFormat(String),
+    /// The output device could not be opened
+    Device(String)
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioError::Io { path, message } => write!(f, "Failed to read '{}': {}", path, message),
+            AudioError::Parse(message) => write!(f, "Failed to parse audio config: {}", message),
+            AudioError::InvalidField { field, expected } => {
+                write!(f, "Field '{}' should be {}", field, expected)
+            },
+            AudioError::Decode { path, message } => {
+                write!(f, "Failed to decode '{}': {}", path, message)
+            },
+            AudioError::UnsupportedFormat(path) => {
+                write!(f, "'{}' is not a wav or ogg file", path)
+            },
+            AudioError::Device(message) => write!(f, "Failed to open audio device: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+/// A group of sounds sharing a volume
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    /// Scales every other channel
+    Master,
+    Music,
+    Effects
+}
+
+impl Channel {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Something that should make a noise, pushed into the `SoundQueue` by systems
+#[derive(Debug, Clone, PartialEq)]
+pub enum SoundEvent {
+    Footstep,
+    Hit,
+    /// Crossfade to a named music track
+    Music(String),
+    /// Fade out the music
+    StopMusic
+}
+
+impl SoundEvent {
+    /// The name of the sound an effect plays, or `None` for music events
+    pub fn effect_name(&self) -> Option<&'static str> {
+        match self {
+            SoundEvent::Footstep => Some("footstep"),
+            SoundEvent::Hit => Some("hit"),
+            SoundEvent::Music(_) | SoundEvent::StopMusic => None
+        }
+    }
+}
+
+/// Sounds waiting to be played this frame
+#[derive(Debug, Default)]
+pub struct SoundQueue {
+    events: Vec<SoundEvent>
+}
+
+impl SoundQueue {
+    pub fn push(&mut self, event: SoundEvent) {
+        self.events.push(event);
+    }
+
+    /// Removes and returns every queued event
+    pub fn drain(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// Decoded samples, interleaved stereo at `SAMPLE_RATE`
+/// Cloning is cheap, the samples are shared.
+#[derive(Debug, Clone)]
+pub struct Sound {
+    samples: Arc<Vec<f32>>
+}
+
+impl Sound {
+    /// Creates a sound from interleaved stereo samples at `SAMPLE_RATE`
+    pub fn from_samples(samples: Vec<f32>) -> Sound {
+        Sound {
+            samples: Arc::new(samples)
+        }
+    }
+
+    /// Loads a wav or ogg file, converting it to the output format
+    /// # Arguments
+    /// * `path` - The path of the sound file
+    pub fn load(path: &str) -> Result<Sound, AudioError> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        match extension.as_deref() {
+            Some("wav") => Sound::load_wav(path),
+            Some("ogg") => Sound::load_ogg(path),
+            _ => Err(AudioError::UnsupportedFormat(String::from(path)))
+        }
+    }
+
+    /// Number of stereo frames in the sound
+    pub fn frames(&self) -> usize {
+        self.samples.len() / CHANNELS
+    }
+
+    fn load_wav(path: &str) -> Result<Sound, AudioError> {
+        let decode_error = |message: String| AudioError::Decode {
+            path: String::from(path),
+            message
+        };
+
+        let wav = AudioSpecWAV::load_wav(path).map_err(decode_error)?;
+
+        // Let SDL convert the format, channels and rate in one go
+        let converter = AudioCVT::new(
+            wav.format,
+            wav.channels,
+            wav.freq,
+            AudioFormat::F32LSB,
+            CHANNELS as u8,
+            SAMPLE_RATE as i32
+        )
+        .map_err(decode_error)?;
+        let bytes = converter.convert(wav.buffer().to_vec());
+
+        let samples = bytes
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+
+        Ok(Sound::from_samples(samples))
+    }
+
+    fn load_ogg(path: &str) -> Result<Sound, AudioError> {
+        let decode_error = |message: String| AudioError::Decode {
+            path: String::from(path),
+            message
+        };
+
+        let file = fs::File::open(path).map_err(|e| AudioError::Io {
+            path: String::from(path),
+            message: e.to_string()
+        })?;
+        let mut reader = lewton::inside_ogg::OggStreamReader::new(file)
+            .map_err(|e| decode_error(e.to_string()))?;
+
+        let channels = reader.ident_hdr.audio_channels as usize;
+        let rate = reader.ident_hdr.audio_sample_rate;
+
+        let mut samples = Vec::new();
+        while let Some(packet) = reader
+            .read_dec_packet_itl()
+            .map_err(|e| decode_error(e.to_string()))?
+        {
+            samples.extend(packet.iter().map(|sample| *sample as f32 / 32768.0));
+        }
+
+        let stereo = to_stereo(&samples, channels);
+        Ok(Sound::from_samples(resample(&stereo, rate, SAMPLE_RATE)))
+    }
+}
+
+/// Converts interleaved samples with any number of channels to stereo
+/// Mono is copied to both sides, extra channels past the first two are dropped.
+/// # Arguments
+/// * `samples` - Interleaved samples
+/// * `channels` - Number of channels in the samples
+pub fn to_stereo(samples: &[f32], channels: usize) -> Vec<f32> {
+    match channels {
+        0 => vec![],
+        1 => samples
+            .iter()
+            .flat_map(|sample| vec![*sample, *sample])
+            .collect(),
+        _ => samples
+            .chunks_exact(channels)
+            .flat_map(|frame| vec![frame[0], frame[1]])
+            .collect()
+    }
+}
+
+/// Changes the rate of interleaved stereo samples by linear interpolation
+/// # Arguments
+/// * `samples` - Interleaved stereo samples
+/// * `from` - Rate of the samples
+/// * `to` - Rate to convert to
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    let frames = samples.len() / CHANNELS;
+    if from == to || frames == 0 || from == 0 {
+        return samples.to_vec();
+    }
+
+    let length = (frames as u64 * to as u64 / from as u64) as usize;
+    let mut resampled = Vec::with_capacity(length * CHANNELS);
+    for frame in 0..length {
+        let position = frame as f64 * from as f64 / to as f64;
+        let index = position as usize;
+        let next = (index + 1).min(frames - 1);
+        let t = (position - index as f64) as f32;
+
+        for channel in 0..CHANNELS {
+            let a = samples[index * CHANNELS + channel];
+            let b = samples[next * CHANNELS + channel];
+            resampled.push(a + (b - a) * t);
+        }
+    }
+
+    resampled
+}
+
+/// A sound effect being played
+#[derive(Debug)]
+struct Voice {
+    sound: Sound,
+    frame: usize,
+    volume: f32
+}
+
+/// A looping music track fading between gains
+#[derive(Debug)]
+struct Track {
+    sound: Sound,
+    frame: usize,
+    gain: f32,
+    /// Gain when the fade started
+    start: f32,
+    target: f32,
+    /// Length of the fade and how far through it the track is, in frames
+    fade: usize,
+    faded: usize
+}
+
+impl Track {
+    fn new(sound: &Sound) -> Track {
+        Track {
+            sound: sound.clone(),
+            frame: 0,
+            gain: 0.0,
+            start: 0.0,
+            target: 0.0,
+            fade: 0,
+            faded: 0
+        }
+    }
+
+    fn fade_to(&mut self, target: f32, seconds: f32) {
+        self.start = self.gain;
+        self.target = target;
+        self.fade = (seconds.max(0.0) * SAMPLE_RATE as f32) as usize;
+        self.faded = 0;
+
+        if self.fade == 0 {
+            self.gain = target;
+        }
+    }
+
+    /// Returns the next stereo frame and moves the fade along
+    fn next(&mut self) -> (f32, f32) {
+        let frames = self.sound.frames();
+        if frames == 0 {
+            return (0.0, 0.0);
+        }
+
+        let left = self.sound.samples[self.frame * CHANNELS] * self.gain;
+        let right = self.sound.samples[self.frame * CHANNELS + 1] * self.gain;
+        self.frame = (self.frame + 1) % frames;
+
+        // Computed from the progress rather than summed, so the fade lands exactly on target
+        if self.faded < self.fade {
+            self.faded += 1;
+            let t = self.faded as f32 / self.fade as f32;
+            self.gain = self.start + (self.target - self.start) * t;
+        }
+
+        (left, right)
+    }
+}
+
+/// Mixes sound effects and music into an output buffer
+/// The mixer does not touch the audio device, so it can be driven directly in tests.
+#[derive(Debug)]
+pub struct Mixer {
+    voices: Vec<Voice>,
+    music: Option<Track>,
+    /// Tracks fading out after being replaced
+    fading: Vec<Track>,
+    volumes: [f32; 3]
+}
+
+impl Default for Mixer {
+    fn default() -> Mixer {
+        Mixer {
+            voices: vec![],
+            music: None,
+            fading: vec![],
+            volumes: [1.0; 3]
+        }
+    }
+}
+
+impl Mixer {
+    pub fn new() -> Mixer {
+        Mixer::default()
+    }
+
+    /// Starts a sound effect
+    /// # Arguments
+    /// * `sound` - The sound to play
+    /// * `volume` - Volume of this sound, before the channel volumes
+    pub fn play(&mut self, sound: &Sound, volume: f32) {
+        if self.voices.len() >= MAX_VOICES {
+            self.voices.remove(0);
+        }
+
+        self.voices.push(Voice {
+            sound: sound.clone(),
+            frame: 0,
+            volume
+        });
+    }
+
+    /// Loops a music track, crossfading from the current one
+    /// # Arguments
+    /// * `sound` - The track to play
+    /// * `fade` - Seconds taken to fade in the new track and fade out the old one
+    pub fn play_music(&mut self, sound: &Sound, fade: f32) {
+        self.stop_music(fade);
+
+        let mut track = Track::new(sound);
+        track.fade_to(1.0, fade);
+        self.music = Some(track);
+    }
+
+    /// Fades out the current music track
+    /// # Arguments
+    /// * `fade` - Seconds taken to fade out
+    pub fn stop_music(&mut self, fade: f32) {
+        if let Some(mut track) = self.music.take() {
+            track.fade_to(0.0, fade);
+            self.fading.push(track);
+        }
+    }
+
+    /// Sets the volume of a channel, clamped from 0 to 1
+    pub fn set_volume(&mut self, channel: Channel, volume: f32) {
+        self.volumes[channel.index()] = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn volume(&self, channel: Channel) -> f32 {
+        self.volumes[channel.index()]
+    }
+
+    /// Number of sound effects playing
+    pub fn voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Number of music tracks audible, including ones fading out
+    pub fn tracks(&self) -> usize {
+        self.fading.len() + self.music.iter().count()
+    }
+
+    /// Fills a buffer of interleaved stereo samples with the next part of the mix
+    /// # Arguments
+    /// * `out` - The buffer to fill
+    pub fn mix(&mut self, out: &mut [f32]) {
+        let master = self.volume(Channel::Master);
+        let music = self.volume(Channel::Music) * master;
+        let effects = self.volume(Channel::Effects) * master;
+
+        for frame in out.chunks_exact_mut(CHANNELS) {
+            let mut left = 0.0;
+            let mut right = 0.0;
+
+            for track in self.music.iter_mut().chain(self.fading.iter_mut()) {
+                let (l, r) = track.next();
+                left += l * music;
+                right += r * music;
+            }
+
+            for voice in self.voices.iter_mut() {
+                if voice.frame < voice.sound.frames() {
+                    let index = voice.frame * CHANNELS;
+                    left += voice.sound.samples[index] * voice.volume * effects;
+                    right += voice.sound.samples[index + 1] * voice.volume * effects;
+                    voice.frame += 1;
+                }
+            }
+
+            frame[0] = left.clamp(-1.0, 1.0);
+            frame[1] = right.clamp(-1.0, 1.0);
+        }
+
+        self.voices
+            .retain(|voice| voice.frame < voice.sound.frames());
+        self.fading.retain(|track| track.gain > 0.0);
+    }
+}
+
+impl AudioCallback for Mixer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.mix(out);
+    }
+}
+
+/// Sounds and music loaded from an audio config
+#[derive(Debug, Default)]
+pub struct SoundLibrary {
+    pub sounds: HashMap<String, Handle<Sound>>,
+    pub music: HashMap<String, Handle<Sound>>,
+    /// Seconds taken to crossfade between music tracks
+    pub crossfade: f32,
+    /// Channel volumes, in the order master, music, effects
+    pub volumes: [f32; 3]
+}
+
+impl SoundLibrary {
+    /// Loads every sound named in an audio config
+    /// Sound paths are relative to the config file.
+    /// # Arguments
+    /// * `path` - The path of the audio config
+    /// * `assets` - Decodes each sound file once
+    pub fn load(path: &str, assets: &mut AssetManager) -> Result<SoundLibrary, AudioError> {
+        let source = fs::read_to_string(path).map_err(|e| AudioError::Io {
+            path: String::from(path),
+            message: e.to_string()
+        })?;
+        let root = source
+            .parse::<toml::Value>()
+            .map_err(|e| AudioError::Parse(e.to_string()))?;
+
+        let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let mut library = SoundLibrary {
+            crossfade: number(&root, "crossfade")?.unwrap_or(1.0),
+            volumes: [1.0; 3],
+            ..SoundLibrary::default()
+        };
+
+        if let Some(volume) = root.get("volume") {
+            for (index, name) in ["master", "music", "effects"].iter().enumerate() {
+                if let Some(value) = number(volume, name)? {
+                    library.volumes[index] = value;
+                }
+            }
+        }
+
+        for (table, sounds) in [
+            ("sounds", &mut library.sounds),
+            ("music", &mut library.music)
+        ] {
+            let entries = match root.get(table) {
+                Some(toml::Value::Table(entries)) => entries,
+                Some(_) => {
+                    return Err(AudioError::InvalidField {
+                        field: String::from(table),
+                        expected: "a table"
+                    })
+                },
+                None => continue
+            };
+
+            for (name, file) in entries {
+                let file = file.as_str().ok_or_else(|| AudioError::InvalidField {
+                    field: format!("{}.{}", table, name),
+                    expected: "a path"
+                })?;
+
+                let file = directory.join(file).to_string_lossy().into_owned();
+                let sound = assets.load_sound(&file).map_err(|error| match error {
+                    AssetError::Sound(error) => error,
+                    error => AudioError::Io {
+                        path: file.clone(),
+                        message: error.to_string()
+                    }
+                })?;
+                sounds.insert(name.clone(), sound);
+            }
+        }
+
+        Ok(library)
+    }
+}
+
+/// Reads an optional number from a table
+fn number(table: &toml::Value, field: &str) -> Result<Option<f32>, AudioError> {
+    match table.get(field) {
+        Some(toml::Value::Float(value)) => Ok(Some(*value as f32)),
+        Some(toml::Value::Integer(value)) => Ok(Some(*value as f32)),
+        Some(_) => Err(AudioError::InvalidField {
+            field: String::from(field),
+            expected: "a number"
+        }),
+        None => Ok(None)
+    }
+}
+
+/// Plays sound events on the audio device
+/// Setting `SDL_AUDIODRIVER=dummy` opens a device with no output, for machines without sound.
+pub struct AudioPlayer {
+    device: AudioDevice<Mixer>,
+    library: SoundLibrary
+}
+
+impl AudioPlayer {
+    /// Opens the default output device and starts playing
+    /// # Arguments
+    /// * `audio` - The SDL audio subsystem
+    /// * `library` - The sounds that can be played
+    pub fn open(audio: &AudioSubsystem, library: SoundLibrary) -> Result<AudioPlayer, AudioError> {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(CHANNELS as u8),
+            samples: Some(1024)
+        };
+
+        // SDL converts the mix if the device uses another rate or format
+        let device = audio
+            .open_playback(None, &desired, |_| {
+                let mut mixer = Mixer::new();
+                mixer.set_volume(Channel::Master, library.volumes[0]);
+                mixer.set_volume(Channel::Music, library.volumes[1]);
+                mixer.set_volume(Channel::Effects, library.volumes[2]);
+                mixer
+            })
+            .map_err(AudioError::Device)?;
+        device.resume();
+
+        Ok(AudioPlayer { device, library })
+    }
+
+    /// Plays a sound event, ignoring sounds missing from the library
+    pub fn play(&mut self, event: &SoundEvent) {
+        let library = &self.library;
+        let crossfade = library.crossfade;
+
+        match event {
+            SoundEvent::Music(name) => {
+                if let Some(sound) = library.music.get(name) {
+                    self.device.lock().play_music(sound, crossfade);
+                }
+            },
+            SoundEvent::StopMusic => self.device.lock().stop_music(crossfade),
+            _ => {
+                if let Some(sound) = event
+                    .effect_name()
+                    .and_then(|name| library.sounds.get(name))
+                {
+                    self.device.lock().play(sound, 1.0);
+                }
+            }
+        }
+    }
+
+    /// Sets the volume of a channel
+    pub fn set_volume(&mut self, channel: Channel, volume: f32) {
+        self.device.lock().set_volume(channel, volume);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::mock::MockBackend;
+    use crate::component_system::systems::AudioSystem;
+    use specs::prelude::*;
+
+    /// A sound of a number of frames with every sample at one value
+    fn constant(value: f32, frames: usize) -> Sound {
+        Sound::from_samples(vec![value; frames * CHANNELS])
+    }
+
+    fn mix(mixer: &mut Mixer, frames: usize) -> Vec<f32> {
+        let mut out = vec![0.0; frames * CHANNELS];
+        mixer.mix(&mut out);
+        out
+    }
+
+    #[test]
+    fn queue_drains_in_order() {
+        let mut queue = SoundQueue::default();
+        queue.push(SoundEvent::Hit);
+        queue.push(SoundEvent::Music(String::from("theme")));
+
+        assert_eq!(
+            queue.drain(),
+            [SoundEvent::Hit, SoundEvent::Music(String::from("theme"))]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn mixes_effects_until_they_end() {
+        let mut mixer = Mixer::new();
+        mixer.play(&constant(0.25, 2), 1.0);
+        mixer.play(&constant(0.5, 1), 0.5);
+
+        assert_eq!(mix(&mut mixer, 3), [0.5, 0.5, 0.25, 0.25, 0.0, 0.0]);
+        assert_eq!(mixer.voices(), 0);
+    }
+
+    #[test]
+    fn channel_volumes_scale_the_mix() {
+        let mut mixer = Mixer::new();
+        mixer.set_volume(Channel::Master, 0.5);
+        mixer.set_volume(Channel::Effects, 0.5);
+        mixer.set_volume(Channel::Music, 2.0);
+        assert_eq!(mixer.volume(Channel::Music), 1.0);
+
+        mixer.play(&constant(1.0, 1), 1.0);
+        assert_eq!(mix(&mut mixer, 1), [0.25, 0.25]);
+    }
+
+    #[test]
+    fn mix_is_clamped() {
+        let mut mixer = Mixer::new();
+        for _ in 0..3 {
+            mixer.play(&constant(0.5, 1), 1.0);
+        }
+        assert_eq!(mix(&mut mixer, 1), [1.0, 1.0]);
+    }
+
+    #[test]
+    fn oldest_effect_is_cut_off() {
+        let mut mixer = Mixer::new();
+        for _ in 0..MAX_VOICES + 3 {
+            mixer.play(&constant(0.0, 10), 1.0);
+        }
+        assert_eq!(mixer.voices(), MAX_VOICES);
+    }
+
+    #[test]
+    fn music_crossfades_between_tracks() {
+        let fade = 10.0 / SAMPLE_RATE as f32;
+        let mut mixer = Mixer::new();
+
+        mixer.play_music(&constant(1.0, 4), fade);
+        let start = mix(&mut mixer, 10);
+        assert_eq!(start[0], 0.0);
+        assert!(start.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert_eq!(mix(&mut mixer, 1), [1.0, 1.0]);
+
+        // Both tracks play during the crossfade, then only the new one is left
+        mixer.play_music(&constant(0.5, 4), fade);
+        assert_eq!(mixer.tracks(), 2);
+        mix(&mut mixer, 10);
+        assert_eq!(mixer.tracks(), 1);
+        assert_eq!(mix(&mut mixer, 1), [0.5, 0.5]);
+
+        mixer.stop_music(0.0);
+        mix(&mut mixer, 1);
+        assert_eq!(mixer.tracks(), 0);
+    }
+
+    #[test]
+    fn converts_channels_and_rates() {
+        assert_eq!(to_stereo(&[0.1, 0.2], 1), [0.1, 0.1, 0.2, 0.2]);
+        assert_eq!(
+            to_stereo(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 3),
+            [0.1, 0.2, 0.4, 0.5]
+        );
+
+        let samples = [0.0, 0.0, 1.0, 1.0];
+        assert_eq!(resample(&samples, 100, 100), samples);
+        assert_eq!(
+            resample(&samples, 100, 200),
+            [0.0, 0.0, 0.5, 0.5, 1.0, 1.0, 1.0, 1.0]
+        );
+        assert_eq!(resample(&samples, 200, 100), [0.0, 0.0]);
+    }
+
+    /// Opens the dummy device, which plays nothing but runs the mixer like a real device
+    /// Kept to a single test since only one SDL context can exist at a time.
+    #[test]
+    fn plays_through_the_dummy_driver() {
+        std::env::set_var("SDL_AUDIODRIVER", "dummy");
+        let sdl = sdl2::init().unwrap();
+        let audio = sdl.audio().unwrap();
+
+        let mut backend = MockBackend::new();
+        backend.add_file("hit.wav", "");
+        backend.add_file("theme.ogg", "");
+        let mut assets = AssetManager::new(Box::new(backend));
+
+        let mut library = SoundLibrary {
+            crossfade: 0.5,
+            volumes: [1.0, 0.5, 0.25],
+            ..SoundLibrary::default()
+        };
+        library
+            .sounds
+            .insert(String::from("hit"), assets.load_sound("hit.wav").unwrap());
+        library.music.insert(
+            String::from("theme"),
+            assets.load_sound("theme.ogg").unwrap()
+        );
+
+        let mut player = AudioPlayer::open(&audio, library).unwrap();
+        // Paused so the device thread does not finish sounds while they are checked
+        player.device.pause();
+
+        player.play(&SoundEvent::Hit);
+        player.play(&SoundEvent::Footstep);
+        player.play(&SoundEvent::Music(String::from("theme")));
+        player.play(&SoundEvent::Music(String::from("missing")));
+        player.set_volume(Channel::Master, 0.5);
+        {
+            let mixer = player.device.lock();
+            assert_eq!(mixer.voices(), 1);
+            assert_eq!(mixer.tracks(), 1);
+            assert_eq!(mixer.volume(Channel::Master), 0.5);
+            assert_eq!(mixer.volume(Channel::Effects), 0.25);
+        }
+
+        // The audio system empties the queue into the device
+        player.device.resume();
+        let mut world = World::new();
+        world.insert(SoundQueue::default());
+        world.write_resource::<SoundQueue>().push(SoundEvent::Hit);
+        world
+            .write_resource::<SoundQueue>()
+            .push(SoundEvent::StopMusic);
+
+        AudioSystem::new(Some(player)).run_now(&world);
+        assert!(world.read_resource::<SoundQueue>().is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::gl_util::{self, Buffer, Usage, VertexArray};
+use crate::shader::{Program, UniformError};
+
+/// Floats per sprite in the vertex buffer, two triangles of three xyz vertices
+const VERTEX_FLOATS: usize = 18;
+
+/// Floats per sprite in the texture coordinate buffer, two triangles of three uv pairs
+pub const UV_FLOATS: usize = 12;
+
+/// A textured rectangle to draw this frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprite {
+    pub texture: u32,
+    /// Sprites with a lower depth are drawn first, underneath the rest
+    pub depth: f32,
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+    /// Texture coordinates for the two triangles of the quad
+    pub uv: [f32; UV_FLOATS]
+}
+
+impl Sprite {
+    /// Creates a sprite centered on a point
+    /// # Arguments
+    /// * `texture` - The texture the sprite is drawn from
+    /// * `x`, `y`, `z` - Center of the sprite, z decides the drawing order
+    /// * `half_width`, `half_height` - Half the size of the sprite in world coordinates
+    /// * `uv` - Texture coordinates, in the layout of `Frame::uv`
+    pub fn new(
+        texture: u32,
+        (x, y, z): (f32, f32, f32),
+        half_width: f32,
+        half_height: f32,
+        uv: &[f32]
+    ) -> Sprite {
+        let mut coordinates = [0.0; UV_FLOATS];
+        coordinates.copy_from_slice(&uv[..UV_FLOATS]);
+
+        Sprite {
+            texture,
+            depth: z,
+            left: x - half_width,
+            right: x + half_width,
+            bottom: y - half_height,
+            top: y + half_height,
+            uv: coordinates
+        }
+    }
+}
+
+/// A run of sprites sharing a texture, drawn with one call
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    pub texture: u32,
+    /// Index of the first vertex of the run
+    pub first: usize,
+    /// Number of vertices in the run
+    pub count: usize
+}
+
+/// Orders sprites and builds the buffers and draw calls to render them
+/// Sprites are drawn in order of depth. Sprites at the same depth are grouped by texture so
+/// each texture needs only one draw call.
+/// # Arguments
+/// * `sprites` - The sprites to draw, reordered in place
+pub fn build(sprites: &mut [Sprite]) -> (Vec<f32>, Vec<f32>, Vec<Batch>) {
+    sprites.sort_by(|a, b| {
+        a.depth
+            .partial_cmp(&b.depth)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.texture.cmp(&b.texture))
+    });
+
+    let mut vertices = Vec::with_capacity(sprites.len() * VERTEX_FLOATS);
+    let mut texture_vertices = Vec::with_capacity(sprites.len() * UV_FLOATS);
+    let mut batches: Vec<Batch> = Vec::new();
+
+    for (index, sprite) in sprites.iter().enumerate() {
+        // Same winding as gl_util::create_rectangle
+        let (left, right, bottom, top, z) = (
+            sprite.left,
+            sprite.right,
+            sprite.bottom,
+            sprite.top,
+            sprite.depth
+        );
+        vertices.extend_from_slice(&[
+            left, bottom, z, right, top, z, left, top, z, left, bottom, z, right, bottom, z, right,
+            top, z
+        ]);
+        texture_vertices.extend_from_slice(&sprite.uv);
+
+        match batches.last_mut() {
+            Some(batch) if batch.texture == sprite.texture => batch.count += 6,
+            _ => batches.push(Batch {
+                texture: sprite.texture,
+                first: index * 6,
+                count: 6
+            })
+        }
+    }
+
+    (vertices, texture_vertices, batches)
+}
+
+/// Collects sprites over a frame and draws them with one call per texture
+/// The sprite data is streamed into a single pair of buffers each time it is flushed.
+pub struct SpriteBatch {
+    pub attribute_array: VertexArray,
+    pub vertex_buffer: Buffer,
+    pub texture_coord_buffer: Buffer,
+    sprites: Vec<Sprite>,
+    /// Draw calls issued by the last flush
+    draw_calls: usize
+}
+
+impl Default for SpriteBatch {
+    fn default() -> SpriteBatch {
+        SpriteBatch::new()
+    }
+}
+
+impl SpriteBatch {
+    /// Creates the buffers sprites are streamed into
+    pub fn new() -> SpriteBatch {
+        let attribute_array = VertexArray::new();
+        let vertex_buffer = Buffer::new();
+        let texture_coord_buffer = Buffer::new();
+
+        attribute_array.set_attribute(0, &vertex_buffer, 3);
+        attribute_array.set_attribute(1, &texture_coord_buffer, 2);
+
+        SpriteBatch {
+            attribute_array,
+            vertex_buffer,
+            texture_coord_buffer,
+            sprites: Vec::new(),
+            draw_calls: 0
+        }
+    }
+
+    /// Queues a sprite to be drawn on the next flush
+    pub fn push(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    /// Draws every queued sprite and empties the queue
+    /// # Arguments
+    /// * `program` - The shader program to draw with
+    pub fn flush(&mut self, program: &Program) -> Result<(), UniformError> {
+        let (vertices, texture_vertices, batches) = build(&mut self.sprites);
+        self.sprites.clear();
+        self.draw_calls = batches.len();
+
+        if batches.is_empty() {
+            return Ok(());
+        }
+
+        self.vertex_buffer.set_data(&vertices, Usage::Stream);
+        self.texture_coord_buffer
+            .set_data(&texture_vertices, Usage::Stream);
+
+        program.set_used();
+        self.attribute_array.bind();
+
+        // Vertices are already in world coordinates, a size of 2 leaves them unscaled
+        program.set_vec3("position", [0.0, 0.0, 0.0])?;
+        program.set_vec2("size", [2.0, 2.0])?;
+        program.set_sampler("texture_sampler", 0)?;
+
+        for batch in &batches {
+            gl_util::bind_texture(batch.texture);
+            gl_util::draw_triangles_range(batch.first as u32, batch.count as u32);
+        }
+        Ok(())
+    }
+
+    /// Number of draw calls issued by the last flush
+    pub fn draw_calls(&self) -> usize {
+        self.draw_calls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    const UV: [f32; UV_FLOATS] = [0.0; UV_FLOATS];
+
+    fn sprite(texture: u32, depth: f32) -> Sprite {
+        Sprite::new(texture, (0.0, 0.0, depth), 0.5, 0.5, &UV)
+    }
+
+    #[test]
+    fn one_draw_per_texture() {
+        // Drawn one call per entity, these would take 500 draw calls
+        let mut rng = Rng::new(0);
+        let mut sprites: Vec<Sprite> = (0..500)
+            .map(|_| sprite(rng.range(1, 5) as u32, 0.0))
+            .collect();
+
+        let (vertices, texture_vertices, batches) = build(&mut sprites);
+
+        let textures: Vec<u32> = batches.iter().map(|batch| batch.texture).collect();
+        assert_eq!(textures, [1, 2, 3, 4]);
+        assert_eq!(vertices.len(), 500 * VERTEX_FLOATS);
+        assert_eq!(texture_vertices.len(), 500 * UV_FLOATS);
+        assert_eq!(
+            batches.iter().map(|batch| batch.count).sum::<usize>(),
+            500 * 6
+        );
+    }
+
+    #[test]
+    fn batches_cover_their_sprites() {
+        let mut sprites = vec![sprite(2, 0.0), sprite(1, 0.0), sprite(2, 0.0)];
+        let (_, _, batches) = build(&mut sprites);
+
+        assert_eq!(
+            batches,
+            [
+                Batch {
+                    texture: 1,
+                    first: 0,
+                    count: 6
+                },
+                Batch {
+                    texture: 2,
+                    first: 6,
+                    count: 12
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn depth_comes_before_texture() {
+        let mut sprites = vec![sprite(1, 1.0), sprite(2, 0.0), sprite(1, 0.0)];
+        let (vertices, _, batches) = build(&mut sprites);
+
+        let order: Vec<(u32, f32)> = sprites
+            .iter()
+            .map(|sprite| (sprite.texture, sprite.depth))
+            .collect();
+        assert_eq!(order, [(1, 0.0), (2, 0.0), (1, 1.0)]);
+
+        // A texture used at two depths needs a draw for each so the layers stay in order
+        let textures: Vec<u32> = batches.iter().map(|batch| batch.texture).collect();
+        assert_eq!(textures, [1, 2, 1]);
+        assert_eq!(vertices[2 * VERTEX_FLOATS + 2], 1.0);
+    }
+
+    #[test]
+    fn nothing_to_draw_makes_no_batches() {
+        let (vertices, texture_vertices, batches) = build(&mut []);
+        assert!(vertices.is_empty() && texture_vertices.is_empty() && batches.is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::atlas;
+use crate::batch::{Sprite, SpriteBatch};
+use crate::error::Error;
+use crate::gl_util::{self, Buffer, Filter, Texture, Usage, VertexArray};
+use crate::rng::Rng;
+use std::time::{Duration, Instant};
+
+/// Number of sprites drawn each frame
+const SPRITES: usize = 2000;
+
+/// Number of distinct textures the sprites are drawn from
+const TEXTURES: usize = 4;
+
+/// Number of frames timed for each renderer
+const FRAMES: u32 = 100;
+
+/// Compares drawing sprites one call at a time against drawing them with a `SpriteBatch`
+/// Needs a display, so it only runs when asked for with
+/// `cargo test --release -- --ignored --nocapture benchmark`. Set `LIBGL_ALWAYS_SOFTWARE=1` to
+/// measure on a software GL context. Each frame waits for the GPU to finish so the timings
+/// include the driver's work.
+#[test]
+#[ignore]
+fn batching_beats_drawing_per_entity() -> Result<(), Error> {
+    let (_sdl, _window, _context) = crate::init_sdl()?;
+    let program = crate::create_shader_program()?;
+
+    let textures: Vec<Texture> = (0..TEXTURES)
+        .map(|index| solid_texture(index as u8))
+        .collect();
+    let uv = atlas::quad_coordinates(0, 0, 1, 1, 1, 1);
+
+    let mut rng = Rng::new(0);
+    let sprites: Vec<Sprite> = (0..SPRITES)
+        .map(|_| {
+            let texture = textures[rng.range(0, TEXTURES as i32) as usize].id();
+            let position = (rng.float() * 2.0 - 1.0, rng.float() * 2.0 - 1.0, 0.0);
+            Sprite::new(texture, position, 0.02, 0.02, &uv)
+        })
+        .collect();
+
+    // The old renderer kept a buffer and vertex array for every entity
+    let individual: Vec<(VertexArray, Buffer, Buffer)> = sprites
+        .iter()
+        .map(|sprite| {
+            let attribute_array = VertexArray::new();
+            let vertex_buffer = Buffer::new();
+            let texture_coord_buffer = Buffer::new();
+            vertex_buffer.set_data(&gl_util::create_rectangle(1.0, 1.0), Usage::Static);
+            texture_coord_buffer.set_data(&sprite.uv, Usage::Static);
+            attribute_array.set_attribute(0, &vertex_buffer, 3);
+            attribute_array.set_attribute(1, &texture_coord_buffer, 2);
+            (attribute_array, vertex_buffer, texture_coord_buffer)
+        })
+        .collect();
+
+    let unbatched = time_frames(|| {
+        for (sprite, (attribute_array, _, _)) in sprites.iter().zip(&individual) {
+            let position = [
+                (sprite.left + sprite.right) / 2.0,
+                (sprite.bottom + sprite.top) / 2.0,
+                sprite.depth
+            ];
+            let size = [sprite.right - sprite.left, sprite.top - sprite.bottom];
+
+            program.set_used();
+            attribute_array.bind();
+            gl_util::bind_texture(sprite.texture);
+            program.set_vec3("position", position)?;
+            program.set_vec2("size", size)?;
+            gl_util::draw_triangles(6);
+        }
+        Ok(())
+    })?;
+
+    let mut sprite_batch = SpriteBatch::new();
+    let batched = time_frames(|| {
+        for sprite in &sprites {
+            sprite_batch.push(sprite.clone());
+        }
+        sprite_batch.flush(&program)?;
+        Ok(())
+    })?;
+
+    println!(
+        "{} sprites from {} textures over {} frames",
+        SPRITES, TEXTURES, FRAMES
+    );
+    println!(
+        "Per entity: {:.3} ms per frame, {} draw calls",
+        per_frame(unbatched),
+        SPRITES
+    );
+    println!(
+        "Batched:    {:.3} ms per frame, {} draw calls",
+        per_frame(batched),
+        sprite_batch.draw_calls()
+    );
+    println!(
+        "Speedup:    {:.1}x",
+        unbatched.as_secs_f64() / batched.as_secs_f64()
+    );
+
+    assert_eq!(sprite_batch.draw_calls(), TEXTURES);
+    Ok(())
+}
+
+/// Times a number of frames, waiting for each to finish drawing
+fn time_frames<F>(mut draw: F) -> Result<Duration, Error>
+where
+    F: FnMut() -> Result<(), Error>
+{
+    // One untimed frame so buffer allocation is not counted
+    draw()?;
+    unsafe { gl::Finish() };
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        unsafe { gl::Clear(gl::COLOR_BUFFER_BIT) };
+        draw()?;
+        unsafe { gl::Finish() };
+    }
+    Ok(start.elapsed())
+}
+
+fn per_frame(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0 / FRAMES as f64
+}
+
+/// Creates a single pixel texture of a color picked by index
+fn solid_texture(index: u8) -> Texture {
+    let pixel = [
+        index.wrapping_mul(60),
+        255 - index.wrapping_mul(60),
+        128,
+        255
+    ];
+
+    Texture::from_rgba(1, 1, &pixel, Filter::Nearest, Filter::Nearest)
+}
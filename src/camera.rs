@@ -0,0 +1,250 @@
+extern crate nalgebra;
+use nalgebra::{Matrix4, Vector3};
+
+/// A rectangle in world coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32
+}
+
+/// Decides which part of the world is shown on screen
+/// The camera stays still while its target moves inside the dead zone, then eases toward it
+/// and is kept from showing anything outside its bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    /// Center of the view in world coordinates
+    pub x: f32,
+    pub y: f32,
+    /// Magnification, 2 shows half as much of the world
+    pub zoom: f32,
+    /// Width over height of the window
+    pub aspect: f32,
+    /// How quickly the camera catches up with its target, 0 snaps to it immediately
+    pub smoothing: f32,
+    /// Half the size of the area around the center where the target can move freely
+    pub dead_zone: (f32, f32),
+    /// Area the view must stay inside
+    pub bounds: Option<Bounds>
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera {
+            x: 0.0,
+            y: 0.0,
+            zoom: 1.0,
+            aspect: 1.0,
+            smoothing: 6.0,
+            dead_zone: (0.15, 0.1),
+            bounds: None
+        }
+    }
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera::default()
+    }
+
+    /// Half the width and height of the world shown on screen
+    /// The projection shows -aspect to aspect across and -1 to 1 up before zooming.
+    pub fn half_extents(&self) -> (f32, f32) {
+        (self.aspect / self.zoom, 1.0 / self.zoom)
+    }
+
+    /// Returns the visible rectangle of the world
+    pub fn view_bounds(&self) -> Bounds {
+        let (half_width, half_height) = self.half_extents();
+        Bounds {
+            min_x: self.x - half_width,
+            min_y: self.y - half_height,
+            max_x: self.x + half_width,
+            max_y: self.y + half_height
+        }
+    }
+
+    /// Moves the camera straight to a point, still respecting the bounds
+    pub fn snap_to(&mut self, x: f32, y: f32) {
+        self.x = x;
+        self.y = y;
+        self.clamp();
+    }
+
+    /// Moves the camera toward a target
+    /// # Arguments
+    /// * `target` - World position to keep in view
+    /// * `delta` - Seconds since the last update
+    pub fn follow(&mut self, target: (f32, f32), delta: f32) {
+        let desired = (
+            dead_zone_center(self.x, target.0, self.dead_zone.0),
+            dead_zone_center(self.y, target.1, self.dead_zone.1)
+        );
+
+        // Framerate independent easing
+        let t = if self.smoothing > 0.0 {
+            1.0 - (-self.smoothing * delta).exp()
+        } else {
+            1.0
+        };
+
+        self.x += (desired.0 - self.x) * t;
+        self.y += (desired.1 - self.y) * t;
+        self.clamp();
+    }
+
+    /// Keeps the view inside the bounds, centering on any axis where the bounds are smaller
+    fn clamp(&mut self) {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return
+        };
+
+        let (half_width, half_height) = self.half_extents();
+        self.x = clamp_axis(self.x, half_width, bounds.min_x, bounds.max_x);
+        self.y = clamp_axis(self.y, half_height, bounds.min_y, bounds.max_y);
+    }
+
+    /// Returns the matrix taking world coordinates to the coordinates the projection expects
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        let scale = Matrix4::new_nonuniform_scaling(&Vector3::new(self.zoom, self.zoom, 1.0));
+        scale * Matrix4::new_translation(&Vector3::new(-self.x, -self.y, 0.0))
+    }
+}
+
+/// Returns where the center must be so a target sits inside a dead zone around it
+/// # Arguments
+/// * `center` - Current center on one axis
+/// * `target` - Target position on the same axis
+/// * `half_size` - Half the size of the dead zone on that axis
+pub fn dead_zone_center(center: f32, target: f32, half_size: f32) -> f32 {
+    if target > center + half_size {
+        target - half_size
+    } else if target < center - half_size {
+        target + half_size
+    } else {
+        center
+    }
+}
+
+/// Clamps a center on one axis so a view of the given half size stays within a range
+/// If the range is smaller than the view, the view is centered on the range.
+pub fn clamp_axis(center: f32, half_size: f32, min: f32, max: f32) -> f32 {
+    if max - min <= half_size * 2.0 {
+        (min + max) / 2.0
+    } else {
+        center.clamp(min + half_size, max - half_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera {
+            dead_zone: (0.2, 0.1),
+            ..Camera::default()
+        }
+    }
+
+    #[test]
+    fn stays_still_inside_the_dead_zone() {
+        let mut camera = camera();
+        camera.follow((0.15, -0.05), 1.0);
+        assert_eq!((camera.x, camera.y), (0.0, 0.0));
+
+        assert_eq!(dead_zone_center(1.0, 1.2, 0.2), 1.0);
+        assert_eq!(dead_zone_center(1.0, 1.5, 0.2), 1.3);
+        assert_eq!(dead_zone_center(1.0, 0.5, 0.2), 0.7);
+    }
+
+    #[test]
+    fn smoothing_converges_on_the_target() {
+        let mut camera = camera();
+        let mut previous = camera.x;
+        for _ in 0..120 {
+            camera.follow((2.0, 0.0), 1.0 / 60.0);
+            assert!(camera.x > previous);
+            previous = camera.x;
+        }
+
+        // The target ends up at the edge of the dead zone, never overshooting it
+        assert!((camera.x - 1.8).abs() < 1e-3);
+        assert!(camera.x <= 1.8);
+    }
+
+    #[test]
+    fn smoothing_is_framerate_independent() {
+        let mut fast = camera();
+        let mut slow = camera();
+        for _ in 0..4 {
+            fast.follow((2.0, 0.0), 0.025);
+        }
+        slow.follow((2.0, 0.0), 0.1);
+
+        assert!((fast.x - slow.x).abs() < 1e-5);
+    }
+
+    #[test]
+    fn no_smoothing_snaps_to_the_target() {
+        let mut camera = Camera {
+            smoothing: 0.0,
+            ..camera()
+        };
+        camera.follow((2.0, 1.0), 0.0);
+        assert_eq!((camera.x, camera.y), (1.8, 0.9));
+    }
+
+    #[test]
+    fn view_stays_inside_the_bounds() {
+        let mut camera = camera();
+        camera.bounds = Some(Bounds {
+            min_x: -5.0,
+            min_y: -5.0,
+            max_x: 5.0,
+            max_y: 5.0
+        });
+
+        camera.snap_to(4.5, -4.8);
+        assert_eq!((camera.x, camera.y), (4.0, -4.0));
+        assert_eq!(camera.view_bounds().max_x, 5.0);
+    }
+
+    #[test]
+    fn map_smaller_than_the_view_is_centered() {
+        let mut camera = Camera {
+            aspect: 2.0,
+            ..camera()
+        };
+        // Wider than the map across but not up and down
+        camera.bounds = Some(Bounds {
+            min_x: 0.0,
+            min_y: 0.0,
+            max_x: 3.0,
+            max_y: 6.0
+        });
+
+        camera.snap_to(0.0, 0.0);
+        assert_eq!((camera.x, camera.y), (1.5, 1.0));
+        camera.follow((10.0, 10.0), 1.0);
+        assert_eq!(camera.x, 1.5);
+
+        assert_eq!(clamp_axis(7.0, 1.0, 0.0, 2.0), 1.0);
+    }
+
+    #[test]
+    fn zoom_shrinks_the_view() {
+        let camera = Camera {
+            zoom: 2.0,
+            aspect: 1.5,
+            ..camera()
+        };
+        assert_eq!(camera.half_extents(), (0.75, 0.5));
+
+        let point = camera.view_matrix() * nalgebra::Vector4::new(0.5, 0.25, 0.0, 1.0);
+        assert_eq!((point.x, point.y), (1.0, 0.5));
+    }
+}
@@ -0,0 +1,272 @@
+use crate::map::TileMap;
+
+/// Tolerance used so boxes resting against each other do not count as overlapping
+const EPSILON: f32 = 1e-5;
+
+/// An axis aligned bounding box in world coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32
+}
+
+impl Aabb {
+    /// Creates a box from its center and size
+    /// # Arguments
+    /// * `x`, `y` - Center of the box
+    /// * `width`, `height` - Size of the box
+    pub fn from_center(x: f32, y: f32, width: f32, height: f32) -> Aabb {
+        Aabb {
+            min_x: x - width / 2.0,
+            min_y: y - height / 2.0,
+            max_x: x + width / 2.0,
+            max_y: y + height / 2.0
+        }
+    }
+
+    /// Returns the box for a tile of a map
+    /// # Arguments
+    /// * `map` - The map the tile belongs to
+    /// * `column`, `row` - Cell coordinates of the tile
+    pub fn from_tile(map: &TileMap, column: i32, row: i32) -> Aabb {
+        let (x, y) = map.tile_to_world(column, row);
+        Aabb::from_center(x, y, map.tile_size, map.tile_size)
+    }
+
+    /// Returns a copy of the box moved by an offset
+    pub fn translate(&self, dx: f32, dy: f32) -> Aabb {
+        Aabb {
+            min_x: self.min_x + dx,
+            min_y: self.min_y + dy,
+            max_x: self.max_x + dx,
+            max_y: self.max_y + dy
+        }
+    }
+
+    /// Returns true if the boxes overlap by more than touching
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.overlaps_x(other) && self.overlaps_y(other)
+    }
+
+    fn overlaps_x(&self, other: &Aabb) -> bool {
+        self.min_x < other.max_x - EPSILON && self.max_x > other.min_x + EPSILON
+    }
+
+    fn overlaps_y(&self, other: &Aabb) -> bool {
+        self.min_y < other.max_y - EPSILON && self.max_y > other.min_y + EPSILON
+    }
+}
+
+/// Limits a movement so a box does not pass into solid tiles or other boxes
+/// Each axis is resolved separately, x first, so a box blocked on one axis slides along the
+/// other. The whole path is swept so large steps can not tunnel through walls.
+/// # Arguments
+/// * `aabb` - The box being moved
+/// * `delta` - The desired movement
+/// * `map` - The map whose solid tiles block movement, if any
+/// * `obstacles` - Other boxes that block movement
+pub fn resolve(
+    aabb: &Aabb,
+    delta: (f32, f32),
+    map: Option<&TileMap>,
+    obstacles: &[Aabb]
+) -> (f32, f32) {
+    let dx = sweep_x(aabb, delta.0, map, obstacles);
+    let dy = sweep_y(&aabb.translate(dx, 0.0), delta.1, map, obstacles);
+    (dx, dy)
+}
+
+/// Returns how far a box can move along the x axis
+fn sweep_x(aabb: &Aabb, dx: f32, map: Option<&TileMap>, obstacles: &[Aabb]) -> f32 {
+    if dx == 0.0 {
+        return 0.0;
+    }
+
+    // Only boxes ahead of the leading edge can block movement
+    let limit = |allowed: f32, other: &Aabb| {
+        if !aabb.overlaps_y(other) {
+            allowed
+        } else if dx > 0.0 && other.min_x >= aabb.max_x - EPSILON {
+            allowed.min(other.min_x - aabb.max_x)
+        } else if dx < 0.0 && other.max_x <= aabb.min_x + EPSILON {
+            allowed.max(other.max_x - aabb.min_x)
+        } else {
+            allowed
+        }
+    };
+
+    let mut allowed = obstacles.iter().fold(dx, limit);
+
+    if let Some(map) = map {
+        let swept = Aabb {
+            min_x: aabb.min_x.min(aabb.min_x + dx),
+            max_x: aabb.max_x.max(aabb.max_x + dx),
+            ..*aabb
+        };
+
+        allowed = solid_tiles(map, &swept).iter().fold(allowed, limit);
+    }
+
+    allowed
+}
+
+/// Returns how far a box can move along the y axis
+fn sweep_y(aabb: &Aabb, dy: f32, map: Option<&TileMap>, obstacles: &[Aabb]) -> f32 {
+    if dy == 0.0 {
+        return 0.0;
+    }
+
+    // Only boxes ahead of the leading edge can block movement
+    let limit = |allowed: f32, other: &Aabb| {
+        if !aabb.overlaps_x(other) {
+            allowed
+        } else if dy > 0.0 && other.min_y >= aabb.max_y - EPSILON {
+            allowed.min(other.min_y - aabb.max_y)
+        } else if dy < 0.0 && other.max_y <= aabb.min_y + EPSILON {
+            allowed.max(other.max_y - aabb.min_y)
+        } else {
+            allowed
+        }
+    };
+
+    let mut allowed = obstacles.iter().fold(dy, limit);
+
+    if let Some(map) = map {
+        let swept = Aabb {
+            min_y: aabb.min_y.min(aabb.min_y + dy),
+            max_y: aabb.max_y.max(aabb.max_y + dy),
+            ..*aabb
+        };
+
+        allowed = solid_tiles(map, &swept).iter().fold(allowed, limit);
+    }
+
+    allowed
+}
+
+/// Returns the boxes of all solid tiles touching an area, including cells outside the map
+fn solid_tiles(map: &TileMap, area: &Aabb) -> Vec<Aabb> {
+    let (left, top) = map.world_to_tile(area.min_x, area.max_y);
+    let (right, bottom) = map.world_to_tile(area.max_x, area.min_y);
+
+    let mut tiles = Vec::new();
+    for row in top..=bottom {
+        for column in left..=right {
+            if map.is_solid(column, row) {
+                tiles.push(Aabb::from_tile(map, column, row));
+            }
+        }
+    }
+
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::{Atlas, Frame};
+
+    /// A 5 by 5 map of unit tiles with its bottom left corner at the origin, walled on all sides
+    fn room() -> TileMap {
+        let tileset = Atlas {
+            image: String::from("tiles.png"),
+            width: 16,
+            height: 16,
+            frames: vec![Frame {
+                name: String::from("wall"),
+                x: 0,
+                y: 0,
+                width: 16,
+                height: 16,
+                uv: vec![]
+            }]
+        };
+
+        let mut map = TileMap::new(5, 5, 1.0, tileset);
+        map.origin = (0.0, 0.0);
+        map.solid[1] = true;
+        for i in 0..5 {
+            map.set_tile(0, i, 0, 1);
+            map.set_tile(0, i, 4, 1);
+            map.set_tile(0, 0, i, 1);
+            map.set_tile(0, 4, i, 1);
+        }
+        map
+    }
+
+    fn assert_moves(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4,
+            "Moved {:?}, expected {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn moves_freely_in_open_space() {
+        let map = room();
+        let aabb = Aabb::from_center(2.5, 2.5, 0.5, 0.5);
+
+        assert_moves(resolve(&aabb, (0.3, -0.2), Some(&map), &[]), (0.3, -0.2));
+    }
+
+    #[test]
+    fn slides_along_a_wall() {
+        let map = room();
+        // Resting on the bottom wall
+        let aabb = Aabb::from_center(2.5, 1.25, 0.5, 0.5);
+
+        assert_moves(resolve(&aabb, (0.5, -0.5), Some(&map), &[]), (0.5, 0.0));
+        assert_moves(resolve(&aabb, (-0.5, -0.5), Some(&map), &[]), (-0.5, 0.0));
+    }
+
+    #[test]
+    fn blocks_in_a_corner() {
+        let map = room();
+        let aabb = Aabb::from_center(1.25, 1.25, 0.5, 0.5);
+
+        assert_moves(resolve(&aabb, (-0.5, -0.5), Some(&map), &[]), (0.0, 0.0));
+        assert_moves(resolve(&aabb, (-0.5, 0.5), Some(&map), &[]), (0.0, 0.5));
+    }
+
+    #[test]
+    fn stops_at_the_first_wall_of_a_large_step() {
+        let map = room();
+        let aabb = Aabb::from_center(2.5, 2.5, 0.5, 0.5);
+
+        assert_moves(resolve(&aabb, (10.0, 0.0), Some(&map), &[]), (1.25, 0.0));
+        assert_moves(resolve(&aabb, (0.0, -10.0), Some(&map), &[]), (0.0, -1.25));
+    }
+
+    #[test]
+    fn stops_against_other_boxes() {
+        let aabb = Aabb::from_center(0.0, 0.0, 1.0, 1.0);
+        let other = Aabb::from_center(2.0, 0.5, 1.0, 1.0);
+
+        assert_moves(resolve(&aabb, (2.0, 0.0), None, &[other]), (1.0, 0.0));
+        // Boxes that only line up on the other axis do not block
+        assert_moves(resolve(&aabb, (0.0, 2.0), None, &[other]), (0.0, 2.0));
+    }
+
+    #[test]
+    fn overlapping_boxes_can_separate() {
+        let aabb = Aabb::from_center(0.0, 0.0, 1.0, 1.0);
+        let other = Aabb::from_center(0.5, 0.0, 1.0, 1.0);
+        assert!(aabb.overlaps(&other));
+
+        // Neither box is ahead of the other so they never lock together
+        assert_moves(resolve(&aabb, (-1.0, 0.0), None, &[other]), (-1.0, 0.0));
+        assert_moves(resolve(&other, (1.0, 0.0), None, &[aabb]), (1.0, 0.0));
+    }
+
+    #[test]
+    fn touching_is_not_overlapping() {
+        let aabb = Aabb::from_center(0.0, 0.0, 1.0, 1.0);
+
+        assert!(!aabb.overlaps(&Aabb::from_center(1.0, 0.0, 1.0, 1.0)));
+        assert!(aabb.overlaps(&Aabb::from_center(0.9, 0.9, 1.0, 1.0)));
+    }
+}
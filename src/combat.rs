@@ -0,0 +1,167 @@
+use crate::collision::Aabb;
+use crate::rng::Rng;
+
+/// Least damage a hit deals, so armour can not make an entity invulnerable
+pub const MIN_DAMAGE: u32 = 1;
+
+/// How far past touching an entity can reach to attack, in world coordinates
+pub const REACH: f32 = 0.02;
+
+/// The result of one attack
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub amount: u32,
+    pub critical: bool
+}
+
+/// Damage dealt by a hit after armour
+/// # Arguments
+/// * `power` - Damage of a normal hit before armour
+/// * `armour` - Damage taken off every hit
+/// * `critical` - If the hit is a critical hit
+/// * `crit_multiplier` - How much a critical hit multiplies the power by
+pub fn damage(power: u32, armour: u32, critical: bool, crit_multiplier: f32) -> u32 {
+    let power = if critical {
+        (power as f32 * crit_multiplier.max(0.0)).round() as u32
+    } else {
+        power
+    };
+
+    power.saturating_sub(armour).max(MIN_DAMAGE)
+}
+
+/// Rolls whether an attack is a critical hit and works out its damage
+/// # Arguments
+/// * `power` - Damage of a normal hit before armour
+/// * `crit_chance` - Probability of a critical hit, from 0 to 1
+/// * `crit_multiplier` - How much a critical hit multiplies the power by
+/// * `armour` - Damage taken off the hit by the target
+/// * `rng` - Decides critical hits
+pub fn roll_hit(
+    power: u32,
+    crit_chance: f32,
+    crit_multiplier: f32,
+    armour: u32,
+    rng: &mut Rng
+) -> Hit {
+    let critical = rng.chance(crit_chance);
+    Hit {
+        amount: damage(power, armour, critical, crit_multiplier),
+        critical
+    }
+}
+
+/// Returns true if an attacker moving in a direction bumps into a target within reach
+/// # Arguments
+/// * `attacker` - Collision box of the attacker
+/// * `direction` - Direction the attacker is moving in, which does not need to be normalized
+/// * `target` - Collision box of the target
+/// * `reach` - How far past touching the attacker can reach
+pub fn bumps(attacker: &Aabb, direction: (f32, f32), target: &Aabb, reach: f32) -> bool {
+    let length = (direction.0 * direction.0 + direction.1 * direction.1).sqrt();
+    if length == 0.0 {
+        return false;
+    }
+
+    attacker
+        .translate(direction.0 / length * reach, direction.1 / length * reach)
+        .overlaps(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armour_reduces_damage() {
+        assert_eq!(damage(5, 0, false, 2.0), 5);
+        assert_eq!(damage(5, 2, false, 2.0), 3);
+    }
+
+    #[test]
+    fn armour_never_stops_all_damage() {
+        assert_eq!(damage(5, 5, false, 2.0), MIN_DAMAGE);
+        assert_eq!(damage(5, 100, false, 2.0), MIN_DAMAGE);
+        assert_eq!(damage(0, 0, false, 2.0), MIN_DAMAGE);
+        assert_eq!(damage(5, 100, true, 2.0), MIN_DAMAGE);
+    }
+
+    #[test]
+    fn critical_hits_multiply_power_before_armour() {
+        assert_eq!(damage(5, 0, true, 2.0), 10);
+        assert_eq!(damage(5, 4, true, 2.0), 6);
+    }
+
+    #[test]
+    fn critical_damage_rounds_to_the_nearest_point() {
+        assert_eq!(damage(5, 0, true, 1.5), 8);
+        assert_eq!(damage(3, 0, true, 1.1), 3);
+        assert_eq!(damage(3, 0, true, 1.2), 4);
+        assert_eq!(damage(5, 2, true, 1.5), 6);
+    }
+
+    #[test]
+    fn negative_crit_multipliers_deal_the_least_damage() {
+        assert_eq!(damage(5, 0, true, -2.0), MIN_DAMAGE);
+        // Only critical hits use the multiplier
+        assert_eq!(damage(5, 0, false, -2.0), 5);
+    }
+
+    #[test]
+    fn rolls_are_repeatable_for_a_seed() {
+        let roll = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..50)
+                .map(|_| roll_hit(5, 0.3, 2.0, 1, &mut rng))
+                .collect::<Vec<Hit>>()
+        };
+
+        let hits = roll(17);
+        assert_eq!(hits, roll(17));
+        assert!(hits.iter().any(|hit| hit.critical));
+        assert!(hits.iter().any(|hit| !hit.critical));
+        for hit in &hits {
+            assert_eq!(hit.amount, if hit.critical { 9 } else { 4 });
+        }
+    }
+
+    #[test]
+    fn crit_chance_bounds_are_certain() {
+        let mut rng = Rng::new(3);
+        for _ in 0..100 {
+            assert!(!roll_hit(5, 0.0, 2.0, 0, &mut rng).critical);
+            assert!(roll_hit(5, 1.0, 2.0, 0, &mut rng).critical);
+        }
+    }
+
+    #[test]
+    fn bumps_targets_within_reach() {
+        let attacker = Aabb::from_center(0.0, 0.0, 1.0, 1.0);
+        let target = Aabb::from_center(1.01, 0.0, 1.0, 1.0);
+
+        assert!(bumps(&attacker, (1.0, 0.0), &target, REACH));
+        // Directions do not need to be normalized
+        assert!(bumps(&attacker, (5.0, 0.0), &target, REACH));
+        assert!(!bumps(&attacker, (-1.0, 0.0), &target, REACH));
+        assert!(!bumps(&attacker, (0.0, 1.0), &target, REACH));
+    }
+
+    #[test]
+    fn does_not_bump_targets_out_of_reach() {
+        let attacker = Aabb::from_center(0.0, 0.0, 1.0, 1.0);
+        let target = Aabb::from_center(1.05, 0.0, 1.0, 1.0);
+
+        assert!(!bumps(&attacker, (1.0, 0.0), &target, REACH));
+        assert!(bumps(&attacker, (1.0, 0.0), &target, 0.1));
+    }
+
+    #[test]
+    fn standing_still_bumps_nothing() {
+        let attacker = Aabb::from_center(0.0, 0.0, 1.0, 1.0);
+        let touching = Aabb::from_center(1.0, 0.0, 1.0, 1.0);
+        let overlapping = Aabb::from_center(0.5, 0.0, 1.0, 1.0);
+
+        assert!(!bumps(&attacker, (0.0, 0.0), &touching, REACH));
+        assert!(!bumps(&attacker, (0.0, 0.0), &overlapping, REACH));
+    }
+}
@@ -3,7 +3,11 @@ extern crate specs;
 
 // Components
 pub mod components {
-    use crate::gl_util;
+    use crate::ai::Behaviour;
+    use crate::animation::Animator;
+    use crate::assets::{Handle, Texture};
+    use crate::atlas::Frame;
+    use crate::collision::Aabb;
     use specs::{Component, NullStorage, VecStorage};
 
     /// Entity position in world coordinates
@@ -31,6 +35,15 @@ pub mod components {
         }
     }
 
+    /// Entity position before the last simulation step, used to draw between steps
+    #[derive(Component, Debug, Clone, Copy)]
+    #[storage(VecStorage)]
+    pub struct LastPosition {
+        pub x: f32,
+        pub y: f32,
+        pub z: f32
+    }
+
     /// Entity velocity in world coordinates
     #[derive(Component)]
     #[storage(VecStorage)]
@@ -77,45 +90,29 @@ pub mod components {
         }
     }
 
+    /// Entity is drawn as a sprite sheet frame
     #[derive(Component)]
     #[storage(VecStorage)]
     pub struct Drawn {
-        pub program: u32,
-        pub attribute_array: u32,
-        pub vertex_buffer: u32,
-        pub vertex_count: u32,
-        pub texture_id: u32,
-        pub texture_coord_buffer: u32
+        pub texture: Handle<Texture>,
+        /// Half the width and height of the frame, with the longer side scaled to 1
+        pub extent: (f32, f32),
+        /// Texture coordinates of the frame shown
+        pub uv: Vec<f32>
     }
     impl Drawn {
-        pub fn new(
-            program: u32,
-            texture_id: u32,
-            vertices: Vec<f32>,
-            texture_vertices: Vec<f32>
-        ) -> Drawn {
-            unsafe {
-                gl::UseProgram(program);
-            }
-
-            let attribute_array = gl_util::generate_vertex_array();
-            gl_util::bind_array(attribute_array);
-
-            let vertex_buffer = gl_util::generate_buffer();
-            gl_util::set_buffer_data(vertex_buffer, &vertices);
-            gl_util::set_vertex_array_pointer(vertex_buffer, attribute_array, 0, 3);
-
-            let texture_coord_buffer = gl_util::generate_buffer();
-            gl_util::set_buffer_data(texture_coord_buffer, &texture_vertices);
-            gl_util::set_vertex_array_pointer(texture_coord_buffer, attribute_array, 1, 2);
+        /// Creates a sprite showing a sprite sheet frame
+        /// # Arguments
+        /// * `texture` - The texture containing the sprite sheet
+        /// * `frame` - The sprite sheet frame to draw
+        pub fn new(texture: Handle<Texture>, frame: &Frame) -> Drawn {
+            // The top right corner of the frame's rectangle
+            let vertices = frame.vertices();
 
             Drawn {
-                program,
-                attribute_array,
-                vertex_buffer,
-                vertex_count: vertices.len() as u32,
-                texture_id,
-                texture_coord_buffer
+                texture,
+                extent: (vertices[3], vertices[4]),
+                uv: frame.uv.clone()
             }
         }
     }
@@ -124,78 +121,281 @@ pub mod components {
     #[derive(Component)]
     #[storage(VecStorage)]
     pub struct Animate {
-        pub speed: std::time::Duration,
-        pub time_elapsed: std::time::Duration,
-        pub layer: u32,
-        pub texture_coord_buffers: Vec<u32>
+        pub animator: Animator,
+        /// Texture coordinates of each frame the animator can show
+        pub frame_uvs: Vec<Vec<f32>>
     }
     impl Animate {
-        pub fn new(speed: f32, layer_coordinates: Vec<Vec<f32>>) -> Animate {
-            let mut texture_coord_buffers = Vec::new();
-
-            for vertices in layer_coordinates {
-                let buffer = gl_util::generate_buffer();
-                gl_util::set_buffer_data(buffer, &vertices);
-
-                texture_coord_buffers.push(buffer);
+        /// Creates an animated component
+        /// # Arguments
+        /// * `frames` - Sprite sheet frames, indexed by the animator's keyframes
+        /// * `animator` - Clip state for the entity
+        pub fn new(frames: &[Frame], animator: Animator) -> Animate {
+            Animate {
+                animator,
+                frame_uvs: frames.iter().map(|frame| frame.uv.clone()).collect()
             }
+        }
+    }
 
-            Animate {
-                speed: std::time::Duration::from_secs_f32(speed),
-                time_elapsed: std::time::Duration::new(0, 0),
-                layer: 0,
-                texture_coord_buffers
+    /// Entity blocks and is blocked by solid tiles and other colliders
+    #[derive(Component, Debug, Default)]
+    #[storage(VecStorage)]
+    pub struct Collider {
+        /// Size of the collision box, or the entity's `Size` if not given
+        pub size: Option<(f32, f32)>
+    }
+    impl Collider {
+        pub fn new(width: f32, height: f32) -> Collider {
+            Collider {
+                size: Some((width, height))
             }
         }
+
+        /// A collider the same size as the entity
+        pub fn from_size() -> Collider {
+            Collider { size: None }
+        }
+
+        /// Returns the collision box for an entity
+        /// # Arguments
+        /// * `position` - Entity position
+        /// * `size` - Entity size, used when the collider has no size of its own
+        pub fn aabb(&self, position: &Position, size: Option<&Size>) -> Aabb {
+            let (width, height) = match (self.size, size) {
+                (Some(collider), _) => collider,
+                (None, Some(size)) => (size.width, size.height),
+                (None, None) => (0.0, 0.0)
+            };
+
+            Aabb::from_center(position.x, position.y, width, height)
+        }
+    }
+
+    /// Entity takes turns in turn based mode
+    #[derive(Component, Debug)]
+    #[storage(VecStorage)]
+    pub struct Actor {
+        /// Energy gained per tick, an actor with twice the speed acts twice as often
+        pub speed: u32
+    }
+    impl Actor {
+        pub fn new(speed: u32) -> Actor {
+            Actor { speed }
+        }
     }
 
     /// If an entity is controlled
     #[derive(Component, Default)]
     #[storage(NullStorage)]
     pub struct Controlled;
+
+    /// Hit points of an entity, which dies once they run out
+    #[derive(Component, Debug)]
+    #[storage(VecStorage)]
+    pub struct Health {
+        pub current: u32,
+        pub max: u32,
+        /// If the entity is left behind as a corpse when it dies instead of being removed
+        pub corpse: bool
+    }
+    impl Health {
+        pub fn new(max: u32, corpse: bool) -> Health {
+            Health {
+                current: max,
+                max,
+                corpse
+            }
+        }
+
+        /// Takes damage off the current health, returning true if the entity died
+        pub fn take(&mut self, amount: u32) -> bool {
+            self.current = self.current.saturating_sub(amount);
+            self.is_dead()
+        }
+
+        pub fn is_dead(&self) -> bool {
+            self.current == 0
+        }
+
+        /// Current health as a fraction of the maximum, from 0 to 1
+        pub fn fraction(&self) -> f32 {
+            if self.max == 0 {
+                return 0.0;
+            }
+            self.current as f32 / self.max as f32
+        }
+    }
+
+    /// How hard and how often an entity hits in melee
+    #[derive(Component, Debug)]
+    #[storage(VecStorage)]
+    pub struct AttackStats {
+        /// Damage of a normal hit before armour
+        pub power: u32,
+        /// Probability of a critical hit, from 0 to 1
+        pub crit_chance: f32,
+        /// How much a critical hit multiplies the power by
+        pub crit_multiplier: f32,
+        /// Time between attacks in real time mode
+        pub cooldown: std::time::Duration,
+        /// Time until the entity can attack again in real time mode
+        pub recovering: std::time::Duration
+    }
+    impl AttackStats {
+        pub fn new(power: u32, crit_chance: f32, crit_multiplier: f32) -> AttackStats {
+            AttackStats {
+                power,
+                crit_chance,
+                crit_multiplier,
+                cooldown: std::time::Duration::from_millis(500),
+                recovering: std::time::Duration::new(0, 0)
+            }
+        }
+    }
+
+    /// Reduces the damage an entity takes from each hit
+    #[derive(Component, Debug, Default)]
+    #[storage(VecStorage)]
+    pub struct Defense {
+        pub armour: u32
+    }
+
+    /// Entity fights the controlled entity
+    #[derive(Component, Default)]
+    #[storage(NullStorage)]
+    pub struct Hostile;
+
+    /// The remains of an entity that died
+    #[derive(Component, Default)]
+    #[storage(NullStorage)]
+    pub struct Corpse;
+
+    /// Moves an entity on its own, chasing the player when it sees them
+    #[derive(Component, Debug)]
+    #[storage(VecStorage)]
+    pub struct Brain {
+        pub behaviour: Behaviour,
+        /// The cell returned to after losing the player, where the entity first thinks if not set
+        pub home: Option<(i32, i32)>,
+        /// Furthest the player can be seen from, in tiles
+        pub sight: u32,
+        /// Fraction of health below which the entity runs from the player
+        pub flee_below: f32,
+        /// If the entity wanders when it has nothing to do, instead of standing still
+        pub wanders: bool,
+        /// Speed in real time mode, in world coordinates per second
+        pub speed: f32,
+        /// The cell being walked to in real time mode
+        pub target: Option<(i32, i32)>
+    }
+    impl Brain {
+        pub fn new(sight: u32, flee_below: f32, wanders: bool, speed: f32) -> Brain {
+            Brain {
+                behaviour: if wanders {
+                    Behaviour::Wander
+                } else {
+                    Behaviour::Idle
+                },
+                home: None,
+                sight,
+                flee_below,
+                wanders,
+                speed,
+                target: None
+            }
+        }
+    }
 }
 
 /// ECS Resources
 pub mod resources {
+    use crate::timestep;
+    use crate::turn::Action;
+    use specs::Entity;
 
-    /// Stores delta time
-    pub struct DeltaTime {
-        last: std::time::Instant,
-        pub delta: std::time::Duration
+    /// The action the player has chosen for their next turn
+    #[derive(Default, Debug)]
+    pub struct PendingAction(pub Option<Action>);
+
+    /// Emitted when an entity's animation clip reaches its end
+    #[derive(Debug, Clone)]
+    pub struct ClipFinished {
+        pub entity: Entity,
+        pub clip: String
     }
-    impl DeltaTime {
-        pub fn update(&mut self) {
-            let now = std::time::Instant::now();
-            self.delta = now - self.last;
-            self.last = now;
+
+    /// Emitted as melee attacks land
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum CombatEvent {
+        Damaged {
+            attacker: Entity,
+            target: Entity,
+            amount: u32,
+            critical: bool
+        },
+        Died {
+            entity: Entity,
+            killer: Entity
         }
     }
+
+    /// Melee attacks requested this step as attacker and target, resolved by the CombatSystem
+    #[derive(Default, Debug)]
+    pub struct MeleeAttacks(pub Vec<(Entity, Entity)>);
+
+    /// Length of the simulation step being run
+    pub struct DeltaTime {
+        pub delta: std::time::Duration
+    }
     impl Default for DeltaTime {
         fn default() -> DeltaTime {
             DeltaTime {
-                last: std::time::Instant::now(),
-                delta: std::time::Duration::new(0, 0)
+                delta: timestep::DEFAULT_STEP
             }
         }
     }
 
-    /// Stores current keyboard inputs
+    /// Timing of the frame being drawn
+    #[derive(Debug, Clone, Copy)]
+    pub struct FrameTime {
+        /// Scaled time since the last frame
+        pub delta: std::time::Duration,
+        /// How far the frame is between the previous and current simulation step, from 0 to 1
+        pub alpha: f32
+    }
+    impl Default for FrameTime {
+        fn default() -> FrameTime {
+            FrameTime {
+                delta: std::time::Duration::new(0, 0),
+                alpha: 1.0
+            }
+        }
+    }
+
+    /// Seed the next level is generated from
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct LevelSeed(pub u64);
+
+    /// Text shown in the top left corner of the screen
     #[derive(Default, Debug)]
-    pub struct InputState {
-        pub up: bool,
-        pub down: bool,
-        pub left: bool,
-        pub right: bool,
-        pub action: bool
-    }
-    impl InputState {
-        pub fn new() -> InputState {
-            InputState {
-                up: false,
-                down: false,
-                left: false,
-                right: false,
-                action: false
+    pub struct HudText(pub String);
+
+    /// The rectangle of the world visible on screen
+    #[derive(Debug, Clone, Copy)]
+    pub struct View {
+        pub left: f32,
+        pub right: f32,
+        pub bottom: f32,
+        pub top: f32
+    }
+    impl Default for View {
+        fn default() -> View {
+            View {
+                left: -1.0,
+                right: 1.0,
+                bottom: -1.0,
+                top: 1.0
             }
         }
     }
@@ -206,89 +406,263 @@ pub mod systems {
 
     use super::components::*;
     use super::resources::*;
-    use crate::gl_util;
+    use crate::ai::{self, Behaviour};
+    use crate::animation;
+    use crate::audio::{AudioPlayer, SoundEvent, SoundQueue};
+    use crate::batch::Sprite;
+    use crate::camera::Camera;
+    use crate::collision::{self, Aabb};
+    use crate::combat;
+    use crate::input::InputState;
+    use crate::map::TileMap;
+    use crate::render::Renderer;
+    use crate::rng::Rng;
+    use crate::timestep;
+    use crate::turn::{self, Action, GameMode, Scheduler};
     use specs::prelude::*;
+    use specs::shrev::EventChannel;
 
-    /// Draws an entity to the screen
-    /// This is the only system that may make OpenGL function calls since OpenGL is not threadsafe.
+    /// Submits the map, entities and HUD to the renderer
+    /// This is the only system that may draw since OpenGL is not threadsafe.
     pub struct DrawSystem;
     impl<'a> System<'a> for DrawSystem {
         type SystemData = (
             ReadStorage<'a, Drawn>,
             ReadStorage<'a, Position>,
-            ReadStorage<'a, Size>
+            ReadStorage<'a, LastPosition>,
+            ReadStorage<'a, Size>,
+            Option<Write<'a, Box<dyn Renderer>>>,
+            Option<Read<'a, TileMap>>,
+            Read<'a, HudText>,
+            Read<'a, View>,
+            Read<'a, Camera>,
+            Read<'a, FrameTime>
         );
 
-        fn run(&mut self, (drawn, position, size): Self::SystemData) {
-            unsafe {
-                gl::Clear(gl::COLOR_BUFFER_BIT);
+        fn run(
+            &mut self,
+            (
+                drawn,
+                position,
+                last_position,
+                size,
+                renderer,
+                map,
+                hud_text,
+                view,
+                camera,
+                frame_time
+            ): Self::SystemData
+        ) {
+            let mut renderer = match renderer {
+                Some(renderer) => renderer,
+                None => return
             };
 
-            for (drawn, position, size) in (&drawn, &position, &size).join() {
-                unsafe {
-                    gl::UseProgram(drawn.program);
-                }
+            // The map and entities move with the camera
+            renderer.set_view(&camera.view_matrix());
 
-                // Update texture coordinate buffer
-                gl_util::set_vertex_array_pointer(
-                    drawn.texture_coord_buffer,
-                    drawn.attribute_array,
-                    1,
-                    2
-                );
+            // Draw the map beneath all entities
+            if let Some(map) = map {
+                renderer.draw_map(&map, (view.left, view.bottom), (view.right, view.top));
+            }
+
+            let entities = (&drawn, &position, last_position.maybe(), &size).join();
+            for (drawn, position, last, size) in entities {
+                let half_width = drawn.extent.0 * size.width / 2.0;
+                let half_height = drawn.extent.1 * size.height / 2.0;
 
-                gl_util::bind_array(drawn.attribute_array);
-                gl_util::bind_texture(drawn.texture_id);
+                // Draw between the last two steps so movement is smooth at any frame rate
+                let (x, y, z) = drawn_position(position, last, frame_time.alpha);
 
-                // Update layout information
-                gl_util::set_uniform_float_vec3("position", drawn.program, &position.as_vec());
-                gl_util::set_uniform_float_vec2("size", drawn.program, &size.as_vec());
+                // Skip sprites entirely off screen
+                if x + half_width < view.left
+                    || x - half_width > view.right
+                    || y + half_height < view.bottom
+                    || y - half_height > view.top
+                {
+                    continue;
+                }
 
-                // Issue the draw call
-                gl_util::draw_triangles(drawn.vertex_count / 3);
+                renderer.draw_sprite(Sprite::new(
+                    drawn.texture.id,
+                    (x, y, z),
+                    half_width,
+                    half_height,
+                    &drawn.uv
+                ));
             }
+
+            // The HUD stays put on screen, where x spans -aspect to aspect and y -1 to 1
+            let margin = 0.03;
+            renderer.draw_text(&hud_text.0, -camera.aspect + margin, 1.0 - margin);
         }
     }
 
-    /// Updates texture coordinates
+    /// Advances animation clips and updates texture coordinates
     pub struct AnimateSystem;
     impl<'a> System<'a> for AnimateSystem {
         type SystemData = (
+            Entities<'a>,
             WriteStorage<'a, Animate>,
             WriteStorage<'a, Drawn>,
-            Read<'a, DeltaTime>
+            ReadStorage<'a, Velocity>,
+            ReadStorage<'a, Controlled>,
+            Read<'a, DeltaTime>,
+            Write<'a, EventChannel<ClipFinished>>,
+            Write<'a, SoundQueue>
         );
 
-        fn run(&mut self, (mut animate, mut drawn, delta_time): Self::SystemData) {
-            for (animate, drawn) in (&mut animate, &mut drawn).join() {
-                animate.time_elapsed += delta_time.delta;
+        fn run(
+            &mut self,
+            (
+                entities,
+                mut animate,
+                mut drawn,
+                velocity,
+                controlled,
+                delta_time,
+                mut finished,
+                mut sounds
+            ): Self::SystemData
+        ) {
+            for (entity, animate, drawn, velocity) in
+                (&entities, &mut animate, &mut drawn, velocity.maybe()).join()
+            {
+                // Switch between standing and walking clips
+                if let Some(velocity) = velocity {
+                    let moving = velocity.x != 0.0 || velocity.y != 0.0;
+                    let clip = animation::locomotion_clip(
+                        &animate.animator.clip().name,
+                        moving,
+                        animate.animator.is_finished()
+                    );
+
+                    if let Some(clip) = clip {
+                        if animate.animator.has_clip(clip) {
+                            animate.animator.play(clip);
+                        }
+                    }
+                }
 
-                if animate.time_elapsed >= animate.speed {
-                    animate.time_elapsed = std::time::Duration::new(0, 0);
-                    animate.layer += 1;
+                for _ in 0..animate.animator.advance(delta_time.delta) {
+                    finished.single_write(ClipFinished {
+                        entity,
+                        clip: animate.animator.clip().name.clone()
+                    });
 
-                    if animate.layer >= animate.texture_coord_buffers.len() as u32 {
-                        animate.layer = 0;
+                    // The player's steps are heard once per walk cycle
+                    if controlled.contains(entity) && animate.animator.clip().name == "walk" {
+                        sounds.push(SoundEvent::Footstep);
                     }
+                }
 
-                    drawn.texture_coord_buffer =
-                        animate.texture_coord_buffers[animate.layer as usize];
+                drawn
+                    .uv
+                    .clone_from(&animate.frame_uvs[animate.animator.frame()]);
+            }
+        }
+    }
+
+    /// Plays the sounds queued by other systems (thread local)
+    /// Without an audio device the queue is still emptied each frame.
+    pub struct AudioSystem {
+        player: Option<AudioPlayer>
+    }
+    impl AudioSystem {
+        pub fn new(player: Option<AudioPlayer>) -> AudioSystem {
+            AudioSystem { player }
+        }
+    }
+    impl<'a> System<'a> for AudioSystem {
+        type SystemData = Write<'a, SoundQueue>;
+
+        fn run(&mut self, mut sounds: Self::SystemData) {
+            for event in sounds.drain() {
+                if let Some(player) = self.player.as_mut() {
+                    player.play(&event);
                 }
             }
         }
     }
 
-    /// Computes the delta time step
-    pub struct TimeSystem;
-    impl<'a> System<'a> for TimeSystem {
-        type SystemData = Write<'a, DeltaTime>;
+    /// Moves the camera after the player and updates the visible area of the world
+    /// Runs once per frame, following the player where they are drawn.
+    pub struct CameraSystem;
+    impl<'a> System<'a> for CameraSystem {
+        type SystemData = (
+            ReadStorage<'a, Controlled>,
+            ReadStorage<'a, Position>,
+            ReadStorage<'a, LastPosition>,
+            Option<Read<'a, TileMap>>,
+            Read<'a, FrameTime>,
+            Write<'a, Camera>,
+            Write<'a, View>
+        );
 
-        fn run(&mut self, mut delta_time: Self::SystemData) {
-            delta_time.update();
+        fn run(
+            &mut self,
+            (controlled, position, last_position, map, frame_time, mut camera, mut view): Self::SystemData
+        ) {
+            camera.bounds = map.map(|map| map.bounds());
+
+            if let Some((_, position, last)) = (&controlled, &position, last_position.maybe())
+                .join()
+                .next()
+            {
+                let (x, y, _) = drawn_position(position, last, frame_time.alpha);
+                camera.follow((x, y), frame_time.delta.as_secs_f32());
+            }
+
+            let bounds = camera.view_bounds();
+            view.left = bounds.min_x;
+            view.right = bounds.max_x;
+            view.bottom = bounds.min_y;
+            view.top = bounds.max_y;
+        }
+    }
+
+    /// Remembers where entities were before a simulation step so they can be drawn between steps
+    pub struct SnapshotSystem;
+    impl<'a> System<'a> for SnapshotSystem {
+        type SystemData = (
+            Entities<'a>,
+            ReadStorage<'a, Position>,
+            WriteStorage<'a, LastPosition>
+        );
+
+        fn run(&mut self, (entities, position, mut last_position): Self::SystemData) {
+            for (entity, position) in (&entities, &position).join() {
+                let last = LastPosition {
+                    x: position.x,
+                    y: position.y,
+                    z: position.z
+                };
+                if let Err(message) = last_position.insert(entity, last) {
+                    eprintln!("Failed to store the last position: {}", message);
+                }
+            }
+        }
+    }
+
+    /// Where to draw an entity between the previous and current simulation step
+    /// Entities that have not been through a step yet are drawn where they are.
+    fn drawn_position(
+        position: &Position,
+        last: Option<&LastPosition>,
+        alpha: f32
+    ) -> (f32, f32, f32) {
+        match last {
+            Some(last) => (
+                timestep::interpolate(last.x, position.x, alpha),
+                timestep::interpolate(last.y, position.y, alpha),
+                timestep::interpolate(last.z, position.z, alpha)
+            ),
+            None => (position.x, position.y, position.z)
         }
     }
 
-    /// Modifies entity velocity based on keyboard input
+    /// Modifies entity velocity based on the held movement actions or the left stick
     pub struct ControlSystem;
     impl<'a> System<'a> for ControlSystem {
         type SystemData = (
@@ -298,36 +672,529 @@ pub mod systems {
         );
 
         fn run(&mut self, (mut velocity, controlled, input_state): Self::SystemData) {
-            let up = if input_state.up { 1.0 } else { 0.0 };
-            let down = if input_state.down { -1.0 } else { 0.0 };
-
-            let right = if input_state.right { 1.0 } else { 0.0 };
-            let left = if input_state.left { -1.0 } else { 0.0 };
+            let (x, y) = input_state.movement();
 
             for (velocity, _) in (&mut velocity, &controlled).join() {
-                velocity.x = right + left;
-                velocity.y = up + down;
+                velocity.x = x;
+                velocity.y = y;
             }
         }
     }
 
     /// Integrates position using velocity and delta time
+    /// Entities with a collider are stopped by solid tiles and each other, sliding along walls.
     pub struct PhysicsSystem;
     impl<'a> System<'a> for PhysicsSystem {
         type SystemData = (
+            Entities<'a>,
             WriteStorage<'a, Position>,
             ReadStorage<'a, Velocity>,
+            ReadStorage<'a, Collider>,
+            ReadStorage<'a, Size>,
+            Option<Read<'a, TileMap>>,
             Read<'a, DeltaTime>
         );
 
-        fn run(&mut self, (mut position, velocity, delta_time): Self::SystemData) {
+        fn run(
+            &mut self,
+            (entities, mut position, velocity, collider, size, map, delta_time): Self::SystemData
+        ) {
             let delta = delta_time.delta.as_secs_f32();
+            let map = map.as_deref();
+
+            // Collision boxes of every collider, updated as entities move
+            let mut boxes: Vec<(Entity, Aabb)> = (&entities, &position, &collider, size.maybe())
+                .join()
+                .map(|(entity, position, collider, size)| (entity, collider.aabb(position, size)))
+                .collect();
+
+            for (entity, position, velocity, collider) in
+                (&entities, &mut position, &velocity, collider.maybe()).join()
+            {
+                let mut dx = velocity.x * delta;
+                let mut dy = velocity.y * delta;
+
+                if collider.is_some() {
+                    let index = boxes.iter().position(|(e, _)| *e == entity).unwrap();
+                    let obstacles: Vec<Aabb> = boxes
+                        .iter()
+                        .filter(|(e, _)| *e != entity)
+                        .map(|(_, aabb)| *aabb)
+                        .collect();
+
+                    let resolved = collision::resolve(&boxes[index].1, (dx, dy), map, &obstacles);
+                    dx = resolved.0;
+                    dy = resolved.1;
+
+                    boxes[index].1 = boxes[index].1.translate(dx, dy);
+                }
 
-            for (position, velocity) in (&mut position, &velocity).join() {
-                position.x += velocity.x * delta;
-                position.y += velocity.y * delta;
+                position.x += dx;
+                position.y += dy;
                 position.z += velocity.z * delta;
             }
         }
     }
+
+    /// Steers entities with a brain towards the next cell they want to step into (real time only)
+    /// A new step is chosen once the last one is reached or someone else stands in it.
+    pub struct AiSystem;
+    impl<'a> System<'a> for AiSystem {
+        type SystemData = (
+            Entities<'a>,
+            WriteStorage<'a, Brain>,
+            WriteStorage<'a, Velocity>,
+            ReadStorage<'a, Position>,
+            ReadStorage<'a, Collider>,
+            ReadStorage<'a, Controlled>,
+            ReadStorage<'a, Health>,
+            Option<Read<'a, TileMap>>,
+            Write<'a, Rng>
+        );
+
+        fn run(
+            &mut self,
+            (
+                entities,
+                mut brain,
+                mut velocity,
+                position,
+                collider,
+                controlled,
+                health,
+                map,
+                mut rng
+            ): Self::SystemData
+        ) {
+            let map = match map {
+                Some(map) => map,
+                None => return
+            };
+
+            let player = (&controlled, &position)
+                .join()
+                .next()
+                .map(|(_, position)| map.world_to_tile(position.x, position.y));
+            let chasers = (&brain)
+                .join()
+                .filter(|brain| brain.behaviour == Behaviour::Chase)
+                .count();
+            let mut navigator = ai::Navigator::new(ai::Grid::from_map(&map), player, chasers);
+
+            let cells: Vec<(Entity, ai::Cell)> = (&entities, &position, &collider)
+                .join()
+                .map(|(entity, position, _)| (entity, map.world_to_tile(position.x, position.y)))
+                .collect();
+
+            for (entity, brain, velocity, position) in
+                (&entities, &mut brain, &mut velocity, &position).join()
+            {
+                let occupied: Vec<ai::Cell> = cells
+                    .iter()
+                    .filter(|(other, _)| *other != entity)
+                    .map(|(_, cell)| *cell)
+                    .collect();
+
+                // Keep walking to the chosen cell until it is reached or taken
+                let arrived = brain.target.is_none_or(|(column, row)| {
+                    let (x, y) = map.tile_to_world(column, row);
+                    (x - position.x).abs() < map.tile_size * 0.1
+                        && (y - position.y).abs() < map.tile_size * 0.1
+                });
+                let taken = brain
+                    .target
+                    .is_some_and(|target| occupied.contains(&target));
+                if arrived || taken {
+                    let at = map.world_to_tile(position.x, position.y);
+                    brain.target = ai::think(
+                        brain,
+                        at,
+                        health.get(entity).map(Health::fraction),
+                        &mut navigator,
+                        &occupied,
+                        &mut rng
+                    );
+                }
+
+                let (dx, dy) = match brain.target {
+                    Some((column, row)) => {
+                        let (x, y) = map.tile_to_world(column, row);
+                        (x - position.x, y - position.y)
+                    },
+                    None => (0.0, 0.0)
+                };
+                let length = (dx * dx + dy * dy).sqrt();
+                if length > 0.0 {
+                    velocity.x = dx / length * brain.speed;
+                    velocity.y = dy / length * brain.speed;
+                } else {
+                    velocity.x = 0.0;
+                    velocity.y = 0.0;
+                }
+            }
+        }
+    }
+
+    /// Gives actors turns in order of their speed, waiting whenever it is the player's turn
+    /// Actors move a whole tile per action, attacking enemies they move into. Monsters with a
+    /// brain choose their own steps while others wander at random.
+    pub struct TurnSystem;
+    impl<'a> System<'a> for TurnSystem {
+        type SystemData = (
+            Entities<'a>,
+            ReadStorage<'a, Actor>,
+            ReadStorage<'a, Controlled>,
+            ReadStorage<'a, Collider>,
+            WriteStorage<'a, Position>,
+            Option<Read<'a, TileMap>>,
+            Write<'a, Scheduler<Entity>>,
+            Write<'a, PendingAction>,
+            Read<'a, InputState>,
+            Write<'a, Rng>,
+            Write<'a, SoundQueue>,
+            ReadStorage<'a, Hostile>,
+            Write<'a, MeleeAttacks>,
+            WriteStorage<'a, Brain>,
+            ReadStorage<'a, Health>
+        );
+
+        fn run(
+            &mut self,
+            (
+                entities,
+                actor,
+                controlled,
+                collider,
+                mut position,
+                map,
+                mut scheduler,
+                mut pending,
+                input_state,
+                mut rng,
+                mut sounds,
+                hostile,
+                mut attacks,
+                mut brain,
+                health
+            ): Self::SystemData
+        ) {
+            let map = match map {
+                Some(map) => map,
+                None => return
+            };
+
+            // Made when the first brain thinks
+            let mut navigator: Option<ai::Navigator> = None;
+
+            // Actions pressed this frame choose the player's next turn
+            if let Some(action) = turn::action_from_input(&input_state) {
+                pending.0 = Some(action);
+            }
+
+            // Keep the schedule in step with the actors in the world
+            scheduler.retain(|entity| entities.is_alive(entity) && actor.contains(entity));
+            for (entity, actor) in (&entities, &actor).join() {
+                scheduler.add(entity, actor.speed);
+            }
+
+            // Limit the turns taken in one frame in case the player is missing
+            let limit = 16 * (&entities, &actor).join().count();
+
+            for _ in 0..limit {
+                let entity = match scheduler.next_actor() {
+                    Some(entity) => entity,
+                    None => break
+                };
+
+                let action = if controlled.contains(entity) {
+                    match pending.0.take() {
+                        Some(action) => action,
+                        None => break
+                    }
+                } else if brain.contains(entity) {
+                    let at = match position.get(entity) {
+                        Some(position) => map.world_to_tile(position.x, position.y),
+                        None => continue
+                    };
+
+                    // The player may have moved since the last brain thought
+                    let player = (&controlled, &position)
+                        .join()
+                        .next()
+                        .map(|(_, position)| map.world_to_tile(position.x, position.y));
+                    let navigator = navigator.get_or_insert_with(|| {
+                        let chasers = (&brain)
+                            .join()
+                            .filter(|brain| brain.behaviour == Behaviour::Chase)
+                            .count();
+                        ai::Navigator::new(ai::Grid::from_map(&map), player, chasers)
+                    });
+                    navigator.set_player(player);
+
+                    let occupied: Vec<ai::Cell> = (&entities, &position, &collider)
+                        .join()
+                        .filter(|(other, _, _)| *other != entity)
+                        .map(|(_, position, _)| map.world_to_tile(position.x, position.y))
+                        .collect();
+
+                    let step = ai::think(
+                        brain.get_mut(entity).unwrap(),
+                        at,
+                        health.get(entity).map(Health::fraction),
+                        navigator,
+                        &occupied,
+                        &mut rng
+                    );
+                    match step {
+                        Some(to) => Action::Move(to.0 - at.0, at.1 - to.1),
+                        None => Action::Wait
+                    }
+                } else {
+                    match rng.range(0, 5) {
+                        0 => Action::Move(1, 0),
+                        1 => Action::Move(-1, 0),
+                        2 => Action::Move(0, 1),
+                        3 => Action::Move(0, -1),
+                        _ => Action::Wait
+                    }
+                };
+
+                let mut attacked = false;
+                let taken = match action {
+                    Action::Move(dx, dy) => {
+                        let from = match position.get(entity) {
+                            Some(position) => map.world_to_tile(position.x, position.y),
+                            None => continue
+                        };
+
+                        let occupants: Vec<(Entity, (i32, i32))> =
+                            (&entities, &position, &collider)
+                                .join()
+                                .filter(|(other, _, _)| *other != entity)
+                                .map(|(other, position, _)| {
+                                    (other, map.world_to_tile(position.x, position.y))
+                                })
+                                .collect();
+
+                        // Moving into an enemy attacks it
+                        let to = turn::target(from, (dx, dy));
+                        let enemy = occupants.iter().find(|(other, cell)| {
+                            *cell == to && are_enemies(&controlled, &hostile, entity, *other)
+                        });
+                        if let Some((enemy, _)) = enemy {
+                            attacks.0.push((entity, *enemy));
+                            attacked = true;
+                            true
+                        } else {
+                            let occupied: Vec<(i32, i32)> =
+                                occupants.iter().map(|(_, cell)| *cell).collect();
+
+                            match turn::step(&map, from, (dx, dy), &occupied) {
+                                Some((column, row)) => {
+                                    let (x, y) = map.tile_to_world(column, row);
+                                    let position = position.get_mut(entity).unwrap();
+                                    position.x = x;
+                                    position.y = y;
+
+                                    if controlled.contains(entity) {
+                                        sounds.push(SoundEvent::Footstep);
+                                    }
+                                    true
+                                },
+                                None => false
+                            }
+                        }
+                    },
+                    Action::Wait => true
+                };
+
+                // Walking into a wall does not use up the player's turn
+                if taken || !controlled.contains(entity) {
+                    scheduler.spend(entity, turn::ACTION_COST);
+                }
+
+                // Resolve the attack before anyone else acts, so the dead take no more turns
+                if attacked {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Whether two entities fight each other, the controlled entity and hostiles being enemies
+    fn are_enemies(
+        controlled: &ReadStorage<Controlled>,
+        hostile: &ReadStorage<Hostile>,
+        a: Entity,
+        b: Entity
+    ) -> bool {
+        (controlled.contains(a) && hostile.contains(b))
+            || (hostile.contains(a) && controlled.contains(b))
+    }
+
+    /// Resolves melee attacks between the controlled entity and hostiles
+    /// In real time an entity attacks by moving into an enemy within reach, while turn based
+    /// attacks are queued by the TurnSystem. Hit entities play their hurt clip and dead ones are
+    /// removed or left as corpses.
+    pub struct CombatSystem;
+    impl<'a> System<'a> for CombatSystem {
+        type SystemData = (
+            Entities<'a>,
+            ReadStorage<'a, Position>,
+            ReadStorage<'a, Velocity>,
+            ReadStorage<'a, Collider>,
+            ReadStorage<'a, Size>,
+            ReadStorage<'a, Controlled>,
+            ReadStorage<'a, Hostile>,
+            ReadStorage<'a, Defense>,
+            WriteStorage<'a, AttackStats>,
+            WriteStorage<'a, Health>,
+            WriteStorage<'a, Animate>,
+            Write<'a, MeleeAttacks>,
+            Write<'a, Rng>,
+            Write<'a, EventChannel<CombatEvent>>,
+            Write<'a, SoundQueue>,
+            Read<'a, DeltaTime>,
+            Read<'a, GameMode>,
+            Read<'a, LazyUpdate>
+        );
+
+        fn run(
+            &mut self,
+            (
+                entities,
+                position,
+                velocity,
+                collider,
+                size,
+                controlled,
+                hostile,
+                defense,
+                mut attack_stats,
+                mut health,
+                mut animate,
+                mut melee_attacks,
+                mut rng,
+                mut events,
+                mut sounds,
+                delta_time,
+                mode,
+                lazy
+            ): Self::SystemData
+        ) {
+            let mut attacks = std::mem::take(&mut melee_attacks.0);
+
+            if *mode == GameMode::RealTime {
+                // Attackers recover between hits
+                for stats in (&mut attack_stats).join() {
+                    stats.recovering = stats
+                        .recovering
+                        .checked_sub(delta_time.delta)
+                        .unwrap_or_default();
+                }
+
+                for (attacker, stats, attacker_position, velocity, attacker_collider) in
+                    (&entities, &attack_stats, &position, &velocity, &collider).join()
+                {
+                    if stats.recovering > std::time::Duration::new(0, 0) {
+                        continue;
+                    }
+
+                    let aabb = attacker_collider.aabb(attacker_position, size.get(attacker));
+                    let target = (&entities, &position, &collider, &health)
+                        .join()
+                        .filter(|(target, _, _, _)| {
+                            are_enemies(&controlled, &hostile, attacker, *target)
+                        })
+                        .find(|(target, target_position, target_collider, _)| {
+                            combat::bumps(
+                                &aabb,
+                                (velocity.x, velocity.y),
+                                &target_collider.aabb(target_position, size.get(*target)),
+                                combat::REACH
+                            )
+                        })
+                        .map(|(target, _, _, _)| target);
+
+                    if let Some(target) = target {
+                        attacks.push((attacker, target));
+                    }
+                }
+            }
+
+            for (attacker, target) in attacks {
+                // Either side may have died earlier in the step
+                if health.get(attacker).is_some_and(|health| health.is_dead()) {
+                    continue;
+                }
+                let stats = match attack_stats.get_mut(attacker) {
+                    Some(stats) => stats,
+                    None => continue
+                };
+                let target_health = match health.get_mut(target) {
+                    Some(health) if !health.is_dead() => health,
+                    _ => continue
+                };
+
+                stats.recovering = stats.cooldown;
+                let hit = combat::roll_hit(
+                    stats.power,
+                    stats.crit_chance,
+                    stats.crit_multiplier,
+                    defense.get(target).map_or(0, |defense| defense.armour),
+                    &mut rng
+                );
+                let died = target_health.take(hit.amount);
+                let corpse = target_health.corpse;
+
+                events.single_write(CombatEvent::Damaged {
+                    attacker,
+                    target,
+                    amount: hit.amount,
+                    critical: hit.critical
+                });
+                sounds.push(SoundEvent::Hit);
+
+                if !died {
+                    if let Some(animate) = animate.get_mut(target) {
+                        animate.animator.restart("hurt");
+                    }
+                    continue;
+                }
+
+                events.single_write(CombatEvent::Died {
+                    entity: target,
+                    killer: attacker
+                });
+
+                if !corpse {
+                    if let Err(message) = entities.delete(target) {
+                        eprintln!("Failed to remove a dead entity: {}", message);
+                    }
+                    continue;
+                }
+
+                // Corpses stay where they fell but no longer act, block or fight
+                lazy.remove::<Health>(target);
+                lazy.remove::<AttackStats>(target);
+                lazy.remove::<Defense>(target);
+                lazy.remove::<Hostile>(target);
+                lazy.remove::<Controlled>(target);
+                lazy.remove::<Actor>(target);
+                lazy.remove::<Brain>(target);
+                lazy.remove::<Collider>(target);
+                lazy.remove::<Velocity>(target);
+                lazy.insert(target, Corpse);
+
+                // Without a death clip the corpse keeps the frame it died on
+                match animate.get_mut(target) {
+                    Some(animate) if animate.animator.has_clip("dead") => {
+                        animate.animator.restart("dead");
+                    },
+                    Some(_) => lazy.remove::<Animate>(target),
+                    None => {}
+                }
+            }
+        }
+    }
 }
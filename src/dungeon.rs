@@ -0,0 +1,691 @@
+use crate::atlas::Atlas;
+use crate::map::{MapError, TileMap};
+use crate::rng::Rng;
+
+use std::collections::VecDeque;
+
+/// The algorithm used to lay out a level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    /// Randomly placed rooms joined in order by corridors
+    RoomsAndCorridors,
+    /// Space split recursively into partitions, each holding a room
+    Bsp,
+    /// Organic caves grown with a cellular automaton
+    Caves
+}
+
+/// Parameters for generating a level
+#[derive(Debug, Clone)]
+pub struct DungeonConfig {
+    pub width: u32,
+    pub height: u32,
+    pub algorithm: Algorithm,
+    /// Smallest width and height of a room, including for BSP leaves
+    pub room_min: u32,
+    /// Largest width and height of a room
+    pub room_max: u32,
+    /// Number of attempts at placing a room for rooms and corridors
+    pub max_rooms: u32,
+    pub monsters: u32,
+    pub chests: u32
+}
+
+impl Default for DungeonConfig {
+    fn default() -> DungeonConfig {
+        DungeonConfig {
+            width: 48,
+            height: 32,
+            algorithm: Algorithm::RoomsAndCorridors,
+            room_min: 4,
+            room_max: 9,
+            max_rooms: 30,
+            monsters: 6,
+            chests: 3
+        }
+    }
+}
+
+/// The contents of a single cell
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cell {
+    Wall,
+    Floor,
+    Door,
+    StairsUp,
+    StairsDown
+}
+
+impl Cell {
+    /// Name of the tileset frame used to draw the cell
+    pub fn tile_name(self) -> &'static str {
+        match self {
+            Cell::Wall => "wall",
+            Cell::Floor => "floor",
+            Cell::Door => "door",
+            Cell::StairsUp => "stairs_up",
+            Cell::StairsDown => "stairs_down"
+        }
+    }
+
+    /// If entities can move through the cell
+    pub fn is_walkable(self) -> bool {
+        self != Cell::Wall
+    }
+}
+
+/// A rectangular room, in cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Room {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32
+}
+
+impl Room {
+    pub fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    /// Returns true if the rooms overlap or are closer than a gap of cells
+    fn intersects(&self, other: &Room, gap: i32) -> bool {
+        self.x - gap < other.x + other.width
+            && self.x + self.width + gap > other.x
+            && self.y - gap < other.y + other.height
+            && self.y + self.height + gap > other.y
+    }
+}
+
+/// What to create at a spawn point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpawnKind {
+    Player,
+    Monster,
+    Chest
+}
+
+/// A cell where an entity should be created
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spawn {
+    pub kind: SpawnKind,
+    pub column: i32,
+    pub row: i32
+}
+
+/// A generated level
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dungeon {
+    pub width: u32,
+    pub height: u32,
+    /// Cells stored row by row from the top of the level
+    pub cells: Vec<Cell>,
+    pub rooms: Vec<Room>,
+    pub spawns: Vec<Spawn>
+}
+
+impl Dungeon {
+    /// Creates a level filled with walls
+    fn new(width: u32, height: u32) -> Dungeon {
+        Dungeon {
+            width,
+            height,
+            cells: vec![Cell::Wall; (width * height) as usize],
+            rooms: vec![],
+            spawns: vec![]
+        }
+    }
+
+    /// Returns the contents of a cell, cells outside the level are walls
+    pub fn get(&self, column: i32, row: i32) -> Cell {
+        if self.contains(column, row) {
+            self.cells[(row as u32 * self.width + column as u32) as usize]
+        } else {
+            Cell::Wall
+        }
+    }
+
+    fn set(&mut self, column: i32, row: i32, cell: Cell) {
+        if self.contains(column, row) {
+            self.cells[(row as u32 * self.width + column as u32) as usize] = cell;
+        }
+    }
+
+    fn contains(&self, column: i32, row: i32) -> bool {
+        column >= 0 && row >= 0 && column < self.width as i32 && row < self.height as i32
+    }
+
+    /// Returns the first spawn point of a kind
+    pub fn spawn(&self, kind: SpawnKind) -> Option<&Spawn> {
+        self.spawns.iter().find(|spawn| spawn.kind == kind)
+    }
+
+    /// Builds a tile map of the level
+    /// The tileset must have frames named after each `Cell::tile_name`, walls are made solid.
+    /// # Arguments
+    /// * `tileset` - The sprite sheet tiles are drawn from
+    /// * `tile_size` - Size of a tile in world coordinates
+    pub fn to_tile_map(&self, tileset: Atlas, tile_size: f32) -> Result<TileMap, MapError> {
+        let mut map = TileMap::new(self.width, self.height, tile_size, tileset);
+
+        for row in 0..self.height as i32 {
+            for column in 0..self.width as i32 {
+                let tile = map.tile_id(self.get(column, row).tile_name())?;
+                map.set_tile(0, column, row, tile);
+            }
+        }
+
+        let wall = map.tile_id(Cell::Wall.tile_name())?;
+        map.solid[wall as usize] = true;
+
+        Ok(map)
+    }
+
+    fn carve_room(&mut self, room: &Room) {
+        for row in room.y..room.y + room.height {
+            for column in room.x..room.x + room.width {
+                self.set(column, row, Cell::Floor);
+            }
+        }
+    }
+
+    /// Carves an L shaped corridor between two cells
+    fn carve_corridor(&mut self, from: (i32, i32), to: (i32, i32), horizontal_first: bool) {
+        let corner = if horizontal_first {
+            (to.0, from.1)
+        } else {
+            (from.0, to.1)
+        };
+
+        for (start, end) in [(from, corner), (corner, to)].iter() {
+            for column in start.0.min(end.0)..=start.0.max(end.0) {
+                for row in start.1.min(end.1)..=start.1.max(end.1) {
+                    self.set(column, row, Cell::Floor);
+                }
+            }
+        }
+    }
+
+    /// Places doors where corridors enter rooms
+    fn place_doors(&mut self) {
+        for room in self.rooms.clone() {
+            let mut ring = Vec::new();
+            for column in room.x..room.x + room.width {
+                ring.push((column, room.y - 1));
+                ring.push((column, room.y + room.height));
+            }
+            for row in room.y..room.y + room.height {
+                ring.push((room.x - 1, row));
+                ring.push((room.x + room.width, row));
+            }
+
+            for (column, row) in ring {
+                if self.get(column, row) != Cell::Floor {
+                    continue;
+                }
+
+                // Only narrow openings get a door
+                let walls_horizontal = self.get(column - 1, row) == Cell::Wall
+                    && self.get(column + 1, row) == Cell::Wall;
+                let walls_vertical = self.get(column, row - 1) == Cell::Wall
+                    && self.get(column, row + 1) == Cell::Wall;
+
+                // Avoid doubled doors where openings pass through thick walls
+                let next_to_door = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                    .iter()
+                    .any(|(dx, dy)| self.get(column + dx, row + dy) == Cell::Door);
+
+                if (walls_horizontal || walls_vertical) && !next_to_door {
+                    self.set(column, row, Cell::Door);
+                }
+            }
+        }
+    }
+
+    /// Returns the walking distance from a cell to every other cell, `None` if unreachable
+    pub fn distances(&self, start: (i32, i32)) -> Vec<Option<u32>> {
+        let mut distances = vec![None; self.cells.len()];
+        let mut queue = VecDeque::new();
+
+        if self.get(start.0, start.1).is_walkable() {
+            distances[(start.1 as u32 * self.width + start.0 as u32) as usize] = Some(0);
+            queue.push_back(start);
+        }
+
+        while let Some((column, row)) = queue.pop_front() {
+            let distance = distances[(row as u32 * self.width + column as u32) as usize].unwrap();
+
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+                let (next_column, next_row) = (column + dx, row + dy);
+                if !self.get(next_column, next_row).is_walkable() {
+                    continue;
+                }
+
+                let index = (next_row as u32 * self.width + next_column as u32) as usize;
+                if distances[index].is_none() {
+                    distances[index] = Some(distance + 1);
+                    queue.push_back((next_column, next_row));
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// Places the stairs, player, chests and monsters
+    fn populate(&mut self, config: &DungeonConfig, rng: &mut Rng) {
+        // Start in the first room, or anywhere for caves
+        let start = match self.rooms.first() {
+            Some(room) => room.center(),
+            None => match self.random_floor(rng, |_, _| true) {
+                Some(start) => start,
+                None => return
+            }
+        };
+
+        self.set(start.0, start.1, Cell::StairsUp);
+        self.spawns.push(Spawn {
+            kind: SpawnKind::Player,
+            column: start.0,
+            row: start.1
+        });
+
+        // The way down is as far from the start as possible
+        let distances = self.distances(start);
+        let farthest = distances
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.cells[*index] == Cell::Floor)
+            .max_by_key(|(index, distance)| (distance.unwrap_or(0), std::cmp::Reverse(*index)))
+            .map(|(index, _)| index as u32);
+        if let Some(index) = farthest {
+            self.cells[index as usize] = Cell::StairsDown;
+        }
+
+        // Chests go in rooms other than the first when there are any
+        for _ in 0..config.chests {
+            let rooms = self.rooms.clone();
+            let cell = if rooms.len() > 1 {
+                let room = rooms[rng.range(1, rooms.len() as i32) as usize];
+                self.random_floor(rng, |column, row| {
+                    column >= room.x
+                        && column < room.x + room.width
+                        && row >= room.y
+                        && row < room.y + room.height
+                })
+            } else {
+                self.random_floor(rng, |_, _| true)
+            };
+
+            if let Some((column, row)) = cell {
+                self.spawns.push(Spawn {
+                    kind: SpawnKind::Chest,
+                    column,
+                    row
+                });
+            }
+        }
+
+        // Monsters keep their distance from the start
+        let width = self.width;
+        for _ in 0..config.monsters {
+            let cell = self.random_floor(rng, |column, row| {
+                let index = (row as u32 * width + column as u32) as usize;
+                matches!(distances[index], Some(distance) if distance > 4)
+            });
+
+            if let Some((column, row)) = cell {
+                self.spawns.push(Spawn {
+                    kind: SpawnKind::Monster,
+                    column,
+                    row
+                });
+            }
+        }
+    }
+
+    /// Picks a random unoccupied floor cell accepted by a filter
+    fn random_floor<F>(&self, rng: &mut Rng, filter: F) -> Option<(i32, i32)>
+    where
+        F: Fn(i32, i32) -> bool
+    {
+        let candidates: Vec<(i32, i32)> = (0..self.height as i32)
+            .flat_map(|row| (0..self.width as i32).map(move |column| (column, row)))
+            .filter(|(column, row)| self.get(*column, *row) == Cell::Floor)
+            .filter(|(column, row)| {
+                !self
+                    .spawns
+                    .iter()
+                    .any(|spawn| spawn.column == *column && spawn.row == *row)
+            })
+            .filter(|(column, row)| filter(*column, *row))
+            .collect();
+
+        if candidates.is_empty() {
+            None
+        } else {
+            Some(candidates[rng.range(0, candidates.len() as i32) as usize])
+        }
+    }
+}
+
+/// Generates a level, the same seed and config always give the same level
+/// # Arguments
+/// * `config` - Parameters of the level
+/// * `seed` - Seed for the random number generator
+pub fn generate(config: &DungeonConfig, seed: u64) -> Dungeon {
+    let mut rng = Rng::new(seed);
+    let mut dungeon = Dungeon::new(config.width, config.height);
+
+    match config.algorithm {
+        Algorithm::RoomsAndCorridors => rooms_and_corridors(&mut dungeon, config, &mut rng),
+        Algorithm::Bsp => bsp(&mut dungeon, config, &mut rng),
+        Algorithm::Caves => caves(&mut dungeon, &mut rng)
+    }
+
+    if config.algorithm != Algorithm::Caves {
+        dungeon.place_doors();
+    }
+    dungeon.populate(config, &mut rng);
+
+    dungeon
+}
+
+/// Places non-overlapping rooms and joins each to the one before it
+fn rooms_and_corridors(dungeon: &mut Dungeon, config: &DungeonConfig, rng: &mut Rng) {
+    let (min, max) = (config.room_min as i32, config.room_max as i32 + 1);
+
+    for _ in 0..config.max_rooms {
+        let width = rng.range(min, max);
+        let height = rng.range(min, max);
+        let room = Room {
+            x: rng.range(1, dungeon.width as i32 - width - 1),
+            y: rng.range(1, dungeon.height as i32 - height - 1),
+            width,
+            height
+        };
+
+        if room.x + room.width >= dungeon.width as i32
+            || room.y + room.height >= dungeon.height as i32
+        {
+            continue;
+        }
+        if dungeon.rooms.iter().any(|other| room.intersects(other, 1)) {
+            continue;
+        }
+
+        dungeon.carve_room(&room);
+        if let Some(previous) = dungeon.rooms.last() {
+            let horizontal_first = rng.chance(0.5);
+            dungeon.carve_corridor(previous.center(), room.center(), horizontal_first);
+        }
+        dungeon.rooms.push(room);
+    }
+}
+
+/// Recursively splits the level, places a room in each leaf and joins sibling partitions
+fn bsp(dungeon: &mut Dungeon, config: &DungeonConfig, rng: &mut Rng) {
+    let area = Room {
+        x: 1,
+        y: 1,
+        width: dungeon.width as i32 - 2,
+        height: dungeon.height as i32 - 2
+    };
+
+    split(dungeon, area, config, rng);
+}
+
+/// Fills a partition with rooms and returns the room used to connect it to its sibling
+fn split(dungeon: &mut Dungeon, area: Room, config: &DungeonConfig, rng: &mut Rng) -> Option<Room> {
+    // Each half must be able to hold the smallest room with a wall around it
+    let min = config.room_min as i32 + 2;
+    let can_split_x = area.width >= min * 2;
+    let can_split_y = area.height >= min * 2;
+    let too_big =
+        area.width > config.room_max as i32 + 2 || area.height > config.room_max as i32 + 2;
+
+    if (can_split_x || can_split_y) && (too_big || rng.chance(0.75)) {
+        // Split across the longer side
+        let vertical = match (can_split_x, can_split_y) {
+            (true, true) => {
+                area.width > area.height || (area.width == area.height && rng.chance(0.5))
+            },
+            (vertical, _) => vertical
+        };
+
+        let (first, second) = if vertical {
+            let at = rng.range(min, area.width - min + 1);
+            (
+                Room { width: at, ..area },
+                Room {
+                    x: area.x + at,
+                    width: area.width - at,
+                    ..area
+                }
+            )
+        } else {
+            let at = rng.range(min, area.height - min + 1);
+            (
+                Room { height: at, ..area },
+                Room {
+                    y: area.y + at,
+                    height: area.height - at,
+                    ..area
+                }
+            )
+        };
+
+        let first = split(dungeon, first, config, rng);
+        let second = split(dungeon, second, config, rng);
+
+        return match (first, second) {
+            (Some(first), Some(second)) => {
+                let horizontal_first = rng.chance(0.5);
+                dungeon.carve_corridor(first.center(), second.center(), horizontal_first);
+                Some(if rng.chance(0.5) { first } else { second })
+            },
+            (first, second) => first.or(second)
+        };
+    }
+
+    // Leaf partitions hold a single room with a wall around it
+    let max_width = (area.width - 2).min(config.room_max as i32);
+    let max_height = (area.height - 2).min(config.room_max as i32);
+    if max_width < config.room_min as i32 || max_height < config.room_min as i32 {
+        return None;
+    }
+
+    let width = rng.range(config.room_min as i32, max_width + 1);
+    let height = rng.range(config.room_min as i32, max_height + 1);
+    let room = Room {
+        x: area.x + rng.range(1, area.width - width),
+        y: area.y + rng.range(1, area.height - height),
+        width,
+        height
+    };
+
+    dungeon.carve_room(&room);
+    dungeon.rooms.push(room);
+    Some(room)
+}
+
+/// Grows caves from random noise and keeps only the largest connected cave
+fn caves(dungeon: &mut Dungeon, rng: &mut Rng) {
+    let (width, height) = (dungeon.width as i32, dungeon.height as i32);
+
+    for row in 1..height - 1 {
+        for column in 1..width - 1 {
+            if !rng.chance(0.45) {
+                dungeon.set(column, row, Cell::Floor);
+            }
+        }
+    }
+
+    // Cells become walls when surrounded by walls and floor when surrounded by floor
+    for _ in 0..5 {
+        let previous = dungeon.clone();
+        for row in 1..height - 1 {
+            for column in 1..width - 1 {
+                let mut walls = 0;
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        if previous.get(column + dx, row + dy) == Cell::Wall {
+                            walls += 1;
+                        }
+                    }
+                }
+
+                let cell = if walls >= 5 { Cell::Wall } else { Cell::Floor };
+                dungeon.set(column, row, cell);
+            }
+        }
+    }
+
+    // Fill in every cave except the largest
+    let mut largest: Option<Vec<Option<u32>>> = None;
+    let mut largest_size = 0;
+    let mut visited = vec![false; dungeon.cells.len()];
+    for index in 0..dungeon.cells.len() {
+        if visited[index] || dungeon.cells[index] != Cell::Floor {
+            continue;
+        }
+
+        let start = (
+            (index as u32 % dungeon.width) as i32,
+            (index as u32 / dungeon.width) as i32
+        );
+        let region = dungeon.distances(start);
+        let size = region.iter().filter(|distance| distance.is_some()).count();
+        for (visited, distance) in visited.iter_mut().zip(region.iter()) {
+            *visited |= distance.is_some();
+        }
+
+        if size > largest_size {
+            largest_size = size;
+            largest = Some(region);
+        }
+    }
+
+    if let Some(region) = largest {
+        for (cell, distance) in dungeon.cells.iter_mut().zip(region.iter()) {
+            if distance.is_none() {
+                *cell = Cell::Wall;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: [u64; 4] = [0, 1, 42, 0xdead_beef];
+
+    fn config(algorithm: Algorithm) -> DungeonConfig {
+        DungeonConfig {
+            algorithm,
+            ..DungeonConfig::default()
+        }
+    }
+
+    fn tile_map(dungeon: &Dungeon) -> TileMap {
+        let tileset = Atlas::load("./src/tiles/dungeon.toml").unwrap();
+        dungeon.to_tile_map(tileset, 0.1).unwrap()
+    }
+
+    fn find(dungeon: &Dungeon, cell: Cell) -> Option<(i32, i32)> {
+        dungeon.cells.iter().position(|c| *c == cell).map(|index| {
+            (
+                (index as u32 % dungeon.width) as i32,
+                (index as u32 / dungeon.width) as i32
+            )
+        })
+    }
+
+    fn assert_repeatable(algorithm: Algorithm) {
+        let config = config(algorithm);
+        for seed in SEEDS.iter() {
+            let first = generate(&config, *seed);
+            let second = generate(&config, *seed);
+
+            assert_eq!(tile_map(&first), tile_map(&second), "seed {}", seed);
+            assert_eq!(first.spawns, second.spawns, "seed {}", seed);
+        }
+    }
+
+    fn assert_stairs_connected(algorithm: Algorithm) {
+        let config = config(algorithm);
+        for seed in SEEDS.iter() {
+            let dungeon = generate(&config, *seed);
+            let up = find(&dungeon, Cell::StairsUp).expect("No stairs up");
+            let down = find(&dungeon, Cell::StairsDown).expect("No stairs down");
+
+            let distances = dungeon.distances(up);
+            let index = (down.1 as u32 * dungeon.width + down.0 as u32) as usize;
+            assert!(distances[index].is_some(), "seed {}", seed);
+
+            // Everything placed in the level can be walked to from the start
+            let player = dungeon.spawn(SpawnKind::Player).unwrap();
+            assert_eq!((player.column, player.row), up);
+            for spawn in &dungeon.spawns {
+                let index = (spawn.row as u32 * dungeon.width + spawn.column as u32) as usize;
+                assert!(distances[index].is_some(), "seed {}: {:?}", seed, spawn);
+            }
+        }
+    }
+
+    #[test]
+    fn rooms_and_corridors_are_repeatable() {
+        assert_repeatable(Algorithm::RoomsAndCorridors);
+    }
+
+    #[test]
+    fn bsp_is_repeatable() {
+        assert_repeatable(Algorithm::Bsp);
+    }
+
+    #[test]
+    fn caves_are_repeatable() {
+        assert_repeatable(Algorithm::Caves);
+    }
+
+    #[test]
+    fn different_seeds_give_different_levels() {
+        let config = config(Algorithm::RoomsAndCorridors);
+        assert_ne!(generate(&config, 1).cells, generate(&config, 2).cells);
+    }
+
+    #[test]
+    fn rooms_and_corridors_stairs_are_reachable() {
+        assert_stairs_connected(Algorithm::RoomsAndCorridors);
+    }
+
+    #[test]
+    fn bsp_stairs_are_reachable() {
+        assert_stairs_connected(Algorithm::Bsp);
+    }
+
+    #[test]
+    fn caves_stairs_are_reachable() {
+        assert_stairs_connected(Algorithm::Caves);
+    }
+
+    #[test]
+    fn edges_of_the_level_are_walls() {
+        for algorithm in [
+            Algorithm::RoomsAndCorridors,
+            Algorithm::Bsp,
+            Algorithm::Caves
+        ] {
+            let dungeon = generate(&config(algorithm), 7);
+            for column in 0..dungeon.width as i32 {
+                assert_eq!(dungeon.get(column, 0), Cell::Wall);
+                assert_eq!(dungeon.get(column, dungeon.height as i32 - 1), Cell::Wall);
+            }
+            for row in 0..dungeon.height as i32 {
+                assert_eq!(dungeon.get(0, row), Cell::Wall);
+                assert_eq!(dungeon.get(dungeon.width as i32 - 1, row), Cell::Wall);
+            }
+        }
+    }
+}
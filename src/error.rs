@@ -0,0 +1,131 @@
+use crate::assets::AssetError;
+use crate::audio::AudioError;
+use crate::preprocessor::PreprocessError;
+use crate::shader::UniformError;
+use crate::text::TextError;
+use std::fmt;
+
+/// Errors that stop the game, while it starts or while it runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// SDL, the window or the OpenGL context could not be set up
+    Sdl(String),
+    /// An OpenGL call failed, such as writing a uniform
+    Gl(String),
+    /// A shader failed to compile, with the compiler's log
+    ShaderCompile { path: String, log: String },
+    /// A shader program failed to link, with the linker's log
+    ShaderLink(String),
+    /// A file could not be read
+    Io { path: String, message: String },
+    /// An image could not be decoded
+    Image { path: String, message: String },
+    /// FreeType could not render a character of the font
+    Font(String),
+    /// A config file or setting is invalid
+    Config { path: String, message: String }
+}
+
+impl Error {
+    /// Creates a `Config` error from the error of loading a config file
+    /// # Arguments
+    /// * `path` - The config file, or the name of the setting
+    /// * `error` - What was wrong with it
+    pub fn config<E: fmt::Display>(path: &str, error: E) -> Error {
+        Error::Config {
+            path: String::from(path),
+            message: error.to_string()
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Sdl(message) => write!(f, "SDL error: {}", message),
+            Error::Gl(message) => write!(f, "OpenGL error: {}", message),
+            Error::ShaderCompile { path, log } => {
+                write!(
+                    f,
+                    "Shader '{}' failed to compile:\n{}",
+                    path,
+                    log.trim_end()
+                )
+            },
+            Error::ShaderLink(log) => {
+                write!(f, "Shader program failed to link:\n{}", log.trim_end())
+            },
+            Error::Io { path, message } => write!(f, "Failed to read '{}': {}", path, message),
+            Error::Image { path, message } => {
+                write!(f, "Image '{}' could not be read: {}", path, message)
+            },
+            Error::Font(message) => write!(f, "Font error: {}", message),
+            Error::Config { path, message } => write!(f, "Invalid config '{}': {}", path, message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<UniformError> for Error {
+    fn from(error: UniformError) -> Error {
+        Error::Gl(error.to_string())
+    }
+}
+
+impl From<PreprocessError> for Error {
+    fn from(error: PreprocessError) -> Error {
+        match error {
+            PreprocessError::Io { path, message } => Error::Io { path, message },
+            PreprocessError::Include { ref path, .. } => Error::ShaderCompile {
+                path: path.clone(),
+                log: error.to_string()
+            },
+            PreprocessError::Cycle(ref chain) => Error::ShaderCompile {
+                path: chain.first().cloned().unwrap_or_default(),
+                log: error.to_string()
+            }
+        }
+    }
+}
+
+impl From<AssetError> for Error {
+    fn from(error: AssetError) -> Error {
+        match error {
+            AssetError::NotFound(path) => Error::Io {
+                path,
+                message: String::from("no such file")
+            },
+            AssetError::Image { path, message } => Error::Image { path, message },
+            AssetError::Atlas { path, error } => Error::config(&path, error),
+            AssetError::Shader { path, message } => Error::ShaderCompile { path, log: message },
+            AssetError::Sound(error) => Error::from(error)
+        }
+    }
+}
+
+impl From<AudioError> for Error {
+    fn from(error: AudioError) -> Error {
+        match error {
+            AudioError::Io { path, message } | AudioError::Decode { path, message } => {
+                Error::Io { path, message }
+            },
+            AudioError::UnsupportedFormat(ref path) => Error::Io {
+                path: path.clone(),
+                message: error.to_string()
+            },
+            AudioError::Device(message) => Error::Sdl(message),
+            // The sound config's path is not part of these errors
+            AudioError::Parse(_) | AudioError::InvalidField { .. } => Error::config("audio", error)
+        }
+    }
+}
+
+impl From<TextError> for Error {
+    fn from(error: TextError) -> Error {
+        match error {
+            TextError::Font { path, message } => Error::Io { path, message },
+            TextError::Glyph { .. } => Error::Font(error.to_string())
+        }
+    }
+}
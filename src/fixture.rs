@@ -0,0 +1,54 @@
+use crate::assets::mock::MockBackend;
+use crate::assets::AssetManager;
+use crate::component_system::resources::LevelSeed;
+use crate::input::Bindings;
+use crate::prefab;
+use crate::rng::Rng;
+use crate::timestep::{ManualClock, Timestep};
+use crate::turn::GameMode;
+use specs::prelude::*;
+use std::time::Duration;
+
+/// Sprite sheet of the chest prefab
+pub const CHEST_IMAGE: &str = "./src/frames/chest_empty_open_anim_f0.png";
+
+/// Image of the dungeon tileset
+pub const TILESET_IMAGE: &str = "./src/tiles/dungeon.png";
+
+/// A world with nothing in it, set up like the game's and loading its sprite sheets from memory
+/// Time only moves when the world's `ManualClock` is advanced.
+pub fn world() -> World {
+    let timestep = Timestep::new(Box::new(ManualClock::new()), Duration::from_millis(10), 5);
+    let mut world = crate::setup_ecs(GameMode::RealTime, Bindings::default(), timestep);
+
+    let mut backend = MockBackend::new();
+    backend.add_file(
+        "./src/animations/ogre_idle_animation.toml",
+        include_str!("animations/ogre_idle_animation.toml")
+    );
+    backend.add_image("./src/animations/ogre_idle_animation.png", 64, 16);
+    backend.add_file(
+        "./src/frames/chest_empty_open_anim_f0.toml",
+        include_str!("frames/chest_empty_open_anim_f0.toml")
+    );
+    backend.add_image(CHEST_IMAGE, 16, 16);
+    backend.add_image(TILESET_IMAGE, 64, 64);
+    world.insert(AssetManager::new(Box::new(backend)));
+    world.insert(Rng::new(0));
+    world.insert(LevelSeed(0));
+
+    world
+}
+
+/// Builds an entity from a prefab of the game's config
+/// # Arguments
+/// * `world` - The world to create the entity in
+/// * `name` - The prefab, e.g. "player" or "chest"
+/// * `x`, `y` - Position of the entity in world coordinates
+pub fn spawn(world: &mut World, name: &str, x: f32, y: f32) -> Entity {
+    let prefabs = prefab::parse_prefabs(include_str!("config.toml")).unwrap();
+    prefab::find(&prefabs, name)
+        .unwrap()
+        .spawn_at(world, x, y)
+        .unwrap()
+}
@@ -1,103 +1,257 @@
-use std::ffi::CString;
+use gl::types::{GLenum, GLsizeiptr, GLuint, GLvoid};
 
-/// Generates a buffer on the GPU and returns its id
-pub fn generate_buffer() -> u32 {
-    let mut id = 0;
-
-    unsafe {
-        gl::GenBuffers(1, &mut id);
-    };
+/// How often the contents of a buffer are expected to change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Usage {
+    /// Written once and drawn many times
+    Static,
+    /// Rewritten occasionally and drawn many times
+    Dynamic,
+    /// Rewritten every time it is drawn
+    Stream
+}
 
-    assert_ne!(id, 0);
+impl Usage {
+    fn to_gl(self) -> GLenum {
+        match self {
+            Usage::Static => gl::STATIC_DRAW,
+            Usage::Dynamic => gl::DYNAMIC_DRAW,
+            Usage::Stream => gl::STREAM_DRAW
+        }
+    }
+}
 
-    return id;
+/// Represents a vertex buffer on the GPU
+pub struct Buffer {
+    id: GLuint
 }
 
-/// Sets the vertex data in a buffer
-/// # Arguments
-/// * `id` - Buffer ID
-/// * `data` - Data to upload
-pub fn set_buffer_data(id: u32, data: &Vec<f32>) {
-    unsafe {
-        gl::BindBuffer(gl::ARRAY_BUFFER, id);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            (data.len() * std::mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-            data.as_ptr() as *const gl::types::GLvoid,
-            gl::STATIC_DRAW
-        );
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-    };
+impl Drop for Buffer {
+    /// Deletes the buffer from the GPU
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+        };
+    }
 }
 
-/// Bind a buffer
-/// # Arguments
-/// * `id` - Buffer ID
-pub fn bind_buffer(id: u32) {
-    unsafe {
-        gl::BindBuffer(gl::ARRAY_BUFFER, id);
+impl Default for Buffer {
+    fn default() -> Buffer {
+        Buffer::new()
     }
 }
 
-/// Generates a vertex attribute array on the GPU
-pub fn generate_vertex_array() -> u32 {
-    let mut id = 0;
+impl Buffer {
+    /// Generates an empty buffer on the GPU
+    pub fn new() -> Buffer {
+        let mut id = 0;
 
-    unsafe {
-        gl::GenVertexArrays(1, &mut id);
-    };
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+        };
+
+        assert_ne!(id, 0);
+
+        Buffer { id }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    /// Binds the buffer as the array buffer
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
+        }
+    }
 
-    assert_ne!(id, 0);
+    /// Replaces the contents of the buffer
+    /// # Arguments
+    /// * `data` - Data to upload
+    /// * `usage` - How often the data will be replaced
+    pub fn set_data<T: Copy>(&self, data: &[T], usage: Usage) {
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(data) as GLsizeiptr,
+                data.as_ptr() as *const GLvoid,
+                usage.to_gl()
+            );
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        };
+    }
+}
 
-    return id;
+/// Represents a vertex array object, which records where each vertex attribute is read from
+pub struct VertexArray {
+    id: GLuint
 }
 
-/// Bind an attribute array
-/// # Arguments
-/// * `id` - Vertex Array ID
-pub fn bind_array(id: u32) {
-    unsafe {
-        gl::BindVertexArray(id);
+impl Drop for VertexArray {
+    /// Deletes the vertex array from the GPU
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.id);
+        };
     }
 }
 
-/// Set vertex attribute array
-/// # Arguments
-/// * `buffer` - Buffer vertex data is stored in
-/// * `id` - Vertex Array ID
-/// * `index` - Vertex Array Index to modify
-/// * `size` - The number of components per vertex
-pub fn set_vertex_array_pointer(buffer: u32, id: u32, index: u32, size: i32) {
-    if size > 4 {
-        panic!("Size must be 1, 2, 3, or 4");
+impl Default for VertexArray {
+    fn default() -> VertexArray {
+        VertexArray::new()
     }
+}
 
-    unsafe {
-        gl::BindVertexArray(id);
-        gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+impl VertexArray {
+    /// Generates a vertex array on the GPU
+    pub fn new() -> VertexArray {
+        let mut id = 0;
+
+        unsafe {
+            gl::GenVertexArrays(1, &mut id);
+        };
+
+        assert_ne!(id, 0);
+
+        VertexArray { id }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    /// Binds the vertex array for drawing
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindVertexArray(self.id);
+        }
+    }
+
+    /// Reads a vertex attribute of floats from a buffer
+    /// # Arguments
+    /// * `index` - The attribute location to set
+    /// * `buffer` - Buffer the vertex data is stored in
+    /// * `size` - The number of components per vertex
+    pub fn set_attribute(&self, index: u32, buffer: &Buffer, size: i32) {
+        assert!((1..=4).contains(&size), "Size must be 1, 2, 3, or 4");
+
+        unsafe {
+            gl::BindVertexArray(self.id);
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffer.id);
 
-        gl::EnableVertexAttribArray(index);
-        gl::VertexAttribPointer(index, size, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+            gl::EnableVertexAttribArray(index);
+            gl::VertexAttribPointer(index, size, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
 
-        gl::BindVertexArray(0);
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
     }
 }
 
-/// Generate a texture buffer
-pub fn generate_texture() -> u32 {
-    let mut id = 0;
+/// How a texture is sampled when drawn larger or smaller than its size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Linear
+}
 
-    unsafe {
-        gl::GenTextures(1, &mut id);
-    };
+impl Filter {
+    fn to_gl(self) -> i32 {
+        match self {
+            Filter::Nearest => gl::NEAREST as i32,
+            Filter::Linear => gl::LINEAR as i32
+        }
+    }
+}
 
-    assert_ne!(id, 0);
+/// Represents a 2D texture on the GPU
+pub struct Texture {
+    id: GLuint,
+    width: u32,
+    height: u32
+}
 
-    return id;
+impl Drop for Texture {
+    /// Deletes the texture from the GPU
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        };
+    }
+}
+
+impl Texture {
+    /// Uploads an RGBA image to a new texture
+    /// # Arguments
+    /// * `width`, `height` - Size of the image in pixels
+    /// * `pixels` - Rows of 8 bit RGBA pixels, starting from the bottom
+    /// * `min_filter` - Sampling used when the texture is drawn smaller than its size
+    /// * `mag_filter` - Sampling used when the texture is drawn larger than its size
+    pub fn from_rgba(
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        min_filter: Filter,
+        mag_filter: Filter
+    ) -> Texture {
+        assert_eq!(
+            pixels.len(),
+            (width * height * 4) as usize,
+            "Pixel data does not match the texture size"
+        );
+
+        let mut id = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut id);
+        };
+
+        assert_ne!(id, 0);
+
+        let texture = Texture { id, width, height };
+        texture.bind();
+
+        unsafe {
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as i32,
+                width as i32,
+                height as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const GLvoid
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter.to_gl());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter.to_gl());
+        }
+
+        texture
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Binds the texture for drawing
+    pub fn bind(&self) {
+        bind_texture(self.id);
+    }
 }
 
 /// Bind a texture
+/// Textures owned elsewhere, like those held by the asset manager, are drawn by id.
 /// # Arguments
 /// * `id` - Texture ID
 pub fn bind_texture(id: u32) {
@@ -106,15 +260,6 @@ pub fn bind_texture(id: u32) {
     }
 }
 
-/// Set a shader program as used
-/// # Arugments
-/// * `id` - Shader Program ID
-pub fn use_program(id: u32) {
-    unsafe {
-        gl::UseProgram(id);
-    }
-}
-
 /// Draw Triangles
 /// # Arguments
 /// * `vertex_count` - Number of vertices to draw
@@ -122,26 +267,44 @@ pub fn draw_triangles(vertex_count: u32) {
     unsafe { gl::DrawArrays(gl::TRIANGLES, 0, vertex_count as i32) }
 }
 
-/// Set the value of a vec3 uniform
+/// Draw Triangles from part of the bound buffers
 /// # Arguments
-/// * `uniform` - The name of the uniform to copy data to
-/// * `program` - The shader program in use
-/// * `data` - Data to copy to the uniform
-pub fn set_uniform_float_vec3(uniform: &str, program: u32, data: &Vec<f32>) {
-    unsafe {
-        let location = gl::GetUniformLocation(program, CString::new(uniform).unwrap().as_ptr());
-        gl::Uniform3fv(location, 1, data.as_ptr());
-    }
+/// * `first` - Index of the first vertex to draw
+/// * `vertex_count` - Number of vertices to draw
+pub fn draw_triangles_range(first: u32, vertex_count: u32) {
+    unsafe { gl::DrawArrays(gl::TRIANGLES, first as i32, vertex_count as i32) }
 }
 
-/// Set the value of a vec2 uniform
+/// Creates a rectangle normalized to (-1, 1)
 /// # Arguments
-/// * `uniform` - The name of the uniform to copy data to
-/// * `program` - The shader program in use
-/// * `data` - Data to copy to the uniform
-pub fn set_uniform_float_vec2(uniform: &str, program: u32, data: &Vec<f32>) {
-    unsafe {
-        let location = gl::GetUniformLocation(program, CString::new(uniform).unwrap().as_ptr());
-        gl::Uniform2fv(location, 1, data.as_ptr());
+/// * `width` - Width of the rectangle in pixels
+/// * `height` - Height of the rectangle in pixels
+pub fn create_rectangle(width: f32, height: f32) -> Vec<f32> {
+    let aspect_ratio = width / height;
+
+    // Start with the vertices arranged as a square
+    let mut vertices: Vec<f32> = vec![
+        -1.0, -1.0, 0.0, 1.0, 1.0, 0.0, -1.0, 1.0, 0.0, -1.0, -1.0, 0.0, 1.0, -1.0, 0.0, 1.0, 1.0,
+        0.0,
+    ];
+
+    if width > height {
+        // Divide y by aspect ratio
+        vertices[1] /= aspect_ratio;
+        vertices[4] /= aspect_ratio;
+        vertices[7] /= aspect_ratio;
+        vertices[10] /= aspect_ratio;
+        vertices[13] /= aspect_ratio;
+        vertices[16] /= aspect_ratio;
+    } else if height > width {
+        // Multiply x by aspect ratio
+        vertices[0] *= aspect_ratio;
+        vertices[3] *= aspect_ratio;
+        vertices[6] *= aspect_ratio;
+        vertices[9] *= aspect_ratio;
+        vertices[12] *= aspect_ratio;
+        vertices[15] *= aspect_ratio;
     }
+
+    return vertices;
 }
@@ -0,0 +1,729 @@
+extern crate sdl2;
+extern crate toml;
+
+use sdl2::controller::{Axis, Button as ControllerButton, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+use sdl2::GameControllerSubsystem;
+
+use std::fmt;
+use std::fs;
+
+/// Stick deflection ignored when no deadzone is given in the bindings
+pub const DEFAULT_DEADZONE: f32 = 0.2;
+
+/// Errors that can occur while loading input bindings
+#[derive(Debug)]
+pub enum InputError {
+    /// The bindings file could not be read
+    Io(String),
+    /// The bindings file is not valid TOML
+    Parse(String),
+    /// An action name is not recognised
+    UnknownAction(String),
+    /// A button name is not recognised
+    UnknownButton { action: String, button: String },
+    /// An action is not bound to a list of buttons
+    InvalidBinding(String),
+    /// The deadzone is not a number from 0 up to 1
+    InvalidDeadzone
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::Io(message) => write!(f, "Failed to read bindings: {}", message),
+            InputError::Parse(message) => write!(f, "Failed to parse bindings: {}", message),
+            InputError::UnknownAction(action) => write!(f, "Unknown action '{}'", action),
+            InputError::UnknownButton { action, button } => {
+                write!(f, "Action '{}' has unknown button '{}'", action, button)
+            },
+            InputError::InvalidBinding(action) => {
+                write!(
+                    f,
+                    "Action '{}' must be bound to an array of buttons",
+                    action
+                )
+            },
+            InputError::InvalidDeadzone => {
+                write!(f, "Deadzone must be a number from 0 up to 1")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// Something the player can do, independent of which button does it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputAction {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Wait,
+    Inventory,
+    Pause,
+    Confirm,
+    Cancel,
+    QuickSave,
+    QuickLoad
+}
+
+impl InputAction {
+    /// Every action, in the order used to store their states
+    pub const ALL: [InputAction; 11] = [
+        InputAction::MoveUp,
+        InputAction::MoveDown,
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::Wait,
+        InputAction::Inventory,
+        InputAction::Pause,
+        InputAction::Confirm,
+        InputAction::Cancel,
+        InputAction::QuickSave,
+        InputAction::QuickLoad
+    ];
+
+    /// Parses an action from its name in a bindings file
+    pub fn from_name(name: &str) -> Option<InputAction> {
+        InputAction::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+
+    /// The name of the action in a bindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            InputAction::MoveUp => "move_up",
+            InputAction::MoveDown => "move_down",
+            InputAction::MoveLeft => "move_left",
+            InputAction::MoveRight => "move_right",
+            InputAction::Wait => "wait",
+            InputAction::Inventory => "inventory",
+            InputAction::Pause => "pause",
+            InputAction::Confirm => "confirm",
+            InputAction::Cancel => "cancel",
+            InputAction::QuickSave => "quick_save",
+            InputAction::QuickLoad => "quick_load"
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A physical button an action can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(Scancode),
+    Mouse(MouseButton),
+    Controller(ControllerButton)
+}
+
+impl Button {
+    /// Parses a button from its name in a bindings file
+    /// Keys use SDL scancode names such as `W` or `Space`, mouse buttons are prefixed with
+    /// `Mouse:` and controller buttons with `Pad:`, e.g. `Mouse:Left` or `Pad:a`.
+    pub fn from_name(name: &str) -> Option<Button> {
+        if let Some(mouse) = name.strip_prefix("Mouse:") {
+            let button = match mouse {
+                "Left" => MouseButton::Left,
+                "Middle" => MouseButton::Middle,
+                "Right" => MouseButton::Right,
+                "X1" => MouseButton::X1,
+                "X2" => MouseButton::X2,
+                _ => return None
+            };
+            Some(Button::Mouse(button))
+        } else if let Some(pad) = name.strip_prefix("Pad:") {
+            ControllerButton::from_string(pad).map(Button::Controller)
+        } else {
+            Scancode::from_name(name).map(Button::Key)
+        }
+    }
+}
+
+/// A change in the state of a button or stick
+/// SDL events are translated into these, and tests can create them directly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Pressed(Button),
+    Released(Button),
+    /// A controller axis moved to a position from -1 to 1, with y pointing down
+    Axis(Axis, f32)
+}
+
+impl InputEvent {
+    /// Translates an SDL event, returning `None` for events that are not input changes
+    pub fn from_sdl(event: &Event) -> Option<InputEvent> {
+        match event {
+            Event::KeyDown {
+                scancode: Some(scancode),
+                repeat: false,
+                ..
+            } => Some(InputEvent::Pressed(Button::Key(*scancode))),
+            Event::KeyUp {
+                scancode: Some(scancode),
+                ..
+            } => Some(InputEvent::Released(Button::Key(*scancode))),
+            Event::MouseButtonDown { mouse_btn, .. } => {
+                Some(InputEvent::Pressed(Button::Mouse(*mouse_btn)))
+            },
+            Event::MouseButtonUp { mouse_btn, .. } => {
+                Some(InputEvent::Released(Button::Mouse(*mouse_btn)))
+            },
+            Event::ControllerButtonDown { button, .. } => {
+                Some(InputEvent::Pressed(Button::Controller(*button)))
+            },
+            Event::ControllerButtonUp { button, .. } => {
+                Some(InputEvent::Released(Button::Controller(*button)))
+            },
+            Event::ControllerAxisMotion { axis, value, .. } => {
+                Some(InputEvent::Axis(*axis, axis_value(*value)))
+            },
+            _ => None
+        }
+    }
+}
+
+/// Which buttons trigger each action
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    bindings: Vec<(InputAction, Button)>,
+    /// Fraction of the left stick's range ignored around its center
+    pub deadzone: f32
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings {
+            bindings: vec![],
+            deadzone: DEFAULT_DEADZONE
+        }
+    }
+}
+
+impl Bindings {
+    /// Loads bindings from a file
+    /// # Arguments
+    /// * `path` - The path of the bindings file
+    pub fn load(path: &str) -> Result<Bindings, InputError> {
+        let source = fs::read_to_string(path).map_err(|e| InputError::Io(e.to_string()))?;
+        Bindings::parse(&source)
+    }
+
+    /// Parses bindings where each action name is given an array of button names
+    /// An optional `deadzone` number sets the deadzone of the left stick.
+    /// # Arguments
+    /// * `source` - The contents of a bindings file
+    pub fn parse(source: &str) -> Result<Bindings, InputError> {
+        let root = source
+            .parse::<toml::Value>()
+            .map_err(|e| InputError::Parse(e.to_string()))?;
+        let table = root
+            .as_table()
+            .ok_or_else(|| InputError::Parse(String::from("expected a table")))?;
+
+        let mut bindings = Bindings::default();
+        for (name, buttons) in table {
+            if name == "deadzone" {
+                bindings.deadzone = match buttons {
+                    toml::Value::Float(value) => *value as f32,
+                    toml::Value::Integer(value) => *value as f32,
+                    _ => return Err(InputError::InvalidDeadzone)
+                };
+
+                if !(0.0..1.0).contains(&bindings.deadzone) {
+                    return Err(InputError::InvalidDeadzone);
+                }
+
+                continue;
+            }
+
+            let action = InputAction::from_name(name)
+                .ok_or_else(|| InputError::UnknownAction(name.clone()))?;
+            let buttons = buttons
+                .as_array()
+                .ok_or_else(|| InputError::InvalidBinding(name.clone()))?;
+
+            for button in buttons {
+                let button = button
+                    .as_str()
+                    .ok_or_else(|| InputError::InvalidBinding(name.clone()))?;
+                let button =
+                    Button::from_name(button).ok_or_else(|| InputError::UnknownButton {
+                        action: name.clone(),
+                        button: String::from(button)
+                    })?;
+
+                bindings.bind(action, button);
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    /// Adds a button to an action
+    pub fn bind(&mut self, action: InputAction, button: Button) {
+        if !self.bindings.contains(&(action, button)) {
+            self.bindings.push((action, button));
+        }
+    }
+
+    /// Removes every button from an action
+    pub fn clear(&mut self, action: InputAction) {
+        self.bindings.retain(|(bound, _)| *bound != action);
+    }
+
+    /// Returns the buttons bound to an action
+    pub fn buttons(&self, action: InputAction) -> Vec<Button> {
+        self.bindings
+            .iter()
+            .filter(|(bound, _)| *bound == action)
+            .map(|(_, button)| *button)
+            .collect()
+    }
+}
+
+/// Current state of every action, updated from input events each frame
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    pub bindings: Bindings,
+    /// Buttons currently held down
+    held: Vec<Button>,
+    /// Raw position of the left stick, y pointing down
+    stick: (f32, f32),
+    just_pressed: [bool; InputAction::ALL.len()],
+    just_released: [bool; InputAction::ALL.len()]
+}
+
+impl InputState {
+    pub fn new(bindings: Bindings) -> InputState {
+        InputState {
+            bindings,
+            ..InputState::default()
+        }
+    }
+
+    /// Clears the presses and releases that have been handled
+    /// Must be called after the first simulation step that saw them and before handling newer
+    /// events, so presses in frames without a step are not lost.
+    pub fn clear_presses(&mut self) {
+        self.just_pressed = [false; InputAction::ALL.len()];
+        self.just_released = [false; InputAction::ALL.len()];
+    }
+
+    /// Updates action states from a button or stick change
+    /// # Arguments
+    /// * `event` - The change
+    pub fn handle(&mut self, event: InputEvent) {
+        let (button, down) = match event {
+            InputEvent::Pressed(button) => (button, true),
+            InputEvent::Released(button) => (button, false),
+            InputEvent::Axis(Axis::LeftX, value) => {
+                self.stick.0 = value;
+                return;
+            },
+            InputEvent::Axis(Axis::LeftY, value) => {
+                self.stick.1 = value;
+                return;
+            },
+            InputEvent::Axis(..) => return
+        };
+
+        // Note which actions change before updating the held buttons
+        let before: Vec<bool> = InputAction::ALL.iter().map(|a| self.pressed(*a)).collect();
+
+        if down && !self.held.contains(&button) {
+            self.held.push(button);
+        } else if !down {
+            self.held.retain(|held| *held != button);
+        }
+
+        for action in InputAction::ALL.iter() {
+            let now = self.pressed(*action);
+            if now && !before[action.index()] {
+                self.just_pressed[action.index()] = true;
+            } else if !now && before[action.index()] {
+                self.just_released[action.index()] = true;
+            }
+        }
+    }
+
+    /// Releases every button and centers the stick, e.g. when the window loses focus
+    pub fn release_all(&mut self) {
+        for button in self.held.clone() {
+            self.handle(InputEvent::Released(button));
+        }
+        self.stick = (0.0, 0.0);
+    }
+
+    /// True while any button bound to the action is held
+    pub fn pressed(&self, action: InputAction) -> bool {
+        self.bindings
+            .buttons(action)
+            .iter()
+            .any(|button| self.held.contains(button))
+    }
+
+    /// True if the action started being pressed this frame
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.just_pressed[action.index()]
+    }
+
+    /// True if the action stopped being pressed this frame
+    pub fn just_released(&self, action: InputAction) -> bool {
+        self.just_released[action.index()]
+    }
+
+    /// The direction to move in, with x to the right and y up
+    /// Held movement actions give -1, 0 or 1 on each axis. Otherwise the left stick gives a
+    /// vector no longer than 1, scaled by how far it is pushed past the deadzone.
+    pub fn movement(&self) -> (f32, f32) {
+        let axis = |positive, negative| {
+            let positive = if self.pressed(positive) { 1.0 } else { 0.0 };
+            let negative = if self.pressed(negative) { 1.0 } else { 0.0 };
+            positive - negative
+        };
+
+        let x = axis(InputAction::MoveRight, InputAction::MoveLeft);
+        let y = axis(InputAction::MoveUp, InputAction::MoveDown);
+        if x != 0.0 || y != 0.0 {
+            return (x, y);
+        }
+
+        let (x, y) = apply_deadzone(self.stick, self.bindings.deadzone);
+        (x, -y)
+    }
+}
+
+/// Converts a raw SDL axis value to the range -1 to 1
+pub fn axis_value(value: i16) -> f32 {
+    (value as f32 / i16::MAX as f32).max(-1.0)
+}
+
+/// Removes small stick movements and rescales the rest to keep the full range
+/// The deadzone is radial so diagonal movement is not snapped to the axes. The result is never
+/// longer than 1, since the corners of a stick's range can go past the unit circle.
+/// # Arguments
+/// * `stick` - Position of the stick, each axis from -1 to 1
+/// * `deadzone` - Fraction of the range around the center to ignore, from 0 up to 1
+pub fn apply_deadzone(stick: (f32, f32), deadzone: f32) -> (f32, f32) {
+    let length = (stick.0 * stick.0 + stick.1 * stick.1).sqrt();
+    if length <= deadzone || length == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let scaled = ((length - deadzone) / (1.0 - deadzone)).min(1.0);
+    (stick.0 / length * scaled, stick.1 / length * scaled)
+}
+
+/// Game controllers currently plugged in
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    open: Vec<GameController>
+}
+
+impl Controllers {
+    pub fn new(subsystem: GameControllerSubsystem) -> Controllers {
+        Controllers {
+            subsystem,
+            open: vec![]
+        }
+    }
+
+    /// Opens or closes controllers as they are plugged in and removed
+    /// SDL reports controllers connected at startup as added, so none need opening beforehand.
+    /// Returns true if a controller was removed, so its held buttons can be released.
+    /// # Arguments
+    /// * `event` - The SDL event
+    pub fn handle(&mut self, event: &Event) -> bool {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                // A controller that can not be opened is ignored, as if it was never plugged in
+                if let Ok(controller) = self.subsystem.open(*which) {
+                    self.open.push(controller);
+                }
+                false
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                let count = self.open.len();
+                self.open
+                    .retain(|controller| controller.instance_id() != *which);
+                self.open.len() != count
+            },
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: Button = Button::Key(Scancode::W);
+    const UP: Button = Button::Key(Scancode::Up);
+    const CLICK: Button = Button::Mouse(MouseButton::Left);
+    const PAD_A: Button = Button::Controller(ControllerButton::A);
+
+    fn input() -> InputState {
+        let mut bindings = Bindings::default();
+        bindings.bind(InputAction::MoveUp, W);
+        bindings.bind(InputAction::MoveUp, UP);
+        bindings.bind(InputAction::Cancel, CLICK);
+        bindings.bind(InputAction::Confirm, PAD_A);
+        InputState::new(bindings)
+    }
+
+    #[test]
+    fn press_is_only_new_for_one_frame() {
+        let mut input = input();
+
+        input.handle(InputEvent::Pressed(W));
+        assert!(input.pressed(InputAction::MoveUp));
+        assert!(input.just_pressed(InputAction::MoveUp));
+        assert!(!input.just_released(InputAction::MoveUp));
+
+        input.clear_presses();
+        assert!(input.pressed(InputAction::MoveUp));
+        assert!(!input.just_pressed(InputAction::MoveUp));
+
+        input.handle(InputEvent::Released(W));
+        assert!(!input.pressed(InputAction::MoveUp));
+        assert!(input.just_released(InputAction::MoveUp));
+
+        input.clear_presses();
+        assert!(!input.just_released(InputAction::MoveUp));
+    }
+
+    #[test]
+    fn action_stays_pressed_while_any_button_is_held() {
+        let mut input = input();
+
+        input.handle(InputEvent::Pressed(W));
+        input.handle(InputEvent::Pressed(UP));
+        input.clear_presses();
+
+        input.handle(InputEvent::Released(W));
+        assert!(input.pressed(InputAction::MoveUp));
+        assert!(!input.just_released(InputAction::MoveUp));
+
+        input.handle(InputEvent::Released(UP));
+        assert!(input.just_released(InputAction::MoveUp));
+    }
+
+    #[test]
+    fn tap_within_one_frame_is_not_lost() {
+        let mut input = input();
+
+        input.handle(InputEvent::Pressed(CLICK));
+        input.handle(InputEvent::Released(CLICK));
+
+        assert!(!input.pressed(InputAction::Cancel));
+        assert!(input.just_pressed(InputAction::Cancel));
+        assert!(input.just_released(InputAction::Cancel));
+    }
+
+    #[test]
+    fn mouse_and_controller_buttons_trigger_actions() {
+        let mut input = input();
+
+        input.handle(InputEvent::Pressed(CLICK));
+        input.handle(InputEvent::Pressed(PAD_A));
+
+        assert!(input.just_pressed(InputAction::Cancel));
+        assert!(input.just_pressed(InputAction::Confirm));
+        assert!(!input.pressed(InputAction::MoveUp));
+    }
+
+    #[test]
+    fn unbound_buttons_are_ignored() {
+        let mut input = input();
+        input.handle(InputEvent::Pressed(Button::Key(Scancode::Q)));
+
+        assert!(InputAction::ALL
+            .iter()
+            .all(|action| !input.pressed(*action) && !input.just_pressed(*action)));
+    }
+
+    #[test]
+    fn release_all_releases_held_actions() {
+        let mut input = input();
+        input.handle(InputEvent::Pressed(W));
+        input.clear_presses();
+
+        input.release_all();
+        assert!(!input.pressed(InputAction::MoveUp));
+        assert!(input.just_released(InputAction::MoveUp));
+    }
+
+    #[test]
+    fn held_movement_gives_unit_directions() {
+        let mut bindings = Bindings::default();
+        bindings.bind(InputAction::MoveUp, W);
+        bindings.bind(InputAction::MoveLeft, Button::Key(Scancode::A));
+        bindings.bind(InputAction::MoveRight, Button::Key(Scancode::D));
+        let mut input = InputState::new(bindings);
+
+        input.handle(InputEvent::Pressed(W));
+        input.handle(InputEvent::Pressed(Button::Key(Scancode::A)));
+        assert_eq!(input.movement(), (-1.0, 1.0));
+
+        // Opposite directions cancel out
+        input.handle(InputEvent::Pressed(Button::Key(Scancode::D)));
+        assert_eq!(input.movement(), (0.0, 1.0));
+    }
+
+    fn assert_near(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-5 && (actual.1 - expected.1).abs() < 1e-5,
+            "Got {:?}, expected {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn length(vector: (f32, f32)) -> f32 {
+        (vector.0 * vector.0 + vector.1 * vector.1).sqrt()
+    }
+
+    #[test]
+    fn deadzone_ignores_small_movements() {
+        assert_eq!(apply_deadzone((0.0, 0.0), 0.2), (0.0, 0.0));
+        assert_eq!(apply_deadzone((0.1, -0.1), 0.2), (0.0, 0.0));
+        assert_eq!(apply_deadzone((0.2, 0.0), 0.2), (0.0, 0.0));
+    }
+
+    #[test]
+    fn deadzone_rescales_to_the_full_range() {
+        // Just past the edge of the deadzone is barely moving, halfway out is half speed
+        assert!(apply_deadzone((0.201, 0.0), 0.2).0 < 0.01);
+        assert_near(apply_deadzone((0.6, 0.0), 0.2), (0.5, 0.0));
+        assert_near(apply_deadzone((0.0, -1.0), 0.2), (0.0, -1.0));
+
+        // Without a deadzone the stick is unchanged
+        assert_near(apply_deadzone((0.3, -0.4), 0.0), (0.3, -0.4));
+    }
+
+    #[test]
+    fn deadzone_clamps_the_corners_of_the_range() {
+        let corner = apply_deadzone((1.0, 1.0), 0.2);
+        assert_near(corner, (0.5f32.sqrt(), 0.5f32.sqrt()));
+        assert!((length(corner) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn deadzone_keeps_the_diagonal_direction() {
+        let diagonal = apply_deadzone((0.5, 0.5), 0.2);
+
+        // The deadzone is radial so the same distance is removed in every direction
+        assert!((diagonal.0 - diagonal.1).abs() < 1e-6);
+        let expected = (0.5f32.sqrt() - 0.2) / 0.8;
+        assert!((length(diagonal) - expected).abs() < 1e-5);
+
+        // A diagonal just outside the deadzone moves even though each axis is inside it
+        assert!(length(apply_deadzone((0.15, 0.15), 0.2)) > 0.0);
+    }
+
+    #[test]
+    fn converts_raw_axis_values() {
+        assert_eq!(axis_value(0), 0.0);
+        assert_eq!(axis_value(i16::MAX), 1.0);
+        assert_eq!(axis_value(i16::MIN), -1.0);
+    }
+
+    #[test]
+    fn stick_moves_when_no_direction_is_held() {
+        let mut input = input();
+        input.handle(InputEvent::Axis(Axis::LeftX, 0.6));
+        input.handle(InputEvent::Axis(Axis::LeftY, 0.0));
+        assert_near(input.movement(), (0.5, 0.0));
+
+        // The stick's y axis points down
+        input.handle(InputEvent::Axis(Axis::LeftX, 0.0));
+        input.handle(InputEvent::Axis(Axis::LeftY, -1.0));
+        assert_near(input.movement(), (0.0, 1.0));
+
+        // Held buttons win over the stick
+        input.handle(InputEvent::Pressed(W));
+        input.handle(InputEvent::Axis(Axis::LeftY, 1.0));
+        assert_eq!(input.movement(), (0.0, 1.0));
+
+        input.release_all();
+        assert_eq!(input.movement(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn rejects_deadzones_outside_the_range() {
+        assert!(matches!(
+            Bindings::parse("deadzone = 1.0"),
+            Err(InputError::InvalidDeadzone)
+        ));
+        assert!(matches!(
+            Bindings::parse("deadzone = -0.1"),
+            Err(InputError::InvalidDeadzone)
+        ));
+        assert_eq!(Bindings::parse("deadzone = 0").unwrap().deadzone, 0.0);
+    }
+
+    #[test]
+    fn loads_the_default_bindings() {
+        let bindings = Bindings::parse(include_str!("bindings.toml")).unwrap();
+
+        assert_eq!(
+            bindings.buttons(InputAction::MoveUp),
+            [W, UP, Button::Controller(ControllerButton::DPadUp)]
+        );
+        assert_eq!(
+            bindings.buttons(InputAction::Cancel),
+            [
+                Button::Key(Scancode::Backspace),
+                Button::Mouse(MouseButton::Right),
+                Button::Controller(ControllerButton::B)
+            ]
+        );
+        assert_eq!(
+            bindings.buttons(InputAction::Wait),
+            [
+                Button::Key(Scancode::Space),
+                Button::Key(Scancode::Kp5),
+                Button::Controller(ControllerButton::Y)
+            ]
+        );
+        assert_eq!(
+            bindings.buttons(InputAction::QuickLoad),
+            [Button::Key(Scancode::F9)]
+        );
+        assert_eq!(bindings.deadzone, 0.2);
+
+        // Every action has a button
+        for action in InputAction::ALL.iter() {
+            assert!(!bindings.buttons(*action).is_empty(), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_bindings() {
+        assert!(matches!(
+            Bindings::parse("jump = [\"Space\"]"),
+            Err(InputError::UnknownAction(action)) if action == "jump"
+        ));
+        assert!(matches!(
+            Bindings::parse("wait = [\"Nowhere\"]"),
+            Err(InputError::UnknownButton { button, .. }) if button == "Nowhere"
+        ));
+        assert!(matches!(
+            Bindings::parse("wait = \"Space\""),
+            Err(InputError::InvalidBinding(action)) if action == "wait"
+        ));
+        assert!(matches!(
+            Bindings::parse("cancel = [\"Mouse:Side\"]"),
+            Err(InputError::UnknownButton { .. })
+        ));
+    }
+}
@@ -1,38 +1,59 @@
 extern crate gl;
 extern crate sdl2;
 use sdl2::event::{Event, WindowEvent};
-use sdl2::keyboard::Scancode;
 use sdl2::video::GLProfile;
 
 extern crate image;
 
 pub mod shader;
-use shader::{Program, Shader};
-
+use shader::Program;
+
+pub mod state;
+use state::{State, StateId, StateStack};
+
+pub mod render;
+use render::Renderer;
+
+pub mod ai;
+pub mod animation;
+pub mod assets;
+pub mod atlas;
+pub mod audio;
+pub mod batch;
+#[cfg(test)]
+mod benchmark;
+pub mod camera;
+pub mod collision;
+pub mod combat;
 pub mod component_system;
+pub mod dungeon;
+pub mod error;
+#[cfg(test)]
+mod fixture;
+use error::Error;
 pub mod gl_util;
+pub mod input;
+pub mod map;
+pub mod prefab;
+pub mod preprocessor;
+pub mod rng;
+pub mod save;
+pub mod text;
+pub mod timestep;
+pub mod turn;
 
 extern crate specs;
 use specs::prelude::*;
 use specs::WorldExt;
 
-use std::ffi::CString;
-
 extern crate nalgebra;
-use nalgebra::Orthographic3;
 
-fn init_sdl() -> (sdl2::Sdl, sdl2::video::Window, sdl2::video::GLContext) {
+fn init_sdl() -> Result<(sdl2::Sdl, sdl2::video::Window, sdl2::video::GLContext), Error> {
     // Initialize SDL
-    let sdl_context = match sdl2::init() {
-        Ok(context) => context,
-        Err(message) => panic!(format!("SDL Init Failed: {}", message))
-    };
+    let sdl_context = sdl2::init().map_err(Error::Sdl)?;
 
     // Ask SDL to initialize the vide system
-    let video_subsystem = match sdl_context.video() {
-        Ok(video_subsystem) => video_subsystem,
-        Err(message) => panic!(format!("Failed to create video subsystem: {}", message))
-    };
+    let video_subsystem = sdl_context.video().map_err(Error::Sdl)?;
 
     // Set the attributes of the OpenGL Context
     let gl_attributes = video_subsystem.gl_attr();
@@ -41,30 +62,30 @@ fn init_sdl() -> (sdl2::Sdl, sdl2::video::Window, sdl2::video::GLContext) {
     gl_attributes.set_context_version(3, 3);
 
     // Create the window
-    let window = match video_subsystem
+    let window = video_subsystem
         .window("Rust Rouge", 600, 600)
         .position_centered()
         .resizable()
         .opengl()
         .build()
-    {
-        Ok(window) => window,
-        Err(message) => panic!(format!("Failed to create window: {}", message))
-    };
+        .map_err(|error| Error::Sdl(format!("Failed to create window: {}", error)))?;
 
     // Create the OpenGL Context
-    let gl_context = match window.gl_create_context() {
-        Ok(context) => context,
-        Err(message) => panic!(format!("Failed to create OpenGL Context: {}", message))
-    };
+    let gl_context = window
+        .gl_create_context()
+        .map_err(|message| Error::Sdl(format!("Failed to create OpenGL context: {}", message)))?;
 
     // Load the OpenGL Functions
     gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::ffi::c_void);
 
-    (sdl_context, window, gl_context)
+    Ok((sdl_context, window, gl_context))
 }
 
-fn setup_ecs<'a>() -> (specs::World, specs::Dispatcher<'a, 'a>) {
+fn setup_ecs(
+    mode: turn::GameMode,
+    bindings: input::Bindings,
+    timestep: timestep::Timestep
+) -> specs::World {
     use component_system::*;
 
     // Create the world
@@ -72,188 +93,292 @@ fn setup_ecs<'a>() -> (specs::World, specs::Dispatcher<'a, 'a>) {
 
     // Register Components
     world.register::<components::Position>();
+    world.register::<components::LastPosition>();
     world.register::<components::Velocity>();
     world.register::<components::Size>();
+    world.register::<components::Collider>();
     world.register::<components::Controlled>();
+    world.register::<components::Actor>();
     world.register::<components::Animate>();
     world.register::<components::Drawn>();
+    world.register::<components::Health>();
+    world.register::<components::AttackStats>();
+    world.register::<components::Defense>();
+    world.register::<components::Hostile>();
+    world.register::<components::Corpse>();
+    world.register::<components::Brain>();
 
     // Insert Resources
-    world.insert(resources::DeltaTime::default());
-    world.insert(resources::InputState::new());
+    world.insert(resources::DeltaTime {
+        delta: timestep.step()
+    });
+    world.insert(resources::FrameTime::default());
+    world.insert(timestep);
+    world.insert(input::InputState::new(bindings));
+    world.insert(resources::View::default());
+    world.insert(camera::Camera::new());
+    world.insert(resources::HudText::default());
+    world.insert(resources::PendingAction::default());
+    world.insert(turn::Scheduler::<specs::Entity>::new());
+    world.insert(mode);
+    world.insert(specs::shrev::EventChannel::<resources::ClipFinished>::new());
+    world.insert(specs::shrev::EventChannel::<resources::CombatEvent>::new());
+    world.insert(resources::MeleeAttacks::default());
+    world.insert(audio::SoundQueue::default());
+    world.insert(state::Transitions::default());
+
+    world
+}
 
-    // Create the dispatcher
-    let dispatcher = DispatcherBuilder::new()
+/// Creates the systems run once per simulation step while playing
+fn create_update_dispatcher(mode: turn::GameMode) -> specs::Dispatcher<'static, 'static> {
+    use component_system::*;
+
+    let mut builder = DispatcherBuilder::new()
         // Add parallel systems
-        .with(systems::TimeSystem, "TimeSystem", &[])
-        .with(systems::ControlSystem, "ControlSystem", &["TimeSystem"])
-        .with(systems::PhysicsSystem, "PhysicsSystem", &["ControlSystem"])
-        .with(systems::AnimateSystem, "AnimationSystem", &["TimeSystem"])
+        .with(systems::SnapshotSystem, "SnapshotSystem", &[])
+        .with(systems::AnimateSystem, "AnimationSystem", &[]);
+
+    // Movement is continuous in real time and by whole tiles when turn based
+    builder = match mode {
+        turn::GameMode::RealTime => builder
+            .with(systems::ControlSystem, "ControlSystem", &[])
+            .with(systems::AiSystem, "AiSystem", &[])
+            .with(
+                systems::PhysicsSystem,
+                "PhysicsSystem",
+                &["SnapshotSystem", "ControlSystem", "AiSystem"]
+            )
+            .with(
+                systems::CombatSystem,
+                "CombatSystem",
+                &["PhysicsSystem", "AnimationSystem"]
+            ),
+        turn::GameMode::TurnBased => builder
+            .with(systems::TurnSystem, "TurnSystem", &["SnapshotSystem"])
+            .with(
+                systems::CombatSystem,
+                "CombatSystem",
+                &["TurnSystem", "AnimationSystem"]
+            )
+    };
+    builder.build()
+}
+
+/// Creates the systems run once per frame to draw the level
+fn create_render_dispatcher() -> specs::Dispatcher<'static, 'static> {
+    use component_system::*;
+
+    DispatcherBuilder::new()
+        .with(systems::CameraSystem, "CameraSystem", &[])
         // Add serial systems
         .with_thread_local(systems::DrawSystem)
-        .build();
-
-    (world, dispatcher)
+        .build()
 }
 
-fn create_shader_program() -> Program {
-    // Load shaders
-    let vertex_shader = match Shader::new_from_file("./src/shaders/entity.vert", gl::VERTEX_SHADER)
-    {
-        Ok(shader) => shader,
-        Err(message) => panic!(format!("Failed to create vertex shader: {}", message))
-    };
-
-    let fragment_shader =
-        match Shader::new_from_file("./src/shaders/entity.frag", gl::FRAGMENT_SHADER) {
-            Ok(shader) => shader,
-            Err(message) => panic!(format!("Failed to create fragment shader: {}", message))
-        };
+/// Creates the states of the game as the state stack asks for them
+fn create_state(mode: turn::GameMode, id: StateId) -> Box<dyn State> {
+    match id {
+        StateId::Title => Box::new(state::TitleState),
+        StateId::Playing => Box::new(state::PlayingState::new(
+            create_update_dispatcher(mode),
+            create_render_dispatcher(),
+            start_level
+        )),
+        StateId::Paused => Box::new(state::PausedState),
+        StateId::Inventory => Box::new(state::InventoryState),
+        StateId::GameOver => Box::new(state::GameOverState)
+    }
+}
 
-    // Create shader program
-    let shader_program = match Program::new()
-        .attach_shader(&vertex_shader)
-        .attach_shader(&fragment_shader)
-        .link()
-    {
-        Ok(program) => program,
-        Err(message) => panic!(format!("Failed to create shader program: {}", message))
-    };
+fn create_shader_program() -> Result<Program, Error> {
+    // Load shaders and create shader program
+    let shader_program = Program::new_from_files(
+        &[
+            ("./src/shaders/entity.vert", gl::VERTEX_SHADER),
+            ("./src/shaders/entity.frag", gl::FRAGMENT_SHADER)
+        ],
+        &[]
+    )?;
 
     // Use shader program
     shader_program.set_used();
 
-    return shader_program;
+    Ok(shader_program)
 }
 
-/// Creates a rectangle normalized to (-1, 1)
-fn create_rectangle(width: f32, height: f32) -> Vec<f32> {
-    let aspect_ratio = width / height;
-
-    // Start with the vertices arranged as a square
-    let mut vertices: Vec<f32> = vec![
-        -1.0, -1.0, 0.0, 1.0, 1.0, 0.0, -1.0, 1.0, 0.0, -1.0, -1.0, 0.0, 1.0, -1.0, 0.0, 1.0, 1.0,
-        0.0,
-    ];
-
-    if width > height {
-        // Divide y by aspect ratio
-        vertices[1] /= aspect_ratio;
-        vertices[4] /= aspect_ratio;
-        vertices[7] /= aspect_ratio;
-        vertices[10] /= aspect_ratio;
-        vertices[13] /= aspect_ratio;
-        vertices[16] /= aspect_ratio;
-    } else if height > width {
-        // Multiply x by aspect ratio
-        vertices[0] *= aspect_ratio;
-        vertices[3] *= aspect_ratio;
-        vertices[6] *= aspect_ratio;
-        vertices[9] *= aspect_ratio;
-        vertices[12] *= aspect_ratio;
-        vertices[15] *= aspect_ratio;
-    }
+/// Generates a level and spawns the entities placed in it
+fn create_level(world: &mut specs::World, seed: u64) -> Result<(), Error> {
+    use dungeon::SpawnKind;
 
-    return vertices;
-}
+    let config = dungeon::DungeonConfig::default();
+    let dungeon = dungeon::generate(&config, seed);
 
-fn create_entity(world: &mut specs::World, program: u32) {
-    use component_system::components::*;
+    // Build the map
+    let (tileset, texture) = {
+        let mut assets = world.write_resource::<assets::AssetManager>();
+        let tileset = assets.load_atlas("./src/tiles/dungeon.toml")?;
+        let texture = assets.load_texture(&tileset.image)?;
+        ((*tileset).clone(), texture)
+    };
+    let map = dungeon
+        .to_tile_map(tileset, 0.1)
+        .map_err(|error| Error::config("./src/tiles/dungeon.toml", error))?;
 
-    let vertices = create_rectangle(22.0, 28.0);
+    // Draw the new map's tiles from its tileset
+    if let Some(mut renderer) = world.try_fetch_mut::<Box<dyn Renderer>>() {
+        renderer.set_map(texture);
+    }
 
-    // let texture_vertices: Vec<f32> =
-    //     vec![0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0];
+    // Add entities at their spawn points
+    let prefabs = prefab::load_prefabs("./src/config.toml")
+        .map_err(|error| Error::config("./src/config.toml", error))?;
 
-    let texture_vertices: Vec<Vec<f32>> = vec![
-        vec![
-            0.0, 0.0, 0.25, 1.0, 0.0, 1.0, 0.0, 0.0, 0.25, 0.0, 0.25, 1.0,
-        ],
-        vec![
-            0.25, 0.0, 0.5, 1.0, 0.25, 1.0, 0.25, 0.0, 0.5, 0.0, 0.5, 1.0,
-        ],
-        vec![
-            0.5, 0.0, 0.75, 1.0, 0.5, 1.0, 0.5, 0.0, 0.75, 0.0, 0.75, 1.0,
-        ],
-        vec![
-            0.75, 0.0, 1.0, 1.0, 0.75, 1.0, 0.75, 0.0, 1.0, 0.0, 1.0, 1.0,
-        ],
-    ];
-
-    let texture = match image::open("./src/animations/ogre_idle_animation.png") {
-        Ok(image) => image.flipv().into_rgba(),
-        Err(message) => panic!(format!("Image could not be loaded: {}", message))
-    };
+    for spawn in &dungeon.spawns {
+        let name = match spawn.kind {
+            SpawnKind::Player => "player",
+            SpawnKind::Monster => "ogre",
+            SpawnKind::Chest => "chest"
+        };
 
-    let texture_id = gl_util::generate_texture();
-    gl_util::bind_texture(texture_id);
+        let (x, y) = map.tile_to_world(spawn.column, spawn.row);
+        prefab::find(&prefabs, name)
+            .and_then(|prefab| prefab.spawn_at(world, x, y))
+            .map_err(|error| Error::config("./src/config.toml", error))?;
 
-    unsafe {
-        gl::TexImage2D(
-            gl::TEXTURE_2D,
-            0,
-            gl::RGBA8 as i32,
-            88,
-            28,
-            0,
-            gl::RGBA,
-            gl::UNSIGNED_BYTE,
-            texture.as_ptr() as *const gl::types::GLvoid
-        );
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        // Start with the player in view
+        if spawn.kind == SpawnKind::Player {
+            let mut camera = world.write_resource::<camera::Camera>();
+            camera.bounds = Some(map.bounds());
+            camera.snap_to(x, y);
+        }
     }
 
-    // Create an entity
+    world.insert(map);
+    Ok(())
+}
+
+/// Replaces the current level with a new one
+fn start_level(world: &mut specs::World) -> Result<(), Error> {
+    use component_system::resources::{HudText, LevelSeed, MeleeAttacks, PendingAction};
+
+    // Clear out the last level
+    world.delete_all();
+    world.maintain();
+    world.insert(PendingAction::default());
+    world.insert(MeleeAttacks::default());
+
+    let seed = world.read_resource::<LevelSeed>().0;
+    create_level(world, seed)?;
+    world.insert(HudText(format!("Seed {}", seed)));
     world
-        .create_entity()
-        .with(Position::new_xyz(0.0, 0.0, 0.0))
-        .with(Velocity::new())
-        .with(Controlled)
-        .with(Size::new(0.3, 0.3))
-        .with(Drawn::new(
-            program,
-            texture_id,
-            vertices,
-            texture_vertices[0].clone()
-        ))
-        .with(Animate::new(0.2, texture_vertices))
-        .build();
+        .write_resource::<audio::SoundQueue>()
+        .push(audio::SoundEvent::Music(String::from("dungeon")));
+
+    // The next game gets a different level
+    let next_seed = world.write_resource::<rng::Rng>().next_u64();
+    world.insert(LevelSeed(next_seed));
+    Ok(())
 }
 
 fn main() {
-    // Initialize SDL and create a window
-    let (sdl_context, window, _gl_context) = init_sdl();
+    if let Err(error) = run() {
+        eprintln!("Rust Rogue stopped with an error\n{}", error);
+        std::process::exit(1);
+    }
+}
 
-    // Setup the ECS
-    let (mut world, mut dispatcher) = setup_ecs();
+fn run() -> Result<(), Error> {
+    // Initialize SDL and create a window
+    let (sdl_context, window, _gl_context) = init_sdl()?;
 
-    // Create the shader program
-    let shader_program = create_shader_program();
+    // Select real time or turn based play
+    let mode = if std::env::args().any(|arg| arg == "--turn-based") {
+        turn::GameMode::TurnBased
+    } else {
+        turn::GameMode::RealTime
+    };
 
-    // Add entities to the world
-    create_entity(&mut world, shader_program.id);
+    // Load the input bindings
+    let bindings = input::Bindings::load("./src/bindings.toml")
+        .map_err(|error| Error::config("./src/bindings.toml", error))?;
+
+    // Every texture, sprite sheet and sound is loaded through here
+    let mut assets = assets::AssetManager::new(Box::new(assets::GlBackend::new()));
+
+    // Open the audio device, the game still runs without one
+    let sounds = audio::SoundLibrary::load("./src/audio.toml", &mut assets)?;
+    let player = sdl_context
+        .audio()
+        .map_err(audio::AudioError::Device)
+        .and_then(|subsystem| audio::AudioPlayer::open(&subsystem, sounds));
+    let player = player.ok();
+
+    // Simulate in fixed steps, optionally slowed down or sped up
+    let mut timestep = timestep::Timestep::new(
+        Box::new(timestep::SystemClock::new()),
+        timestep::DEFAULT_STEP,
+        timestep::DEFAULT_MAX_STEPS
+    );
+    if let Ok(scale) = std::env::var("RUST_ROGUE_TIME_SCALE") {
+        let scale: f32 = scale.parse().map_err(|error| {
+            Error::config(
+                "RUST_ROGUE_TIME_SCALE",
+                format!("'{}' is not a number: {}", scale, error)
+            )
+        })?;
+        if !scale.is_finite() {
+            return Err(Error::config(
+                "RUST_ROGUE_TIME_SCALE",
+                format!("'{}' is not a finite number", scale)
+            ));
+        }
+        timestep.set_time_scale(scale);
+    }
 
-    // Set the projection matrix
-    let projection_id = unsafe {
-        gl::GetUniformLocation(
-            shader_program.id,
-            CString::new("projection").unwrap().as_ptr()
-        )
-    };
+    // Setup the ECS
+    let mut world = setup_ecs(mode, bindings, timestep);
+    world.insert(assets);
 
-    let aspect = 1.0;
-    let projection = Orthographic3::new(-aspect, aspect, -1.0, 1.0, -1.0, 1.0);
+    // Sounds keep playing whichever state the game is in
+    let mut audio_dispatcher = DispatcherBuilder::new()
+        .with_thread_local(component_system::systems::AudioSystem::new(player))
+        .build();
 
-    // Write the projection to the gpu
-    unsafe {
-        gl::UniformMatrix4fv(
-            projection_id,
-            1,
-            gl::FALSE,
-            projection.to_homogeneous().as_slice().as_ptr()
-        );
+    // Controllers are opened as SDL reports them plugged in
+    let mut controllers =
+        input::Controllers::new(sdl_context.game_controller().map_err(Error::Sdl)?);
+
+    // Seed the random numbers and the first level
+    let seed = match std::env::var("RUST_ROGUE_SEED") {
+        Ok(seed) => seed.parse().map_err(|error| {
+            Error::config(
+                "RUST_ROGUE_SEED",
+                format!("'{}' is not a seed: {}", seed, error)
+            )
+        })?,
+        Err(_) => std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0)
     };
+    world.insert(rng::Rng::new(seed));
+    world.insert(component_system::resources::LevelSeed(seed));
+
+    // Rasterize the HUD font, sized in pixels of the initial window
+    let glyph_atlas = text::TextGenerator::new_from_font("./src/fonts/DejaVuSans.ttf", 0, 16)
+        .and_then(|generator| generator.generate(text::PRINTABLE_ASCII));
+    let glyph_atlas = glyph_atlas?;
+    let (window_width, window_height) = window.size();
+    let text_renderer = text::TextRenderer::new(&glyph_atlas, 2.0 / window_height as f32);
+
+    // Create the shader program and draw with it
+    let renderer = render::GlRenderer::new(
+        create_shader_program()?,
+        text_renderer,
+        window_width,
+        window_height
+    );
 
     // Last Bit
     unsafe {
@@ -266,15 +391,35 @@ fn main() {
         gl::Clear(gl::COLOR_BUFFER_BIT);
     };
 
+    world.insert::<Box<dyn Renderer>>(Box::new(renderer));
+
+    // Start at the title screen
+    let mut states = StateStack::new(Box::new(move |id| create_state(mode, id)));
+    states.push(StateId::Title, &mut world)?;
+
+    // Rebuild the shaders when their files are saved
+    let hot_reload = std::env::args().any(|arg| arg == "--hot-reload");
+    let reload_interval = std::time::Duration::from_millis(500);
+    let mut last_reload_check = std::time::Instant::now();
+
     // Enter the main event loop
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut event_pump = sdl_context.event_pump().map_err(Error::Sdl)?;
     'main_loop: loop {
         // Clear the event queue
         for event in event_pump.poll_iter() {
+            // Button changes update the input actions
+            if let Some(input_event) = input::InputEvent::from_sdl(&event) {
+                world
+                    .write_resource::<input::InputState>()
+                    .handle(input_event);
+            }
+
+            // A removed controller can not release its buttons
+            if controllers.handle(&event) {
+                world.write_resource::<input::InputState>().release_all();
+            }
+
             match event {
-                Event::KeyDown {
-                    keycode: Some(key), ..
-                } => {},
                 Event::MouseButtonDown {
                     mouse_btn: button,
                     x,
@@ -282,21 +427,18 @@ fn main() {
                     ..
                 } => println!("Button Press: {}, {}, {:?}", x, y, button),
                 Event::Window { win_event, .. } => match win_event {
-                    WindowEvent::Resized(x, y) => unsafe {
-                        gl::Viewport(0, 0, x, y);
-
-                        // Compute the projection
-                        let aspect = x as f32 / y as f32;
-                        let projection = Orthographic3::new(-aspect, aspect, -1.0, 1.0, -1.0, 1.0);
-
-                        // Write the projection to the gpu
-                        shader_program.set_used();
-                        gl::UniformMatrix4fv(
-                            projection_id,
-                            1,
-                            gl::FALSE,
-                            projection.to_homogeneous().as_slice().as_ptr()
-                        );
+                    WindowEvent::Resized(x, y) => {
+                        // Show more of the world across a wider window
+                        world.write_resource::<camera::Camera>().aspect =
+                            x.max(1) as f32 / y.max(1) as f32;
+
+                        world
+                            .write_resource::<Box<dyn Renderer>>()
+                            .resize(x as u32, y as u32);
+                    },
+                    WindowEvent::FocusLost => {
+                        // Buttons released while unfocused would otherwise stay held
+                        world.write_resource::<input::InputState>().release_all();
                     },
                     _ => {}
                 },
@@ -305,26 +447,36 @@ fn main() {
             };
         }
 
-        // Update Input State
-        {
-            use component_system::resources::InputState;
-            let mut input_state = world.write_resource::<InputState>();
-            input_state.up = event_pump.keyboard_state().is_scancode_pressed(Scancode::W);
-            input_state.down = event_pump.keyboard_state().is_scancode_pressed(Scancode::S);
-            input_state.left = event_pump.keyboard_state().is_scancode_pressed(Scancode::A);
-            input_state.right = event_pump.keyboard_state().is_scancode_pressed(Scancode::D);
-            input_state.action = event_pump
-                .keyboard_state()
-                .is_scancode_pressed(Scancode::Space);
+        // Swap in edited shaders, keeping the old program if they fail to build
+        if hot_reload && last_reload_check.elapsed() >= reload_interval {
+            last_reload_check = std::time::Instant::now();
+
+            match world.write_resource::<Box<dyn Renderer>>().reload_shaders() {
+                Ok(true) => println!("Reloaded shaders"),
+                Ok(false) => {},
+                Err(message) => println!("Failed to reload shaders: {}", message)
+            }
+        }
+
+        // Update the top state, the game ends once no states are left
+        states.update(&mut world)?;
+        if states.is_empty() {
+            break 'main_loop;
         }
 
-        // Update Game States
-        dispatcher.dispatch(&mut world);
+        // Draw the top state and any it is shown over
+        states.draw(&mut world);
+        audio_dispatcher.dispatch(&world);
+
+        // Free the assets nothing uses any more
+        world.write_resource::<assets::AssetManager>().collect();
 
         // Swap the buffers
         window.gl_swap_window();
 
-        let sleep_time = std::time::Duration::from_millis(5);
+        let sleep_time = std::time::Duration::from_millis(1);
         std::thread::sleep(sleep_time);
     }
+
+    Ok(())
 }
@@ -0,0 +1,685 @@
+extern crate toml;
+
+use crate::assets::{Handle, Texture};
+use crate::atlas::{Atlas, AtlasError};
+use crate::camera::Bounds;
+use crate::gl_util::{self, Buffer, Usage, VertexArray};
+use crate::shader::{Program, UniformError};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// Tile id of an empty cell
+pub const EMPTY: u32 = 0;
+
+/// Errors that can occur while loading a tile map
+#[derive(Debug)]
+pub enum MapError {
+    /// The map file could not be read
+    Io(String),
+    /// The map file is not valid TOML
+    Parse(String),
+    /// A required field is missing
+    MissingField(String),
+    /// A field is present but has the wrong type or value
+    InvalidField {
+        field: String,
+        expected: &'static str
+    },
+    /// The tileset could not be loaded
+    Atlas(AtlasError),
+    /// A tile name does not match any frame of the tileset
+    UnknownTile(String),
+    /// A layer contains a symbol missing from the legend
+    UnknownSymbol {
+        layer: String,
+        symbol: char,
+        column: u32,
+        row: u32
+    },
+    /// A layer is not the same size as the first layer
+    LayerSize(String)
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(message) => write!(f, "Failed to read map: {}", message),
+            MapError::Parse(message) => write!(f, "Failed to parse map: {}", message),
+            MapError::MissingField(field) => write!(f, "Map is missing field '{}'", field),
+            MapError::InvalidField { field, expected } => {
+                write!(
+                    f,
+                    "Map has invalid field '{}', expected {}",
+                    field, expected
+                )
+            },
+            MapError::Atlas(error) => write!(f, "Failed to load tileset: {}", error),
+            MapError::UnknownTile(name) => write!(f, "Tileset has no tile named '{}'", name),
+            MapError::UnknownSymbol {
+                layer,
+                symbol,
+                column,
+                row
+            } => write!(
+                f,
+                "Layer '{}' has unknown symbol '{}' at column {}, row {}",
+                layer, symbol, column, row
+            ),
+            MapError::LayerSize(layer) => {
+                write!(f, "Layer '{}' does not match the size of the map", layer)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+/// A grid of tile ids, stored row by row from the top of the map
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub tiles: Vec<u32>
+}
+
+/// A tile map made up of layers drawn from a single tileset
+/// Tile ids are one more than the index of their frame in the tileset, zero is empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMap {
+    /// Number of columns
+    pub width: u32,
+    /// Number of rows
+    pub height: u32,
+    /// Width and height of a tile in world coordinates
+    pub tile_size: f32,
+    /// World coordinates of the bottom left corner of the map
+    pub origin: (f32, f32),
+    pub tileset: Atlas,
+    /// Layers in the order they are drawn
+    pub layers: Vec<Layer>,
+    /// If each tile id blocks movement
+    pub solid: Vec<bool>
+}
+
+impl TileMap {
+    /// Creates a map with a single empty layer
+    /// # Arguments
+    /// * `width` - Number of columns
+    /// * `height` - Number of rows
+    /// * `tile_size` - Size of a tile in world coordinates
+    /// * `tileset` - The sprite sheet tiles are drawn from
+    pub fn new(width: u32, height: u32, tile_size: f32, tileset: Atlas) -> TileMap {
+        let solid = vec![false; tileset.frames.len() + 1];
+
+        TileMap {
+            width,
+            height,
+            tile_size,
+            origin: (
+                -(width as f32) * tile_size / 2.0,
+                -(height as f32) * tile_size / 2.0
+            ),
+            tileset,
+            layers: vec![Layer {
+                name: String::from("ground"),
+                tiles: vec![EMPTY; (width * height) as usize]
+            }],
+            solid
+        }
+    }
+
+    /// Loads a map file along with its tileset
+    /// # Arguments
+    /// * `path` - The path of the map file
+    pub fn load(path: &str) -> Result<TileMap, MapError> {
+        let source = fs::read_to_string(path).map_err(|e| MapError::Io(e.to_string()))?;
+        let root = source
+            .parse::<toml::Value>()
+            .map_err(|e| MapError::Parse(e.to_string()))?;
+
+        // The tileset is located relative to the map
+        let tileset = match root.get("tileset") {
+            Some(toml::Value::String(tileset)) => tileset,
+            Some(_) => return Err(invalid("tileset", "a string")),
+            None => return Err(MapError::MissingField(String::from("tileset")))
+        };
+        let tileset = Path::new(path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(tileset)
+            .to_string_lossy()
+            .into_owned();
+
+        let tileset = Atlas::load(&tileset).map_err(MapError::Atlas)?;
+
+        TileMap::from_descriptor(&root, tileset)
+    }
+
+    /// Builds a map from a parsed map file and an already loaded tileset
+    /// # Arguments
+    /// * `descriptor` - The parsed map file
+    /// * `tileset` - The sprite sheet tiles are drawn from
+    pub fn from_descriptor(descriptor: &toml::Value, tileset: Atlas) -> Result<TileMap, MapError> {
+        let tile_size = match descriptor.get("tile_size") {
+            Some(toml::Value::Float(size)) if *size > 0.0 => *size as f32,
+            Some(toml::Value::Integer(size)) if *size > 0 => *size as f32,
+            Some(_) => return Err(invalid("tile_size", "a positive number")),
+            None => return Err(MapError::MissingField(String::from("tile_size")))
+        };
+
+        // Map legend symbols to tile ids
+        let mut legend = HashMap::new();
+        legend.insert(' ', EMPTY);
+        match descriptor.get("legend") {
+            Some(toml::Value::Table(table)) => {
+                for (symbol, name) in table {
+                    let mut chars = symbol.chars();
+                    let symbol = match (chars.next(), chars.next()) {
+                        (Some(symbol), None) => symbol,
+                        _ => return Err(invalid("legend", "single character keys"))
+                    };
+                    let name = name
+                        .as_str()
+                        .ok_or_else(|| invalid("legend", "tile names"))?;
+
+                    legend.insert(symbol, tile_id(&tileset, name)?);
+                }
+            },
+            Some(_) => return Err(invalid("legend", "a table")),
+            None => return Err(MapError::MissingField(String::from("legend")))
+        }
+
+        let tables = match descriptor.get("layer") {
+            Some(toml::Value::Array(tables)) if !tables.is_empty() => tables,
+            Some(_) => return Err(invalid("layer", "an array of tables")),
+            None => return Err(MapError::MissingField(String::from("layer")))
+        };
+
+        let mut layers = Vec::new();
+        let mut size = None;
+        for table in tables {
+            let name = match table.get("name") {
+                Some(toml::Value::String(name)) => name.clone(),
+                Some(_) => return Err(invalid("layer.name", "a string")),
+                None => return Err(MapError::MissingField(String::from("layer.name")))
+            };
+            let tiles = match table.get("tiles") {
+                Some(toml::Value::String(tiles)) => tiles,
+                Some(_) => return Err(invalid("layer.tiles", "a string")),
+                None => return Err(MapError::MissingField(String::from("layer.tiles")))
+            };
+
+            let (layer, width, height) = parse_layer(name, tiles, &legend)?;
+            match size {
+                Some(size) if size != (width, height) => {
+                    return Err(MapError::LayerSize(layer.name))
+                },
+                _ => size = Some((width, height))
+            }
+            layers.push(layer);
+        }
+
+        let (width, height) = size.unwrap_or((0, 0));
+        let mut map = TileMap::new(width, height, tile_size, tileset);
+        map.layers = layers;
+
+        if let Some(origin) = descriptor.get("origin") {
+            let coordinate = |value: &toml::Value| match value {
+                toml::Value::Float(value) => Some(*value as f32),
+                toml::Value::Integer(value) => Some(*value as f32),
+                _ => None
+            };
+            map.origin = match origin.as_array().map(|o| o.as_slice()) {
+                Some([x, y]) => coordinate(x).zip(coordinate(y)),
+                _ => None
+            }
+            .ok_or_else(|| invalid("origin", "an array of two numbers"))?;
+        }
+
+        match descriptor.get("solid") {
+            Some(toml::Value::Array(names)) => {
+                for name in names {
+                    let name = name
+                        .as_str()
+                        .ok_or_else(|| invalid("solid", "tile names"))?;
+                    let id = tile_id(&map.tileset, name)?;
+                    map.solid[id as usize] = true;
+                }
+            },
+            Some(_) => return Err(invalid("solid", "an array of tile names")),
+            None => {}
+        }
+
+        Ok(map)
+    }
+
+    /// Returns the id of the named tile
+    /// # Arguments
+    /// * `name` - The name of a frame in the tileset
+    pub fn tile_id(&self, name: &str) -> Result<u32, MapError> {
+        tile_id(&self.tileset, name)
+    }
+
+    /// Returns the tile id on a layer, or `EMPTY` if the cell is outside the map
+    /// # Arguments
+    /// * `layer` - Index of the layer
+    /// * `column`, `row` - Cell coordinates, row 0 is the top of the map
+    pub fn tile(&self, layer: usize, column: i32, row: i32) -> u32 {
+        match self.index(column, row) {
+            Some(index) => self.layers[layer].tiles[index],
+            None => EMPTY
+        }
+    }
+
+    /// Sets the tile id on a layer, ignoring cells outside the map
+    /// # Arguments
+    /// * `layer` - Index of the layer
+    /// * `column`, `row` - Cell coordinates, row 0 is the top of the map
+    /// * `tile` - The tile id to set
+    pub fn set_tile(&mut self, layer: usize, column: i32, row: i32, tile: u32) {
+        if let Some(index) = self.index(column, row) {
+            self.layers[layer].tiles[index] = tile;
+        }
+    }
+
+    /// Returns true if any layer has a solid tile in a cell
+    /// Cells outside of the map are always solid.
+    /// # Arguments
+    /// * `column`, `row` - Cell coordinates, row 0 is the top of the map
+    pub fn is_solid(&self, column: i32, row: i32) -> bool {
+        match self.index(column, row) {
+            Some(index) => self
+                .layers
+                .iter()
+                .any(|layer| self.solid[layer.tiles[index] as usize]),
+            None => true
+        }
+    }
+
+    /// Returns the world coordinates of the center of a cell
+    /// # Arguments
+    /// * `column`, `row` - Cell coordinates, row 0 is the top of the map
+    pub fn tile_to_world(&self, column: i32, row: i32) -> (f32, f32) {
+        (
+            self.origin.0 + (column as f32 + 0.5) * self.tile_size,
+            self.origin.1 + (self.height as f32 - row as f32 - 0.5) * self.tile_size
+        )
+    }
+
+    /// Returns the rectangle covered by the map in world coordinates
+    pub fn bounds(&self) -> Bounds {
+        Bounds {
+            min_x: self.origin.0,
+            min_y: self.origin.1,
+            max_x: self.origin.0 + self.width as f32 * self.tile_size,
+            max_y: self.origin.1 + self.height as f32 * self.tile_size
+        }
+    }
+
+    /// Returns the cell containing a point, which may lie outside the map
+    /// # Arguments
+    /// * `x`, `y` - World coordinates
+    pub fn world_to_tile(&self, x: f32, y: f32) -> (i32, i32) {
+        let column = ((x - self.origin.0) / self.tile_size).floor() as i32;
+        let row = self.height as i32 - 1 - ((y - self.origin.1) / self.tile_size).floor() as i32;
+        (column, row)
+    }
+
+    /// Returns true if a cell lies inside the map
+    pub fn contains(&self, column: i32, row: i32) -> bool {
+        self.index(column, row).is_some()
+    }
+
+    /// Returns the columns and rows of cells that overlap a rectangle in world coordinates
+    /// # Arguments
+    /// * `min` - Bottom left corner of the rectangle
+    /// * `max` - Top right corner of the rectangle
+    pub fn visible_range(&self, min: (f32, f32), max: (f32, f32)) -> (Range<u32>, Range<u32>) {
+        let (left, top) = self.world_to_tile(min.0, max.1);
+        let (right, bottom) = self.world_to_tile(max.0, min.1);
+
+        let clamp = |value: i32, limit: u32| value.max(0).min(limit as i32) as u32;
+
+        (
+            clamp(left, self.width)..clamp(right + 1, self.width),
+            clamp(top, self.height)..clamp(bottom + 1, self.height)
+        )
+    }
+
+    /// Builds vertex and texture coordinates for every non-empty tile in a range of cells
+    /// Vertices are in world coordinates so the whole range can be drawn in one call.
+    /// # Arguments
+    /// * `columns` - Columns to include
+    /// * `rows` - Rows to include
+    pub fn mesh(&self, columns: Range<u32>, rows: Range<u32>) -> (Vec<f32>, Vec<f32>) {
+        let mut vertices = Vec::new();
+        let mut texture_vertices = Vec::new();
+        let half = self.tile_size / 2.0;
+
+        for layer in 0..self.layers.len() {
+            for row in rows.clone() {
+                for column in columns.clone() {
+                    let tile = self.tile(layer, column as i32, row as i32);
+                    if tile == EMPTY {
+                        continue;
+                    }
+
+                    let (x, y) = self.tile_to_world(column as i32, row as i32);
+                    let (left, right, bottom, top) = (x - half, x + half, y - half, y + half);
+
+                    // Same winding as gl_util::create_rectangle
+                    vertices.extend_from_slice(&[
+                        left, bottom, 0.0, right, top, 0.0, left, top, 0.0, left, bottom, 0.0,
+                        right, bottom, 0.0, right, top, 0.0
+                    ]);
+                    texture_vertices.extend_from_slice(&self.tileset.frames[tile as usize - 1].uv);
+                }
+            }
+        }
+
+        (vertices, texture_vertices)
+    }
+
+    fn index(&self, column: i32, row: i32) -> Option<usize> {
+        if column < 0 || row < 0 || column >= self.width as i32 || row >= self.height as i32 {
+            None
+        } else {
+            Some((row as u32 * self.width + column as u32) as usize)
+        }
+    }
+}
+
+/// Draws the visible part of a tile map in a single draw call
+pub struct MapRenderer {
+    pub attribute_array: VertexArray,
+    pub vertex_buffer: Buffer,
+    pub texture_coord_buffer: Buffer,
+    pub texture: Handle<Texture>,
+    pub vertex_count: u32,
+    range: Option<(Range<u32>, Range<u32>)>
+}
+
+impl MapRenderer {
+    /// Creates the buffers for a map
+    /// # Arguments
+    /// * `texture` - The tileset image
+    pub fn new(texture: Handle<Texture>) -> MapRenderer {
+        let attribute_array = VertexArray::new();
+        let vertex_buffer = Buffer::new();
+        let texture_coord_buffer = Buffer::new();
+
+        attribute_array.set_attribute(0, &vertex_buffer, 3);
+        attribute_array.set_attribute(1, &texture_coord_buffer, 2);
+
+        MapRenderer {
+            attribute_array,
+            vertex_buffer,
+            texture_coord_buffer,
+            texture,
+            vertex_count: 0,
+            range: None
+        }
+    }
+
+    /// Rebuilds the tile buffers if a different set of cells has become visible
+    /// # Arguments
+    /// * `map` - The map to draw
+    /// * `min` - Bottom left corner of the view in world coordinates
+    /// * `max` - Top right corner of the view in world coordinates
+    pub fn update(&mut self, map: &TileMap, min: (f32, f32), max: (f32, f32)) {
+        let range = map.visible_range(min, max);
+        if self.range.as_ref() == Some(&range) {
+            return;
+        }
+
+        let (vertices, texture_vertices) = map.mesh(range.0.clone(), range.1.clone());
+        // Rewritten whenever the view crosses into another cell
+        self.vertex_buffer.set_data(&vertices, Usage::Dynamic);
+        self.texture_coord_buffer
+            .set_data(&texture_vertices, Usage::Dynamic);
+
+        self.vertex_count = vertices.len() as u32;
+        self.range = Some(range);
+    }
+
+    /// Marks the buffers as stale so they are rebuilt on the next update
+    pub fn invalidate(&mut self) {
+        self.range = None;
+    }
+
+    /// Issues the draw call for the map
+    /// # Arguments
+    /// * `program` - The shader program to draw with
+    pub fn draw(&self, program: &Program) -> Result<(), UniformError> {
+        program.set_used();
+        self.attribute_array.bind();
+        gl_util::bind_texture(self.texture.id);
+
+        // Vertices are already in world coordinates, a size of 2 leaves them unscaled
+        program.set_vec3("position", [0.0, 0.0, 0.0])?;
+        program.set_vec2("size", [2.0, 2.0])?;
+        program.set_sampler("texture_sampler", 0)?;
+
+        gl_util::draw_triangles(self.vertex_count / 3);
+        Ok(())
+    }
+}
+
+fn invalid(field: &str, expected: &'static str) -> MapError {
+    MapError::InvalidField {
+        field: String::from(field),
+        expected
+    }
+}
+
+fn tile_id(tileset: &Atlas, name: &str) -> Result<u32, MapError> {
+    tileset
+        .frames
+        .iter()
+        .position(|frame| frame.name == name)
+        .map(|index| index as u32 + 1)
+        .ok_or_else(|| MapError::UnknownTile(String::from(name)))
+}
+
+/// Converts the rows of symbols in a layer to tile ids
+/// Leading and trailing blank lines are ignored and short rows are padded with empty tiles.
+fn parse_layer(
+    name: String,
+    tiles: &str,
+    legend: &HashMap<char, u32>
+) -> Result<(Layer, u32, u32), MapError> {
+    let lines: Vec<&str> = tiles
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .collect();
+    let end = lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(0, |i| i + 1);
+    let lines = &lines[..end];
+
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as u32;
+    let height = lines.len() as u32;
+
+    let mut ids = Vec::with_capacity((width * height) as usize);
+    for (row, line) in lines.iter().enumerate() {
+        let mut count = 0;
+        for (column, symbol) in line.chars().enumerate() {
+            match legend.get(&symbol) {
+                Some(id) => ids.push(*id),
+                None => {
+                    return Err(MapError::UnknownSymbol {
+                        layer: name,
+                        symbol,
+                        column: column as u32,
+                        row: row as u32
+                    })
+                },
+            }
+            count += 1;
+        }
+        ids.resize(ids.len() + width as usize - count, EMPTY);
+    }
+
+    Ok((Layer { name, tiles: ids }, width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tileset() -> Atlas {
+        let descriptor = r#"
+            [[frame]]
+            name = "floor"
+            x = 0
+            y = 0
+            width = 16
+            height = 16
+
+            [[frame]]
+            name = "wall"
+            x = 16
+            y = 0
+            width = 16
+            height = 16
+        "#
+        .parse::<toml::Value>()
+        .unwrap();
+
+        Atlas::from_descriptor(&descriptor, String::from("tiles.png"), 32, 16).unwrap()
+    }
+
+    fn parse(source: &str) -> Result<TileMap, MapError> {
+        TileMap::from_descriptor(&source.parse::<toml::Value>().unwrap(), tileset())
+    }
+
+    const ROOM: &str = r##"
+        tile_size = 0.5
+        origin = [0.0, 0.0]
+        solid = ["wall"]
+
+        [legend]
+        "." = "floor"
+        "#" = "wall"
+
+        [[layer]]
+        name = "ground"
+        tiles = """
+####
+#..#
+#.
+####
+"""
+    "##;
+
+    #[test]
+    fn parses_layers_from_the_legend() {
+        let map = parse(ROOM).unwrap();
+
+        assert_eq!((map.width, map.height), (4, 4));
+        assert_eq!(map.tile_size, 0.5);
+        assert_eq!(map.layers[0].name, "ground");
+        assert_eq!(map.tile(0, 0, 0), 2);
+        assert_eq!(map.tile(0, 1, 1), 1);
+        // Short rows are padded with empty tiles
+        assert_eq!(map.tile(0, 2, 2), EMPTY);
+        assert_eq!(map.tile(0, 3, 2), EMPTY);
+
+        assert!(map.is_solid(0, 0));
+        assert!(!map.is_solid(1, 1));
+        assert!(!map.is_solid(3, 2));
+    }
+
+    #[test]
+    fn rejects_unknown_symbols_and_tiles() {
+        let source = ROOM.replace("#.\n", "#.?\n");
+        match parse(&source) {
+            Err(MapError::UnknownSymbol {
+                symbol,
+                column,
+                row,
+                ..
+            }) => assert_eq!((symbol, column, row), ('?', 2, 2)),
+            other => panic!("Expected an unknown symbol, got {:?}", other)
+        }
+
+        let source = ROOM.replace("\"wall\"]", "\"lava\"]");
+        assert!(matches!(parse(&source), Err(MapError::UnknownTile(name)) if name == "lava"));
+    }
+
+    #[test]
+    fn rejects_layers_of_different_sizes() {
+        let source = format!("{}\n[[layer]]\nname = \"top\"\ntiles = \"..\"\n", ROOM);
+        assert!(matches!(parse(&source), Err(MapError::LayerSize(name)) if name == "top"));
+    }
+
+    #[test]
+    fn rejects_missing_tile_size() {
+        let source = ROOM.replace("tile_size = 0.5", "");
+        assert!(
+            matches!(parse(&source), Err(MapError::MissingField(field)) if field == "tile_size")
+        );
+    }
+
+    #[test]
+    fn accepts_integer_and_float_origins() {
+        let map = parse(&ROOM.replace("[0.0, 0.0]", "[-2, 1.5]")).unwrap();
+        assert_eq!(map.origin, (-2.0, 1.5));
+
+        let source = ROOM.replace("[0.0, 0.0]", "[0, \"1\"]");
+        assert!(
+            matches!(parse(&source), Err(MapError::InvalidField { field, .. }) if field == "origin")
+        );
+    }
+
+    #[test]
+    fn converts_between_cells_and_world_coordinates() {
+        let map = parse(ROOM).unwrap();
+
+        // Row 0 is the top of the map, which starts at the origin
+        assert_eq!(map.tile_to_world(0, 3), (0.25, 0.25));
+        assert_eq!(map.tile_to_world(3, 0), (1.75, 1.75));
+
+        for row in -2..6 {
+            for column in -2..6 {
+                let (x, y) = map.tile_to_world(column, row);
+                assert_eq!(map.world_to_tile(x, y), (column, row));
+            }
+        }
+    }
+
+    #[test]
+    fn handles_cells_outside_the_map() {
+        let map = parse(ROOM).unwrap();
+
+        assert_eq!(map.world_to_tile(-0.1, 0.1), (-1, 3));
+        assert_eq!(map.world_to_tile(0.1, 2.1), (0, -1));
+        assert_eq!(map.world_to_tile(2.1, 0.1), (4, 3));
+
+        assert!(!map.contains(-1, 0));
+        assert!(!map.contains(0, 4));
+        assert_eq!(map.tile(0, 4, 0), EMPTY);
+        assert!(map.is_solid(-1, 2));
+
+        let mut changed = map.clone();
+        changed.set_tile(0, 4, 0, 1);
+        assert_eq!(changed.layers, map.layers);
+    }
+
+    #[test]
+    fn visible_range_is_clamped_to_the_map() {
+        let map = parse(ROOM).unwrap();
+
+        assert_eq!(map.visible_range((0.6, 0.6), (1.1, 1.1)), (1..3, 1..3));
+        assert_eq!(map.visible_range((-5.0, -5.0), (5.0, 5.0)), (0..4, 0..4));
+        assert_eq!(map.visible_range((3.0, 3.0), (4.0, 4.0)), (4..4, 0..0));
+    }
+}
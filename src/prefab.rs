@@ -0,0 +1,956 @@
+extern crate specs;
+extern crate toml;
+
+use crate::ai;
+use crate::animation::{Animator, Clip, Keyframe, PlayMode};
+use crate::assets::{AssetError, AssetManager};
+use crate::atlas::{Atlas, AtlasError, Frame};
+use crate::component_system::components::*;
+use crate::turn;
+
+use specs::prelude::*;
+use std::fmt;
+use std::fs;
+use std::time::Duration;
+
+/// Errors that can occur while loading or spawning an entity prefab
+#[derive(Debug)]
+pub enum PrefabError {
+    /// The prefab file could not be read
+    Io(String),
+    /// The prefab file is not valid TOML
+    Parse(String),
+    /// A required field is missing
+    MissingField { entity: String, field: String },
+    /// A field is present but has the wrong type or value
+    InvalidField {
+        entity: String,
+        field: String,
+        expected: &'static str
+    },
+    /// A component name is not recognised
+    UnknownComponent { entity: String, component: String },
+    /// No prefab exists with the requested name
+    UnknownPrefab(String),
+    /// A sprite sheet referenced by a prefab is invalid
+    Atlas { entity: String, error: AtlasError },
+    /// A sprite sheet or its image could not be loaded
+    Asset { entity: String, error: AssetError }
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefabError::Io(message) => write!(f, "Failed to read prefabs: {}", message),
+            PrefabError::Parse(message) => write!(f, "Failed to parse prefabs: {}", message),
+            PrefabError::MissingField { entity, field } => {
+                write!(f, "Entity '{}' is missing field '{}'", entity, field)
+            },
+            PrefabError::InvalidField {
+                entity,
+                field,
+                expected
+            } => write!(
+                f,
+                "Entity '{}' has invalid field '{}', expected {}",
+                entity, field, expected
+            ),
+            PrefabError::UnknownComponent { entity, component } => {
+                write!(
+                    f,
+                    "Entity '{}' has unknown component '{}'",
+                    entity, component
+                )
+            },
+            PrefabError::UnknownPrefab(name) => write!(f, "No prefab named '{}'", name),
+            PrefabError::Atlas { entity, error } => write!(f, "Entity '{}': {}", entity, error),
+            PrefabError::Asset { entity, error } => write!(f, "Entity '{}': {}", entity, error)
+        }
+    }
+}
+
+impl std::error::Error for PrefabError {}
+
+/// Description of a single component and its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentDef {
+    Position {
+        x: f32,
+        y: f32,
+        z: f32
+    },
+    Velocity {
+        x: f32,
+        y: f32,
+        z: f32
+    },
+    Size {
+        width: f32,
+        height: f32
+    },
+    /// Collides with solid tiles and other colliders, sized like the entity if no size is given
+    Collider {
+        size: Option<(f32, f32)>
+    },
+    /// Draws a frame from a sprite sheet, the first frame if none is named
+    Drawn {
+        atlas: String,
+        frame: Option<String>
+    },
+    /// Plays named clips made of frames from the drawn sprite sheet
+    Animate {
+        initial: String,
+        clips: Vec<ClipDef>
+    },
+    /// Takes turns in turn based mode
+    Actor {
+        speed: u32
+    },
+    Controlled,
+    /// Dies once its health runs out, leaving a corpse if asked to
+    Health {
+        max: u32,
+        corpse: bool
+    },
+    /// Hits enemies in melee
+    Attack {
+        power: u32,
+        crit_chance: f32,
+        crit_multiplier: f32,
+        cooldown: f32
+    },
+    Defense {
+        armour: u32
+    },
+    /// Fights the controlled entity
+    Hostile,
+    /// Moves on its own, chasing the player when it sees them
+    Brain {
+        sight: u32,
+        flee_below: f32,
+        wanders: bool,
+        speed: f32
+    }
+}
+
+/// Description of an animation clip
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipDef {
+    pub name: String,
+    /// Names of the frames in the clip, all frames of the sheet if none are named
+    pub frames: Option<Vec<String>>,
+    /// Seconds each frame is shown for, a single value applies to every frame
+    pub durations: Vec<f32>,
+    pub mode: PlayMode
+}
+
+/// A named template an entity can be built from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prefab {
+    pub name: String,
+    pub components: Vec<ComponentDef>
+}
+
+impl Prefab {
+    /// Builds an entity from this prefab
+    /// Sprite sheets are loaded through the world's `AssetManager`.
+    /// # Arguments
+    /// * `world` - The world to create the entity in
+    pub fn spawn(&self, world: &mut World) -> Result<Entity, PrefabError> {
+        // Load the sprite sheet first since drawn and animate share it
+        let mut sheet = None;
+        for component in &self.components {
+            if let ComponentDef::Drawn { atlas: path, .. } = component {
+                let mut assets = world.write_resource::<AssetManager>();
+                let atlas = assets.load_atlas(path).map_err(|e| self.asset_error(e))?;
+                let texture = assets
+                    .load_texture(&atlas.image)
+                    .map_err(|e| self.asset_error(e))?;
+                sheet = Some((atlas, texture));
+            }
+        }
+
+        let mut builder = world.create_entity();
+
+        for component in &self.components {
+            builder = match (component, &sheet) {
+                (ComponentDef::Position { x, y, z }, _) => {
+                    builder.with(Position::new_xyz(*x, *y, *z))
+                },
+                (ComponentDef::Velocity { x, y, z }, _) => builder.with(Velocity {
+                    x: *x,
+                    y: *y,
+                    z: *z
+                }),
+                (ComponentDef::Size { width, height }, _) => {
+                    builder.with(Size::new(*width, *height))
+                },
+                (ComponentDef::Collider { size }, _) => builder.with(Collider { size: *size }),
+                (ComponentDef::Drawn { frame, .. }, Some((atlas, texture))) => {
+                    let frame = match frame {
+                        Some(name) => atlas.frame(name).map_err(|e| self.atlas_error(e))?,
+                        None => &atlas.frames[0]
+                    };
+                    builder.with(Drawn::new(texture.clone(), frame))
+                },
+                (ComponentDef::Animate { initial, clips }, Some((atlas, _))) => {
+                    let (frames, animator) = self.build_animator(atlas, initial, clips)?;
+                    builder.with(Animate::new(&frames, animator))
+                },
+                (ComponentDef::Actor { speed }, _) => builder.with(Actor::new(*speed)),
+                (ComponentDef::Controlled, _) => builder.with(Controlled),
+                (ComponentDef::Health { max, corpse }, _) => {
+                    builder.with(Health::new(*max, *corpse))
+                },
+                (
+                    ComponentDef::Attack {
+                        power,
+                        crit_chance,
+                        crit_multiplier,
+                        cooldown
+                    },
+                    _
+                ) => {
+                    let mut stats = AttackStats::new(*power, *crit_chance, *crit_multiplier);
+                    stats.cooldown = Duration::from_secs_f32(*cooldown);
+                    builder.with(stats)
+                },
+                (ComponentDef::Defense { armour }, _) => builder.with(Defense { armour: *armour }),
+                (ComponentDef::Hostile, _) => builder.with(Hostile),
+                (
+                    ComponentDef::Brain {
+                        sight,
+                        flee_below,
+                        wanders,
+                        speed
+                    },
+                    _
+                ) => builder.with(Brain::new(*sight, *flee_below, *wanders, *speed)),
+                // Prefabs are validated when parsed so this can not happen
+                (_, None) => builder
+            };
+        }
+
+        Ok(builder.build())
+    }
+
+    /// Builds an entity from this prefab at a position, replacing any position it declares
+    /// # Arguments
+    /// * `world` - The world to create the entity in
+    /// * `x`, `y` - Position of the entity in world coordinates
+    pub fn spawn_at(&self, world: &mut World, x: f32, y: f32) -> Result<Entity, PrefabError> {
+        let entity = self.spawn(world)?;
+
+        let mut position = world.write_storage::<Position>();
+        let z = position.get(entity).map_or(0.0, |position| position.z);
+        position
+            .insert(entity, Position::new_xyz(x, y, z))
+            .expect("Entity was just created");
+
+        Ok(entity)
+    }
+
+    /// Resolves clip frame names against a sprite sheet
+    /// Returns the frames used by any clip along with an animator indexing into them.
+    fn build_animator(
+        &self,
+        atlas: &Atlas,
+        initial: &str,
+        clips: &[ClipDef]
+    ) -> Result<(Vec<Frame>, Animator), PrefabError> {
+        let mut names: Vec<String> = Vec::new();
+        let mut built = Vec::new();
+
+        for clip in clips {
+            let clip_names = match &clip.frames {
+                Some(frames) => frames.clone(),
+                None => atlas.frames.iter().map(|f| f.name.clone()).collect()
+            };
+
+            if clip.durations.len() != 1 && clip.durations.len() != clip_names.len() {
+                return Err(PrefabError::InvalidField {
+                    entity: self.name.clone(),
+                    field: format!("animate.clip.{}.durations", clip.name),
+                    expected: "one duration or one per frame"
+                });
+            }
+
+            let mut keyframes = Vec::new();
+            for (i, name) in clip_names.iter().enumerate() {
+                // Frames shared between clips only get one buffer
+                let index = match names.iter().position(|n| n == name) {
+                    Some(index) => index,
+                    None => {
+                        names.push(name.clone());
+                        names.len() - 1
+                    }
+                };
+
+                let duration = clip.durations[i.min(clip.durations.len() - 1)];
+                keyframes.push(Keyframe {
+                    index,
+                    duration: Duration::from_secs_f32(duration)
+                });
+            }
+
+            built.push(Clip {
+                name: clip.name.clone(),
+                keyframes,
+                mode: clip.mode
+            });
+        }
+
+        let frames = atlas.select(&names).map_err(|e| self.atlas_error(e))?;
+        let animator = Animator::new(built, initial).ok_or_else(|| PrefabError::InvalidField {
+            entity: self.name.clone(),
+            field: String::from("animate.initial"),
+            expected: "the name of a clip with frames"
+        })?;
+
+        Ok((frames, animator))
+    }
+
+    fn atlas_error(&self, error: AtlasError) -> PrefabError {
+        PrefabError::Atlas {
+            entity: self.name.clone(),
+            error
+        }
+    }
+
+    fn asset_error(&self, error: AssetError) -> PrefabError {
+        PrefabError::Asset {
+            entity: self.name.clone(),
+            error
+        }
+    }
+}
+
+/// Reads a prefab file from disk
+/// # Arguments
+/// * `path` - The path of the prefab file
+pub fn load_prefabs(path: &str) -> Result<Vec<Prefab>, PrefabError> {
+    let source = fs::read_to_string(path).map_err(|e| PrefabError::Io(e.to_string()))?;
+    parse_prefabs(&source)
+}
+
+/// Parses all `[[entity]]` blocks from a prefab file
+/// # Arguments
+/// * `source` - The contents of the prefab file
+pub fn parse_prefabs(source: &str) -> Result<Vec<Prefab>, PrefabError> {
+    let root = source
+        .parse::<toml::Value>()
+        .map_err(|e| PrefabError::Parse(e.to_string()))?;
+
+    let entities = match root.get("entity") {
+        Some(toml::Value::Array(entities)) => entities,
+        Some(_) => {
+            return Err(PrefabError::Parse(String::from(
+                "'entity' must be an array of tables"
+            )))
+        },
+        None => return Ok(vec![])
+    };
+
+    entities.iter().map(parse_prefab).collect()
+}
+
+/// Finds a prefab by name
+/// # Arguments
+/// * `prefabs` - The prefabs to search
+/// * `name` - The name of the prefab to find
+pub fn find<'a>(prefabs: &'a [Prefab], name: &str) -> Result<&'a Prefab, PrefabError> {
+    prefabs
+        .iter()
+        .find(|prefab| prefab.name == name)
+        .ok_or_else(|| PrefabError::UnknownPrefab(String::from(name)))
+}
+
+/// Parses a single `[[entity]]` block
+fn parse_prefab(value: &toml::Value) -> Result<Prefab, PrefabError> {
+    let name = match value.get("name") {
+        Some(toml::Value::String(name)) => name.clone(),
+        Some(_) => {
+            return Err(PrefabError::InvalidField {
+                entity: String::from("?"),
+                field: String::from("name"),
+                expected: "a string"
+            })
+        },
+        None => {
+            return Err(PrefabError::MissingField {
+                entity: String::from("?"),
+                field: String::from("name")
+            })
+        },
+    };
+
+    let names = match value.get("components") {
+        Some(toml::Value::Array(names)) => names,
+        Some(_) => {
+            return Err(PrefabError::InvalidField {
+                entity: name,
+                field: String::from("components"),
+                expected: "an array of strings"
+            })
+        },
+        None => {
+            return Err(PrefabError::MissingField {
+                entity: name,
+                field: String::from("components")
+            })
+        },
+    };
+
+    let mut components = Vec::new();
+    for component in names {
+        let component = component
+            .as_str()
+            .ok_or_else(|| PrefabError::InvalidField {
+                entity: name.clone(),
+                field: String::from("components"),
+                expected: "an array of strings"
+            })?;
+
+        let fields = Fields {
+            entity: &name,
+            component,
+            table: value.get(component)
+        };
+
+        components.push(match component {
+            "position" => ComponentDef::Position {
+                x: fields.float("x", 0.0)?,
+                y: fields.float("y", 0.0)?,
+                z: fields.float("z", 0.0)?
+            },
+            "velocity" => ComponentDef::Velocity {
+                x: fields.float("x", 0.0)?,
+                y: fields.float("y", 0.0)?,
+                z: fields.float("z", 0.0)?
+            },
+            "size" => ComponentDef::Size {
+                width: fields.float("width", 10.0)?,
+                height: fields.float("height", 10.0)?
+            },
+            "collider" => ComponentDef::Collider {
+                size: match (fields.get("width"), fields.get("height")) {
+                    (None, None) => None,
+                    _ => Some((fields.float("width", 0.0)?, fields.float("height", 0.0)?))
+                }
+            },
+            "drawn" => ComponentDef::Drawn {
+                atlas: fields.string("atlas")?,
+                frame: fields.optional_string("frame")?
+            },
+            "animate" => parse_animate(&fields)?,
+            "actor" => {
+                let speed = fields.unsigned("speed", 100)?;
+                if speed > turn::MAX_SPEED {
+                    return Err(fields.invalid("speed", "a speed from 0 to 10000"));
+                }
+                ComponentDef::Actor { speed }
+            },
+            "controlled" => ComponentDef::Controlled,
+            "health" => ComponentDef::Health {
+                max: fields.unsigned("max", 10)?,
+                corpse: fields.boolean("corpse", false)?
+            },
+            "attack" => {
+                let crit_chance = fields.float("crit_chance", 0.0)?;
+                if !(0.0..=1.0).contains(&crit_chance) {
+                    return Err(fields.invalid("crit_chance", "a number from 0 to 1"));
+                }
+
+                let cooldown = fields.float("cooldown", 0.5)?;
+                if !is_seconds(cooldown) {
+                    return Err(fields.invalid("cooldown", "a non-negative number of seconds"));
+                }
+
+                let crit_multiplier = fields.float("crit_multiplier", 2.0)?;
+                if !crit_multiplier.is_finite() || crit_multiplier < 1.0 {
+                    return Err(fields.invalid("crit_multiplier", "a number of at least 1"));
+                }
+
+                ComponentDef::Attack {
+                    power: fields.unsigned("power", 1)?,
+                    crit_chance,
+                    crit_multiplier,
+                    cooldown
+                }
+            },
+            "defense" => ComponentDef::Defense {
+                armour: fields.unsigned("armour", 0)?
+            },
+            "hostile" => ComponentDef::Hostile,
+            "brain" => {
+                let flee_below = fields.float("flee_below", 0.25)?;
+                if !(0.0..=1.0).contains(&flee_below) {
+                    return Err(fields.invalid("flee_below", "a number from 0 to 1"));
+                }
+
+                let sight = fields.unsigned("sight", 6)?;
+                if sight > ai::MAX_SIGHT {
+                    return Err(fields.invalid("sight", "a number of cells from 0 to 100"));
+                }
+
+                let speed = fields.float("speed", 0.5)?;
+                if !speed.is_finite() || speed < 0.0 {
+                    return Err(fields.invalid("speed", "a non-negative number"));
+                }
+
+                ComponentDef::Brain {
+                    sight,
+                    flee_below,
+                    wanders: fields.boolean("wanders", true)?,
+                    speed
+                }
+            },
+            _ => {
+                return Err(PrefabError::UnknownComponent {
+                    entity: name,
+                    component: String::from(component)
+                })
+            },
+        });
+    }
+
+    // Animation frames come from the drawn component
+    let drawn = components
+        .iter()
+        .any(|c| matches!(c, ComponentDef::Drawn { .. }));
+    let animate = components
+        .iter()
+        .any(|c| matches!(c, ComponentDef::Animate { .. }));
+    if animate && !drawn {
+        return Err(PrefabError::MissingField {
+            entity: name,
+            field: String::from("drawn")
+        });
+    }
+
+    Ok(Prefab { name, components })
+}
+
+/// Parses the clips of an animate component
+/// Without any `[[entity.animate.clip]]` tables a single looping idle clip is made from the
+/// `speed` and `frames` fields.
+fn parse_animate(fields: &Fields) -> Result<ComponentDef, PrefabError> {
+    let tables = match fields.get("clip") {
+        Some(toml::Value::Array(tables)) => tables.iter().collect(),
+        Some(_) => return Err(fields.invalid("clip", "an array of tables")),
+        None => vec![]
+    };
+
+    if tables.is_empty() {
+        return Ok(ComponentDef::Animate {
+            initial: String::from("idle"),
+            clips: vec![ClipDef {
+                name: String::from("idle"),
+                frames: fields.optional_strings("frames")?,
+                durations: vec![fields.float("speed", 0.2)?],
+                mode: PlayMode::Loop
+            }]
+        });
+    }
+
+    let mut clips = Vec::new();
+    for table in tables {
+        let clip = Fields {
+            entity: fields.entity,
+            component: "animate.clip",
+            table: Some(table)
+        };
+
+        let durations = match clip.get("durations") {
+            Some(toml::Value::Array(values)) => values
+                .iter()
+                .map(|value| match value {
+                    toml::Value::Float(value) => Ok(*value as f32),
+                    toml::Value::Integer(value) => Ok(*value as f32),
+                    _ => Err(clip.invalid("durations", "an array of numbers"))
+                })
+                .collect::<Result<Vec<f32>, PrefabError>>()?,
+            Some(_) => return Err(clip.invalid("durations", "an array of numbers")),
+            None => vec![clip.float("duration", 0.2)?]
+        };
+
+        if durations.is_empty() || !durations.iter().all(|d| is_seconds(*d)) {
+            return Err(clip.invalid("durations", "non-negative numbers of seconds"));
+        }
+
+        let mode = match clip.optional_string("mode")? {
+            Some(mode) => PlayMode::from_name(&mode)
+                .ok_or_else(|| clip.invalid("mode", "'loop', 'once' or 'ping_pong'"))?,
+            None => PlayMode::Loop
+        };
+
+        clips.push(ClipDef {
+            name: clip.string("name")?,
+            frames: clip.optional_strings("frames")?,
+            durations,
+            mode
+        });
+    }
+
+    let initial = match fields.optional_string("initial")? {
+        Some(initial) => initial,
+        None => clips[0].name.clone()
+    };
+
+    Ok(ComponentDef::Animate { initial, clips })
+}
+
+/// Returns true if a number of seconds can be made into a `Duration`
+/// NaN, infinite, negative and overly large values would make `Duration::from_secs_f32` panic.
+fn is_seconds(seconds: f32) -> bool {
+    seconds.is_finite() && seconds >= 0.0 && Duration::try_from_secs_f32(seconds).is_ok()
+}
+
+/// Parameter table for a single component of a prefab
+struct Fields<'a> {
+    entity: &'a str,
+    component: &'a str,
+    table: Option<&'a toml::Value>
+}
+
+impl<'a> Fields<'a> {
+    fn get(&self, field: &str) -> Option<&'a toml::Value> {
+        self.table.and_then(|table| table.get(field))
+    }
+
+    fn invalid(&self, field: &str, expected: &'static str) -> PrefabError {
+        PrefabError::InvalidField {
+            entity: String::from(self.entity),
+            field: format!("{}.{}", self.component, field),
+            expected
+        }
+    }
+
+    /// Reads a number, accepting both floats and integers
+    fn float(&self, field: &str, default: f32) -> Result<f32, PrefabError> {
+        match self.get(field) {
+            Some(toml::Value::Float(value)) => Ok(*value as f32),
+            Some(toml::Value::Integer(value)) => Ok(*value as f32),
+            Some(_) => Err(self.invalid(field, "a number")),
+            None => Ok(default)
+        }
+    }
+
+    fn unsigned(&self, field: &str, default: u32) -> Result<u32, PrefabError> {
+        match self.get(field) {
+            Some(toml::Value::Integer(value)) if *value >= 0 && *value <= u32::MAX as i64 => {
+                Ok(*value as u32)
+            },
+            Some(_) => Err(self.invalid(field, "a non-negative integer")),
+            None => Ok(default)
+        }
+    }
+
+    fn boolean(&self, field: &str, default: bool) -> Result<bool, PrefabError> {
+        match self.get(field) {
+            Some(toml::Value::Boolean(value)) => Ok(*value),
+            Some(_) => Err(self.invalid(field, "true or false")),
+            None => Ok(default)
+        }
+    }
+
+    fn string(&self, field: &str) -> Result<String, PrefabError> {
+        match self.get(field) {
+            Some(toml::Value::String(value)) => Ok(value.clone()),
+            Some(_) => Err(self.invalid(field, "a string")),
+            None => Err(PrefabError::MissingField {
+                entity: String::from(self.entity),
+                field: format!("{}.{}", self.component, field)
+            })
+        }
+    }
+
+    fn optional_string(&self, field: &str) -> Result<Option<String>, PrefabError> {
+        match self.get(field) {
+            Some(toml::Value::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(self.invalid(field, "a string")),
+            None => Ok(None)
+        }
+    }
+
+    fn optional_strings(&self, field: &str) -> Result<Option<Vec<String>>, PrefabError> {
+        match self.get(field) {
+            Some(toml::Value::Array(values)) => values
+                .iter()
+                .map(|value| {
+                    value
+                        .as_str()
+                        .map(String::from)
+                        .ok_or_else(|| self.invalid(field, "an array of strings"))
+                })
+                .collect::<Result<Vec<String>, PrefabError>>()
+                .map(Some),
+            Some(_) => Err(self.invalid(field, "an array of strings")),
+            None => Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = include_str!("config.toml");
+
+    #[test]
+    fn parses_player_from_config() {
+        let prefabs = parse_prefabs(CONFIG).unwrap();
+        let player = find(&prefabs, "player").unwrap();
+
+        assert_eq!(player.components.len(), 11);
+        assert_eq!(
+            player.components[2],
+            ComponentDef::Size {
+                width: 0.15,
+                height: 0.15
+            }
+        );
+        assert_eq!(
+            player.components[3],
+            ComponentDef::Collider {
+                size: Some((0.08, 0.08))
+            }
+        );
+        assert_eq!(
+            player.components[4],
+            ComponentDef::Drawn {
+                atlas: String::from("./src/animations/ogre_idle_animation.toml"),
+                frame: None
+            }
+        );
+        assert_eq!(player.components[6], ComponentDef::Actor { speed: 100 });
+        assert_eq!(player.components[7], ComponentDef::Controlled);
+
+        match &player.components[5] {
+            ComponentDef::Animate { initial, clips } => {
+                assert_eq!(initial, "idle");
+                let names: Vec<&str> = clips.iter().map(|clip| clip.name.as_str()).collect();
+                assert_eq!(names, ["idle", "walk", "hurt"]);
+                assert_eq!(clips[1].durations, [0.1]);
+                assert_eq!(clips[2].mode, PlayMode::Once);
+            },
+            other => panic!("Expected animate, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn parses_chest_from_config() {
+        let prefabs = parse_prefabs(CONFIG).unwrap();
+        let chest = find(&prefabs, "chest").unwrap();
+
+        assert_eq!(
+            chest.components,
+            vec![
+                ComponentDef::Position {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0
+                },
+                ComponentDef::Size {
+                    width: 0.08,
+                    height: 0.08
+                },
+                ComponentDef::Collider { size: None },
+                ComponentDef::Drawn {
+                    atlas: String::from("./src/frames/chest_empty_open_anim_f0.toml"),
+                    frame: None
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_component() {
+        let source = r#"
+            [[entity]]
+            name = "ghost"
+            components = ["position", "haunting"]
+        "#;
+
+        match parse_prefabs(source) {
+            Err(PrefabError::UnknownComponent { entity, component }) => {
+                assert_eq!(entity, "ghost");
+                assert_eq!(component, "haunting");
+            },
+            other => panic!("Expected an unknown component, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rejects_bad_field_type() {
+        let source = r#"
+            [[entity]]
+            name = "crate"
+            components = ["size"]
+
+            [entity.size]
+            width = "wide"
+        "#;
+
+        match parse_prefabs(source) {
+            Err(PrefabError::InvalidField {
+                entity,
+                field,
+                expected
+            }) => {
+                assert_eq!(entity, "crate");
+                assert_eq!(field, "size.width");
+                assert_eq!(expected, "a number");
+            },
+            other => panic!("Expected an invalid field, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rejects_durations_that_are_not_seconds() {
+        for duration in ["nan", "inf", "-0.5", "1e30"] {
+            let source = format!(
+                r#"
+                [[entity]]
+                name = "torch"
+                components = ["drawn", "animate"]
+
+                [entity.drawn]
+                atlas = "torch.toml"
+
+                [[entity.animate.clip]]
+                name = "burn"
+                durations = [0.1, {}]
+                "#,
+                duration
+            );
+
+            assert!(
+                matches!(
+                    parse_prefabs(&source),
+                    Err(PrefabError::InvalidField { field, .. }) if field == "animate.clip.durations"
+                ),
+                "{} was accepted",
+                duration
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_crit_multipliers_below_one() {
+        let source = |multiplier: &str| {
+            format!(
+                "[[entity]]\nname = \"bat\"\ncomponents = [\"attack\"]\n\n[entity.attack]\ncrit_multiplier = {}\n",
+                multiplier
+            )
+        };
+
+        assert!(parse_prefabs(&source("1.0")).is_ok());
+        for multiplier in &["0.5", "-2.0", "nan", "inf"] {
+            assert!(
+                matches!(
+                    parse_prefabs(&source(multiplier)),
+                    Err(PrefabError::InvalidField { field, .. }) if field == "attack.crit_multiplier"
+                ),
+                "{} was accepted",
+                multiplier
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_brains_that_can_not_think() {
+        let source = |field: &str, value: &str| {
+            format!(
+                "[[entity]]\nname = \"bat\"\ncomponents = [\"brain\"]\n\n[entity.brain]\n{} = {}\n",
+                field, value
+            )
+        };
+
+        assert!(parse_prefabs(&source("sight", &ai::MAX_SIGHT.to_string())).is_ok());
+        assert!(parse_prefabs(&source("speed", "0.0")).is_ok());
+        for (field, value) in &[
+            ("sight", "101"),
+            ("sight", "4294967295"),
+            ("speed", "-0.5"),
+            ("speed", "nan"),
+            ("speed", "inf")
+        ] {
+            assert!(
+                matches!(
+                    parse_prefabs(&source(field, value)),
+                    Err(PrefabError::InvalidField { field: found, .. }) if found == format!("brain.{}", field)
+                ),
+                "{} = {} was accepted",
+                field,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_speeds_too_fast_to_schedule() {
+        let source = |speed: u32| {
+            format!(
+                "[[entity]]\nname = \"bat\"\ncomponents = [\"actor\"]\n\n[entity.actor]\nspeed = {}\n",
+                speed
+            )
+        };
+
+        assert!(parse_prefabs(&source(turn::MAX_SPEED)).is_ok());
+        assert!(matches!(
+            parse_prefabs(&source(turn::MAX_SPEED + 1)),
+            Err(PrefabError::InvalidField { field, .. }) if field == "actor.speed"
+        ));
+        assert!(parse_prefabs(&source(u32::MAX)).is_err());
+    }
+
+    #[test]
+    fn rejects_cooldowns_that_are_not_seconds() {
+        for cooldown in ["nan", "inf", "-0.5", "1e30"] {
+            let source = format!(
+                r#"
+                [[entity]]
+                name = "ogre"
+                components = ["attack"]
+
+                [entity.attack]
+                power = 3
+                cooldown = {}
+                "#,
+                cooldown
+            );
+
+            assert!(
+                matches!(
+                    parse_prefabs(&source),
+                    Err(PrefabError::InvalidField { field, .. }) if field == "attack.cooldown"
+                ),
+                "{} was accepted",
+                cooldown
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_missing_name() {
+        let source = r#"
+            [[entity]]
+            components = ["position"]
+        "#;
+
+        assert!(matches!(
+            parse_prefabs(source),
+            Err(PrefabError::MissingField { field, .. }) if field == "name"
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_prefab() {
+        let prefabs = parse_prefabs(CONFIG).unwrap();
+        assert!(matches!(
+            find(&prefabs, "dragon"),
+            Err(PrefabError::UnknownPrefab(name)) if name == "dragon"
+        ));
+    }
+}
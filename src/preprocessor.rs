@@ -0,0 +1,505 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name reported for lines added by `#define` injection
+const DEFINES_FILE: &str = "<defines>";
+
+/// Errors raised while preprocessing shader source
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreprocessError {
+    /// A source file could not be read
+    Io { path: String, message: String },
+    /// An `#include` line is not followed by a quoted file name
+    Include { path: String, line: usize },
+    /// A file includes itself, directly or through other files
+    Cycle(Vec<String>)
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreprocessError::Io { path, message } => {
+                write!(f, "Failed to read {}: {}", path, message)
+            },
+            PreprocessError::Include { path, line } => {
+                write!(f, "{}:{}: expected #include \"file\"", path, line)
+            },
+            PreprocessError::Cycle(chain) => {
+                write!(f, "Include cycle: {}", chain.join(" -> "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+/// Shader source with its includes expanded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Processed {
+    /// The source to hand to the compiler
+    pub code: String,
+    /// Every file read, starting with the root file
+    pub files: Vec<String>,
+    /// File index and line number of each line of `code`, 0 for the file means a define
+    lines: Vec<(usize, usize)>
+}
+
+impl Processed {
+    /// Finds where a line of the processed code came from
+    /// # Arguments
+    /// * `line` - Line number in the processed code, starting from 1
+    pub fn origin(&self, line: usize) -> Option<(&str, usize)> {
+        let (file, original) = *self.lines.get(line.checked_sub(1)?)?;
+        if file == 0 {
+            Some((DEFINES_FILE, original))
+        } else {
+            Some((&self.files[file - 1], original))
+        }
+    }
+
+    /// Rewrites the line numbers in a compiler log to the files and lines they came from
+    /// Understands the `0:12(5):` style of Mesa, the `ERROR: 0:12:` style of AMD and Intel and
+    /// the `0(12) :` style of NVIDIA. Lines in any other format are left as they are.
+    /// # Arguments
+    /// * `log` - The shader info log
+    pub fn remap_log(&self, log: &str) -> String {
+        log.lines()
+            .map(|line| self.remap_line(line))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn remap_line(&self, line: &str) -> String {
+        let (start, end, number) = match find_location(line) {
+            Some(location) => location,
+            None => return String::from(line)
+        };
+
+        match self.origin(number) {
+            Some((file, original)) => {
+                format!("{}{}:{}{}", &line[..start], file, original, &line[end..])
+            },
+            None => String::from(line)
+        }
+    }
+}
+
+/// Finds a `0:12` or `0(12)` source string and line number near the start of a log line
+/// Returns the byte range of the location and the line number.
+fn find_location(line: &str) -> Option<(usize, usize, usize)> {
+    let prefix = ["ERROR: ", "WARNING: ", ""]
+        .iter()
+        .find(|prefix| line.starts_with(*prefix))
+        .map(|prefix| prefix.len())?;
+
+    let rest = &line[prefix..];
+    let source_digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    if source_digits == 0 {
+        return None;
+    }
+
+    let separator = rest[source_digits..].chars().next()?;
+    if separator != ':' && separator != '(' {
+        return None;
+    }
+    let numbers = &rest[source_digits + 1..];
+    let line_digits = numbers.chars().take_while(|c| c.is_ascii_digit()).count();
+    if line_digits == 0 {
+        return None;
+    }
+    let number = numbers[..line_digits].parse().ok()?;
+
+    let end = match separator {
+        ':' => source_digits + 1 + line_digits,
+        '(' if numbers[line_digits..].starts_with(')') => source_digits + 1 + line_digits + 1,
+        _ => return None
+    };
+
+    Some((prefix, prefix + end, number))
+}
+
+/// Preprocesses a shader file read from disk
+/// # Arguments
+/// * `path` - The root shader file, includes are resolved relative to its directory
+/// * `defines` - Names and values to `#define` at the top of the shader
+pub fn preprocess_file(path: &str, defines: &[(&str, &str)]) -> Result<Processed, PreprocessError> {
+    preprocess(path, defines, |path| {
+        fs::read_to_string(path).map_err(|message| message.to_string())
+    })
+}
+
+/// Expands `#include "file"` lines and injects `#define` lines after `#version`
+/// # Arguments
+/// * `path` - The root shader file, includes are resolved relative to its directory
+/// * `defines` - Names and values to `#define` at the top of the shader
+/// * `read` - Reads a file, returning its contents or an error message
+pub fn preprocess<F>(
+    path: &str,
+    defines: &[(&str, &str)],
+    read: F
+) -> Result<Processed, PreprocessError>
+where
+    F: Fn(&Path) -> Result<String, String>
+{
+    let root = PathBuf::from(path);
+    let directory = root.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let mut processed = Processed {
+        code: String::new(),
+        files: vec![],
+        lines: vec![]
+    };
+
+    let mut expander = Expander {
+        directory,
+        read,
+        processed: &mut processed,
+        stack: vec![],
+        defines,
+        injected: false
+    };
+    expander.expand(&root)?;
+
+    // A shader without a version line gets its defines at the very top
+    if !expander.injected {
+        let mut code = String::new();
+        let mut lines = vec![];
+        for (index, (name, value)) in defines.iter().enumerate() {
+            code.push_str(&define_line(name, value));
+            lines.push((0, index + 1));
+        }
+        processed.code.insert_str(0, &code);
+        processed.lines.splice(0..0, lines);
+    }
+
+    Ok(processed)
+}
+
+/// Recursively copies files into the processed source
+struct Expander<'a, F> {
+    directory: PathBuf,
+    read: F,
+    processed: &'a mut Processed,
+    /// Files currently being expanded, to detect cycles
+    stack: Vec<String>,
+    defines: &'a [(&'a str, &'a str)],
+    /// Whether the defines have been written yet
+    injected: bool
+}
+
+impl<'a, F> Expander<'a, F>
+where
+    F: Fn(&Path) -> Result<String, String>
+{
+    fn expand(&mut self, path: &Path) -> Result<(), PreprocessError> {
+        let name = path.to_string_lossy().into_owned();
+        if self.stack.contains(&name) {
+            let mut chain = self.stack.clone();
+            chain.push(name);
+            return Err(PreprocessError::Cycle(chain));
+        }
+
+        let source = (self.read)(path).map_err(|message| PreprocessError::Io {
+            path: name.clone(),
+            message
+        })?;
+
+        self.processed.files.push(name.clone());
+        let file = self.processed.files.len();
+        self.stack.push(name.clone());
+
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let trimmed = line.trim_start();
+
+            if let Some(rest) = directive(trimmed, "include") {
+                let include = quoted(rest).ok_or(PreprocessError::Include {
+                    path: name.clone(),
+                    line: number
+                })?;
+                let include = self.directory.join(include);
+                self.expand(&include)?;
+                continue;
+            }
+
+            self.push_line(line, file, number);
+
+            // Defines must come after the version, which has to be the first statement
+            if !self.injected && directive(trimmed, "version").is_some() {
+                for (index, (define, value)) in self.defines.iter().enumerate() {
+                    self.push_line(define_line(define, value).trim_end(), 0, index + 1);
+                }
+                self.injected = true;
+            }
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn push_line(&mut self, line: &str, file: usize, number: usize) {
+        self.processed.code.push_str(line);
+        self.processed.code.push('\n');
+        self.processed.lines.push((file, number));
+    }
+}
+
+/// Returns the rest of a line if it is the given preprocessor directive
+fn directive<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix(name)?;
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) || rest.starts_with('"') {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
+
+/// Returns the text between a pair of double quotes
+fn quoted(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix('"')?;
+    let end = rest.find('"')?;
+    if end == 0 || !rest[end + 1..].trim().is_empty() {
+        return None;
+    }
+    Some(&rest[..end])
+}
+
+fn define_line(name: &str, value: &str) -> String {
+    if value.is_empty() {
+        format!("#define {}\n", name)
+    } else {
+        format!("#define {} {}\n", name, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Preprocesses `shaders/main.frag` from in memory files
+    fn run(files: &[(&str, &str)], defines: &[(&str, &str)]) -> Result<Processed, PreprocessError> {
+        let files: HashMap<PathBuf, &str> = files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), *source))
+            .collect();
+
+        preprocess("shaders/main.frag", defines, |path| {
+            files
+                .get(path)
+                .map(|source| String::from(*source))
+                .ok_or_else(|| String::from("not found"))
+        })
+    }
+
+    #[test]
+    fn expands_nested_includes() {
+        let processed = run(
+            &[
+                (
+                    "shaders/main.frag",
+                    "#version 330\n#include \"a.glsl\"\nvoid main() {}"
+                ),
+                ("shaders/a.glsl", "// a\n#include \"b.glsl\"\n// a end"),
+                ("shaders/b.glsl", "// b")
+            ],
+            &[]
+        )
+        .unwrap();
+
+        assert_eq!(
+            processed.code,
+            "#version 330\n// a\n// b\n// a end\nvoid main() {}\n"
+        );
+        assert_eq!(
+            processed.files,
+            ["shaders/main.frag", "shaders/a.glsl", "shaders/b.glsl"]
+        );
+        assert_eq!(processed.origin(3), Some(("shaders/b.glsl", 1)));
+        assert_eq!(processed.origin(4), Some(("shaders/a.glsl", 3)));
+        assert_eq!(processed.origin(5), Some(("shaders/main.frag", 3)));
+        assert_eq!(processed.origin(0), None);
+        assert_eq!(processed.origin(6), None);
+    }
+
+    #[test]
+    fn detects_include_cycles() {
+        let error = run(
+            &[
+                ("shaders/main.frag", "#include \"a.glsl\""),
+                ("shaders/a.glsl", "#include \"b.glsl\""),
+                ("shaders/b.glsl", "#include \"a.glsl\"")
+            ],
+            &[]
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error,
+            PreprocessError::Cycle(vec![
+                String::from("shaders/main.frag"),
+                String::from("shaders/a.glsl"),
+                String::from("shaders/b.glsl"),
+                String::from("shaders/a.glsl")
+            ])
+        );
+    }
+
+    #[test]
+    fn detects_a_file_including_itself() {
+        let error = run(&[("shaders/main.frag", "#include \"main.frag\"")], &[]).unwrap_err();
+
+        assert_eq!(
+            error,
+            PreprocessError::Cycle(vec![
+                String::from("shaders/main.frag"),
+                String::from("shaders/main.frag")
+            ])
+        );
+    }
+
+    #[test]
+    fn allows_including_a_file_twice() {
+        let processed = run(
+            &[
+                (
+                    "shaders/main.frag",
+                    "#include \"a.glsl\"\n#include \"a.glsl\""
+                ),
+                ("shaders/a.glsl", "// a")
+            ],
+            &[]
+        )
+        .unwrap();
+
+        assert_eq!(processed.code, "// a\n// a\n");
+    }
+
+    #[test]
+    fn injects_defines_after_the_version() {
+        let processed = run(
+            &[(
+                "shaders/main.frag",
+                "// header\n#version 330 core\nvoid main() {}"
+            )],
+            &[("LIGHTS", "4"), ("DEBUG", "")]
+        )
+        .unwrap();
+
+        assert_eq!(
+            processed.code,
+            "// header\n#version 330 core\n#define LIGHTS 4\n#define DEBUG\nvoid main() {}\n"
+        );
+        assert_eq!(processed.origin(2), Some(("shaders/main.frag", 2)));
+        assert_eq!(processed.origin(3), Some((DEFINES_FILE, 1)));
+        assert_eq!(processed.origin(4), Some((DEFINES_FILE, 2)));
+        assert_eq!(processed.origin(5), Some(("shaders/main.frag", 3)));
+    }
+
+    #[test]
+    fn injects_defines_at_the_top_without_a_version() {
+        let processed = run(
+            &[("shaders/main.frag", "void main() {}")],
+            &[("LIGHTS", "4")]
+        )
+        .unwrap();
+
+        assert_eq!(processed.code, "#define LIGHTS 4\nvoid main() {}\n");
+        assert_eq!(processed.origin(1), Some((DEFINES_FILE, 1)));
+        assert_eq!(processed.origin(2), Some(("shaders/main.frag", 1)));
+    }
+
+    #[test]
+    fn rejects_malformed_includes() {
+        for line in &[
+            "#include",
+            "#include a.glsl",
+            "#include \"\"",
+            "#include \"a.glsl",
+            "#include \"a.glsl\" extra"
+        ] {
+            let source = format!("// ok\n{}", line);
+            let error = run(&[("shaders/main.frag", &source)], &[]).unwrap_err();
+
+            assert_eq!(
+                error,
+                PreprocessError::Include {
+                    path: String::from("shaders/main.frag"),
+                    line: 2
+                },
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let error = run(&[("shaders/main.frag", "#include \"a.glsl\"")], &[]).unwrap_err();
+
+        assert_eq!(
+            error,
+            PreprocessError::Io {
+                path: String::from("shaders/a.glsl"),
+                message: String::from("not found")
+            }
+        );
+    }
+
+    #[test]
+    fn remaps_compiler_logs() {
+        let processed = run(
+            &[
+                (
+                    "shaders/main.frag",
+                    "#version 330\n#include \"a.glsl\"\nvoid main() {}"
+                ),
+                ("shaders/a.glsl", "// a\nfloat x = ;")
+            ],
+            &[("LIGHTS", "4")]
+        )
+        .unwrap();
+
+        // Mesa
+        assert_eq!(
+            processed.remap_log("0:4(11): error: syntax error"),
+            "shaders/a.glsl:2(11): error: syntax error"
+        );
+        // AMD and Intel
+        assert_eq!(
+            processed.remap_log("ERROR: 0:2: 'LIGHTS' : redefinition"),
+            "ERROR: <defines>:1: 'LIGHTS' : redefinition"
+        );
+        assert_eq!(
+            processed.remap_log("WARNING: 0:5: unused"),
+            "WARNING: shaders/main.frag:3: unused"
+        );
+        // NVIDIA
+        assert_eq!(
+            processed.remap_log("0(4) : error C0000: syntax error"),
+            "shaders/a.glsl:2 : error C0000: syntax error"
+        );
+    }
+
+    #[test]
+    fn leaves_other_log_lines_alone() {
+        let processed = run(&[("shaders/main.frag", "void main() {}")], &[]).unwrap();
+
+        for line in &[
+            "Compilation failed",
+            "0:99(1): error: past the end",
+            "0(1 : error",
+            "ERROR: 2 compilation errors",
+            "0é: unicode",
+            "WARNING: 12€"
+        ] {
+            assert_eq!(processed.remap_log(line), *line);
+        }
+        assert_eq!(
+            processed.remap_log("0:1(1): a\nsomething else"),
+            "shaders/main.frag:1(1): a\nsomething else"
+        );
+    }
+}
@@ -0,0 +1,589 @@
+use crate::assets::{Handle, Texture};
+use crate::batch::{Sprite, SpriteBatch};
+use crate::error::Error;
+use crate::map::{MapRenderer, TileMap};
+use crate::shader::{Program, UniformError};
+use crate::text::{LayoutOptions, TextRenderer};
+use nalgebra::{Matrix4, Orthographic3};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// Floats per map tile in the vertex buffer, two triangles of three xyz vertices
+const TILE_VERTEX_FLOATS: usize = 18;
+
+/// Floats per map tile in the texture coordinate buffer, two triangles of three uv pairs
+const TILE_UV_FLOATS: usize = 12;
+
+/// Height of the screen in world units, divided by the window height to give a pixel's size
+const SCREEN_HEIGHT: f32 = 2.0;
+
+/// What a draw command is part of, drawn in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Map,
+    Entities,
+    Hud
+}
+
+/// A single thing drawn during a frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawCommand {
+    /// A textured rectangle in world coordinates
+    Quad { layer: Layer, sprite: Sprite },
+    /// A block of text with its top left corner in screen coordinates
+    Text { text: String, x: f32, y: f32 }
+}
+
+impl DrawCommand {
+    pub fn layer(&self) -> Layer {
+        match self {
+            DrawCommand::Quad { layer, .. } => *layer,
+            DrawCommand::Text { .. } => Layer::Hud
+        }
+    }
+}
+
+/// Draws frames of the game
+/// Every frame starts with `begin_frame` and ends with `end_frame`, with the view, map, sprites
+/// and text submitted in between.
+pub trait Renderer: Send + Sync {
+    /// Clears the screen
+    fn begin_frame(&mut self);
+
+    /// Sets where the camera is looking
+    /// # Arguments
+    /// * `view` - The camera's view matrix, applied to the map and sprites
+    fn set_view(&mut self, view: &Matrix4<f32>);
+
+    /// Starts drawing a new map
+    /// # Arguments
+    /// * `tileset` - The texture the map's tiles are drawn from
+    fn set_map(&mut self, tileset: Handle<Texture>);
+
+    /// Draws the tiles of the map that overlap a rectangle, beneath everything else
+    /// # Arguments
+    /// * `map` - The map to draw
+    /// * `min` - Bottom left corner of the view in world coordinates
+    /// * `max` - Top right corner of the view in world coordinates
+    fn draw_map(&mut self, map: &TileMap, min: (f32, f32), max: (f32, f32));
+
+    /// Draws a sprite above the map
+    fn draw_sprite(&mut self, sprite: Sprite);
+
+    /// Draws text above everything else, unaffected by the camera
+    /// # Arguments
+    /// * `text` - The text to show
+    /// * `x`, `y` - Top left corner of the text, where x spans -aspect to aspect and y -1 to 1
+    fn draw_text(&mut self, text: &str, x: f32, y: f32);
+
+    /// Finishes drawing everything submitted since `begin_frame`
+    fn end_frame(&mut self);
+
+    /// Fits the drawing to a new window size
+    /// # Arguments
+    /// * `width`, `height` - Size of the window in pixels
+    fn resize(&mut self, _width: u32, _height: u32) {}
+
+    /// Rebuilds the shaders if their files changed, returning true if they were rebuilt
+    fn reload_shaders(&mut self) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
+
+/// Draws with OpenGL
+/// Must only be used on the thread that owns the OpenGL context.
+pub struct GlRenderer {
+    program: Program,
+    map_renderer: Option<MapRenderer>,
+    sprite_batch: SpriteBatch,
+    text_renderer: TextRenderer,
+    /// Text submitted this frame, drawn after the sprites
+    text: Vec<(String, f32, f32)>,
+    view: Matrix4<f32>,
+    aspect: f32,
+    /// What has failed to draw, so a failure repeated every frame is only reported once
+    reported: HashSet<&'static str>
+}
+
+impl GlRenderer {
+    /// Creates a renderer drawing with a shader program
+    /// # Arguments
+    /// * `program` - The shader program to draw with
+    /// * `text_renderer` - Draws the HUD text
+    /// * `width`, `height` - Size of the window in pixels
+    pub fn new(
+        program: Program,
+        text_renderer: TextRenderer,
+        width: u32,
+        height: u32
+    ) -> GlRenderer {
+        let mut renderer = GlRenderer {
+            program,
+            map_renderer: None,
+            sprite_batch: SpriteBatch::new(),
+            text_renderer,
+            text: vec![],
+            view: Matrix4::identity(),
+            aspect: 1.0,
+            reported: HashSet::new()
+        };
+        renderer.resize(width, height);
+        renderer
+    }
+
+    /// The shader program everything is drawn with
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Reports a failure the first time it happens
+    /// # Arguments
+    /// * `what` - What was being done, e.g. "draw the map"
+    /// * `result` - The result of doing it
+    fn report(&mut self, what: &'static str, result: Result<(), UniformError>) {
+        if let Err(error) = result {
+            if self.reported.insert(what) {
+                eprintln!("Failed to {}: {}", what, error);
+            }
+        }
+    }
+
+    fn set_projection(&self) -> Result<(), UniformError> {
+        set_projection(&self.program, self.aspect)
+    }
+
+    fn draw_sprites(&mut self) -> Result<(), UniformError> {
+        self.program.set_mat4("view", self.view.as_slice())?;
+        self.sprite_batch.flush(&self.program)
+    }
+
+    fn draw_text_blocks(&mut self) -> Result<(), UniformError> {
+        let identity = Matrix4::<f32>::identity();
+        self.program.set_mat4("view", identity.as_slice())?;
+        for (text, x, y) in self.text.drain(..) {
+            self.text_renderer
+                .set_text(&text, &LayoutOptions::default());
+            self.text_renderer.draw(&self.program, x, y)?;
+        }
+        Ok(())
+    }
+}
+
+impl Renderer for GlRenderer {
+    fn begin_frame(&mut self) {
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        };
+    }
+
+    fn set_view(&mut self, view: &Matrix4<f32>) {
+        self.view = *view;
+        self.program.set_used();
+        let result = self.program.set_mat4("view", view.as_slice());
+        self.report("set the view", result);
+    }
+
+    fn set_map(&mut self, tileset: Handle<Texture>) {
+        self.map_renderer = Some(MapRenderer::new(tileset));
+    }
+
+    fn draw_map(&mut self, map: &TileMap, min: (f32, f32), max: (f32, f32)) {
+        if let Some(map_renderer) = &mut self.map_renderer {
+            map_renderer.update(map, min, max);
+            let result = map_renderer.draw(&self.program);
+            self.report("draw the map", result);
+        }
+    }
+
+    fn draw_sprite(&mut self, sprite: Sprite) {
+        self.sprite_batch.push(sprite);
+    }
+
+    fn draw_text(&mut self, text: &str, x: f32, y: f32) {
+        self.text.push((String::from(text), x, y));
+    }
+
+    fn end_frame(&mut self) {
+        let result = self.draw_sprites();
+        self.report("draw sprites", result);
+        let result = self.draw_text_blocks();
+        self.report("draw the HUD", result);
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        unsafe {
+            gl::Viewport(0, 0, width as i32, height as i32);
+        };
+        self.aspect = width as f32 / height.max(1) as f32;
+
+        // Keep the text the same size in pixels
+        self.text_renderer
+            .set_scale(SCREEN_HEIGHT / height.max(1) as f32);
+
+        let result = self.set_projection();
+        self.report("set the projection", result);
+    }
+
+    fn reload_shaders(&mut self) -> Result<bool, Error> {
+        if !self.program.reload_if_changed()? {
+            return Ok(false);
+        }
+
+        // Uniforms written once have to be written again
+        self.set_projection()?;
+
+        // The new shaders may fail differently
+        self.reported.clear();
+        Ok(true)
+    }
+}
+
+/// Writes an orthographic projection showing -aspect to aspect across and -1 to 1 up
+/// # Arguments
+/// * `program` - The shader program to write the projection to
+/// * `aspect` - Width of the window divided by its height
+pub fn set_projection(program: &Program, aspect: f32) -> Result<(), UniformError> {
+    let projection = Orthographic3::new(-aspect, aspect, -1.0, 1.0, -1.0, 1.0);
+    program.set_used();
+    program.set_mat4("projection", projection.to_homogeneous().as_slice())
+}
+
+/// What a `RecordingRenderer` has drawn, shared so it can be checked after the renderer is boxed
+#[derive(Debug, Default)]
+pub struct RecordingLog {
+    /// Number of frames finished
+    pub frames: usize,
+    /// View matrix of the last finished frame
+    pub view: Matrix4<f32>,
+    /// Everything drawn in the last finished frame, in the order it was submitted
+    pub commands: Vec<DrawCommand>,
+    /// The last window size passed to `resize`
+    pub size: Option<(u32, u32)>
+}
+
+impl RecordingLog {
+    /// The commands of the last finished frame drawn on a layer
+    pub fn layer(&self, layer: Layer) -> Vec<&DrawCommand> {
+        self.commands
+            .iter()
+            .filter(|command| command.layer() == layer)
+            .collect()
+    }
+}
+
+/// Records draw commands instead of drawing them, for running without a GPU
+/// Map tiles are recorded as one quad each.
+#[derive(Default)]
+pub struct RecordingRenderer {
+    pub log: Arc<Mutex<RecordingLog>>,
+    tileset: Option<Handle<Texture>>,
+    view: Matrix4<f32>,
+    /// Commands submitted since `begin_frame`
+    commands: Vec<DrawCommand>
+}
+
+impl RecordingRenderer {
+    pub fn new() -> RecordingRenderer {
+        RecordingRenderer::default()
+    }
+}
+
+impl Renderer for RecordingRenderer {
+    fn begin_frame(&mut self) {
+        self.view = Matrix4::identity();
+        self.commands.clear();
+    }
+
+    fn set_view(&mut self, view: &Matrix4<f32>) {
+        self.view = *view;
+    }
+
+    fn set_map(&mut self, tileset: Handle<Texture>) {
+        self.tileset = Some(tileset);
+    }
+
+    fn draw_map(&mut self, map: &TileMap, min: (f32, f32), max: (f32, f32)) {
+        let texture = match &self.tileset {
+            Some(tileset) => tileset.id,
+            None => return
+        };
+
+        let (columns, rows) = map.visible_range(min, max);
+        let (vertices, texture_vertices) = map.mesh(columns, rows);
+        let tiles = tile_sprites(texture, &vertices, &texture_vertices);
+        self.commands
+            .extend(tiles.into_iter().map(|sprite| DrawCommand::Quad {
+                layer: Layer::Map,
+                sprite
+            }));
+    }
+
+    fn draw_sprite(&mut self, sprite: Sprite) {
+        self.commands.push(DrawCommand::Quad {
+            layer: Layer::Entities,
+            sprite
+        });
+    }
+
+    fn draw_text(&mut self, text: &str, x: f32, y: f32) {
+        self.commands.push(DrawCommand::Text {
+            text: String::from(text),
+            x,
+            y
+        });
+    }
+
+    fn end_frame(&mut self) {
+        let mut log = self.log.lock().unwrap();
+        log.frames += 1;
+        log.view = self.view;
+        log.commands = std::mem::take(&mut self.commands);
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.log.lock().unwrap().size = Some((width, height));
+    }
+}
+
+/// Splits a map mesh back into a sprite for each tile
+/// # Arguments
+/// * `texture` - The tileset texture
+/// * `vertices`, `texture_vertices` - Buffers built by `TileMap::mesh`
+pub fn tile_sprites(texture: u32, vertices: &[f32], texture_vertices: &[f32]) -> Vec<Sprite> {
+    vertices
+        .chunks_exact(TILE_VERTEX_FLOATS)
+        .zip(texture_vertices.chunks_exact(TILE_UV_FLOATS))
+        .map(|(tile, uv)| {
+            // The first triangle runs bottom left, top right, top left
+            let (left, bottom, right, top) = (tile[0], tile[1], tile[3], tile[4]);
+            Sprite::new(
+                texture,
+                ((left + right) / 2.0, (bottom + top) / 2.0, tile[2]),
+                (right - left) / 2.0,
+                (top - bottom) / 2.0,
+                uv
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::AssetManager;
+    use crate::atlas::Atlas;
+    use crate::component_system::components::{Drawn, LastPosition, Position};
+    use crate::component_system::resources::{FrameTime, HudText};
+    use crate::component_system::systems::DrawSystem;
+    use crate::fixture::{self, CHEST_IMAGE, TILESET_IMAGE};
+    use specs::prelude::*;
+
+    /// A world drawing to a `RecordingRenderer`
+    fn world() -> (World, Arc<Mutex<RecordingLog>>) {
+        let mut world = fixture::world();
+
+        let renderer = RecordingRenderer::new();
+        let log = Arc::clone(&renderer.log);
+        world.insert::<Box<dyn Renderer>>(Box::new(renderer));
+
+        (world, log)
+    }
+
+    /// Draws one frame of the world
+    fn draw(world: &World) {
+        world.write_resource::<Box<dyn Renderer>>().begin_frame();
+        DrawSystem.run_now(world);
+        world.write_resource::<Box<dyn Renderer>>().end_frame();
+    }
+
+    /// A 2 by 2 map of 0.5 tiles with its bottom left corner at the origin, missing its top right
+    /// tile
+    fn map() -> TileMap {
+        let tileset = Atlas::load("./src/tiles/dungeon.toml").unwrap();
+        let mut map = TileMap::new(2, 2, 0.5, tileset);
+        map.origin = (0.0, 0.0);
+        map.set_tile(0, 0, 0, 1);
+        map.set_tile(0, 0, 1, 1);
+        map.set_tile(0, 1, 1, 2);
+        map
+    }
+
+    fn quads(commands: &[&DrawCommand]) -> Vec<Sprite> {
+        commands
+            .iter()
+            .map(|command| match command {
+                DrawCommand::Quad { sprite, .. } => sprite.clone(),
+                other => panic!("Expected a quad, got {:?}", other)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn draws_a_spawned_entity() {
+        let (mut world, log) = world();
+        let chest = fixture::spawn(&mut world, "chest", 0.25, -0.5);
+        let texture = world
+            .write_resource::<AssetManager>()
+            .load_texture(CHEST_IMAGE)
+            .unwrap();
+
+        draw(&world);
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.frames, 1);
+        let sprites = quads(&log.layer(Layer::Entities));
+        assert_eq!(sprites.len(), 1);
+
+        let drawn = world.read_storage::<Drawn>();
+        let drawn = drawn.get(chest).unwrap();
+        let half_width = drawn.extent.0 * 0.08 / 2.0;
+        let half_height = drawn.extent.1 * 0.08 / 2.0;
+        assert!(half_width > 0.0 && half_height > 0.0);
+
+        let sprite = &sprites[0];
+        assert_eq!(sprite.texture, texture.id);
+        assert_eq!(sprite.left, 0.25 - half_width);
+        assert_eq!(sprite.right, 0.25 + half_width);
+        assert_eq!(sprite.bottom, -0.5 - half_height);
+        assert_eq!(sprite.top, -0.5 + half_height);
+        assert_eq!(sprite.uv[..], drawn.uv[..]);
+    }
+
+    #[test]
+    fn draws_the_map_beneath_entities_and_the_hud_on_top() {
+        let (mut world, log) = world();
+        let tileset = world
+            .write_resource::<AssetManager>()
+            .load_texture(TILESET_IMAGE)
+            .unwrap();
+        let map = map();
+        let floor = map.tileset.frames[0].uv.clone();
+        let wall = map.tileset.frames[1].uv.clone();
+        world.insert(map);
+        world
+            .write_resource::<Box<dyn Renderer>>()
+            .set_map(tileset.clone());
+        fixture::spawn(&mut world, "chest", 0.5, 0.5);
+        world.insert(HudText(String::from("Health 10")));
+
+        draw(&world);
+
+        let log = log.lock().unwrap();
+        let layers: Vec<Layer> = log.commands.iter().map(DrawCommand::layer).collect();
+        assert_eq!(
+            layers,
+            [
+                Layer::Map,
+                Layer::Map,
+                Layer::Map,
+                Layer::Entities,
+                Layer::Hud
+            ]
+        );
+
+        // Rows start from the top of the map
+        let tiles = quads(&log.layer(Layer::Map));
+        let corners: Vec<(f32, f32, f32, f32)> = tiles
+            .iter()
+            .map(|tile| (tile.left, tile.right, tile.bottom, tile.top))
+            .collect();
+        assert_eq!(
+            corners,
+            [
+                (0.0, 0.5, 0.5, 1.0),
+                (0.0, 0.5, 0.0, 0.5),
+                (0.5, 1.0, 0.0, 0.5)
+            ]
+        );
+        assert!(tiles.iter().all(|tile| tile.texture == tileset.id));
+        assert_eq!(tiles[0].uv[..], floor[..]);
+        assert_eq!(tiles[2].uv[..], wall[..]);
+
+        match log.layer(Layer::Hud)[..] {
+            [DrawCommand::Text { text, x, y }] => {
+                assert_eq!(text, "Health 10");
+                assert!(*x < 0.0 && *y > 0.0);
+            },
+            ref other => panic!("Expected the HUD text, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn draws_between_the_last_two_steps() {
+        let (mut world, log) = world();
+        let chest = fixture::spawn(&mut world, "chest", 0.5, 0.0);
+        world
+            .write_storage::<LastPosition>()
+            .insert(
+                chest,
+                LastPosition {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0
+                }
+            )
+            .unwrap();
+        world.write_resource::<FrameTime>().alpha = 0.5;
+
+        draw(&world);
+
+        let sprite = &quads(&log.lock().unwrap().layer(Layer::Entities))[0];
+        assert!(((sprite.left + sprite.right) / 2.0 - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn skips_entities_off_screen() {
+        let (mut world, log) = world();
+        fixture::spawn(&mut world, "chest", 0.0, 0.0);
+        fixture::spawn(&mut world, "chest", 5.0, 0.0);
+        fixture::spawn(&mut world, "chest", 0.0, -5.0);
+
+        draw(&world);
+
+        assert_eq!(log.lock().unwrap().layer(Layer::Entities).len(), 1);
+    }
+
+    #[test]
+    fn records_the_camera_view() {
+        let (world, log) = world();
+        {
+            let mut camera = world.write_resource::<crate::camera::Camera>();
+            camera.x = 1.0;
+            camera.zoom = 2.0;
+        }
+
+        draw(&world);
+
+        let expected = world.read_resource::<crate::camera::Camera>().view_matrix();
+        assert_eq!(log.lock().unwrap().view, expected);
+    }
+
+    #[test]
+    fn keeps_only_the_last_finished_frame() {
+        let (mut world, log) = world();
+        let chest = fixture::spawn(&mut world, "chest", 0.0, 0.0);
+
+        draw(&world);
+        world.write_storage::<Position>().get_mut(chest).unwrap().x = 10.0;
+        draw(&world);
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.frames, 2);
+        assert!(log.layer(Layer::Entities).is_empty());
+        assert_eq!(log.layer(Layer::Hud).len(), 1);
+    }
+
+    #[test]
+    fn tile_sprites_undo_the_map_mesh() {
+        let map = map();
+        let (vertices, texture_vertices) = map.mesh(0..2, 0..2);
+
+        let sprites = tile_sprites(7, &vertices, &texture_vertices);
+
+        assert_eq!(sprites.len(), 3);
+        assert_eq!(
+            sprites[2],
+            Sprite::new(7, (0.75, 0.25, 0.0), 0.25, 0.25, &map.tileset.frames[1].uv)
+        );
+    }
+}
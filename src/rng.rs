@@ -0,0 +1,69 @@
+/// A small seeded random number generator (xorshift64*)
+/// The whole state is a single integer so it can be saved and restored exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rng {
+    state: u64
+}
+
+impl Default for Rng {
+    fn default() -> Rng {
+        Rng::new(0)
+    }
+}
+
+impl Rng {
+    /// Creates a generator from a seed, any seed including zero is valid
+    /// # Arguments
+    /// * `seed` - The seed
+    pub fn new(seed: u64) -> Rng {
+        // Scramble the seed so nearby seeds give unrelated sequences
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+
+        Rng {
+            state: if state == 0 { 1 } else { state }
+        }
+    }
+
+    /// Restores a generator from a state returned by `state`
+    pub fn from_state(state: u64) -> Rng {
+        Rng {
+            state: if state == 0 { 1 } else { state }
+        }
+    }
+
+    /// The current state of the generator
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Returns the next random integer
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a random integer in the range `low..high`, or `low` if the range is empty
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        if high <= low {
+            return low;
+        }
+
+        let span = (high as i64 - low as i64) as u64;
+        (low as i64 + (self.next_u64() % span) as i64) as i32
+    }
+
+    /// Returns a random float in the range `0.0..1.0`
+    pub fn float(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Returns true with the given probability
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.float() < probability
+    }
+}
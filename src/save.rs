@@ -0,0 +1,1240 @@
+use crate::ai::{self, Behaviour};
+use crate::animation::{Animator, Clip, Keyframe, PlayMode, Playback};
+use crate::assets::{AssetError, AssetManager};
+use crate::atlas::{Atlas, AtlasError};
+use crate::batch::UV_FLOATS;
+use crate::camera::Camera;
+use crate::component_system::components::*;
+use crate::component_system::resources::{HudText, LevelSeed, MeleeAttacks, PendingAction};
+use crate::map::{self, TileMap};
+use crate::render::Renderer;
+use crate::rng::Rng;
+use crate::turn;
+use specs::prelude::*;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::time::Duration;
+use toml::value::{Array, Table};
+use toml::Value;
+
+/// Version written to new save files, bumped whenever the format changes
+pub const VERSION: i64 = 1;
+
+/// Errors that can occur while saving or loading a game
+#[derive(Debug)]
+pub enum SaveError {
+    /// The save file could not be read or written
+    Io { path: String, message: String },
+    /// The save file is not valid TOML
+    Toml(String),
+    /// The save file was written by an incompatible version of the game
+    Version(i64),
+    /// A required field is missing
+    MissingField(String),
+    /// A field is present but has the wrong type or value
+    InvalidField {
+        field: String,
+        expected: &'static str
+    },
+    /// The map's tileset is invalid
+    Atlas(AtlasError),
+    /// A texture referenced by the save could not be loaded
+    Asset(AssetError)
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io { path, message } => write!(f, "'{}': {}", path, message),
+            SaveError::Toml(message) => write!(f, "Invalid save file: {}", message),
+            SaveError::Version(version) => write!(
+                f,
+                "Save file version {} is not supported, expected {}",
+                version, VERSION
+            ),
+            SaveError::MissingField(field) => write!(f, "Missing field '{}'", field),
+            SaveError::InvalidField { field, expected } => {
+                write!(f, "Field '{}' should be {}", field, expected)
+            },
+            SaveError::Atlas(error) => write!(f, "Invalid tileset: {}", error),
+            SaveError::Asset(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+/// Reads the fields of a table in a save file, naming the table in errors
+pub struct Fields<'a> {
+    name: &'a str,
+    table: &'a Value
+}
+
+impl<'a> Fields<'a> {
+    pub fn new(name: &'a str, table: &'a Value) -> Fields<'a> {
+        Fields { name, table }
+    }
+
+    fn path(&self, field: &str) -> String {
+        format!("{}.{}", self.name, field)
+    }
+
+    fn invalid(&self, field: &str, expected: &'static str) -> SaveError {
+        SaveError::InvalidField {
+            field: self.path(field),
+            expected
+        }
+    }
+
+    pub fn get(&self, field: &str) -> Result<&'a Value, SaveError> {
+        self.table
+            .get(field)
+            .ok_or_else(|| SaveError::MissingField(self.path(field)))
+    }
+
+    pub fn float(&self, field: &str) -> Result<f32, SaveError> {
+        match self.get(field)? {
+            Value::Float(value) => Ok(*value as f32),
+            Value::Integer(value) => Ok(*value as f32),
+            _ => Err(self.invalid(field, "a number"))
+        }
+    }
+
+    pub fn floats(&self, field: &str) -> Result<Vec<f32>, SaveError> {
+        self.array(field)?
+            .iter()
+            .map(|value| match value {
+                Value::Float(value) => Ok(*value as f32),
+                Value::Integer(value) => Ok(*value as f32),
+                _ => Err(self.invalid(field, "an array of numbers"))
+            })
+            .collect()
+    }
+
+    /// Reads an array of exactly two numbers
+    pub fn pair(&self, field: &str) -> Result<(f32, f32), SaveError> {
+        match self.floats(field)?.as_slice() {
+            [first, second] => Ok((*first, *second)),
+            _ => Err(self.invalid(field, "an array of two numbers"))
+        }
+    }
+
+    pub fn unsigned(&self, field: &str) -> Result<u32, SaveError> {
+        match self.get(field)? {
+            Value::Integer(value) if *value >= 0 && *value <= u32::MAX as i64 => Ok(*value as u32),
+            _ => Err(self.invalid(field, "a positive integer"))
+        }
+    }
+
+    pub fn boolean(&self, field: &str) -> Result<bool, SaveError> {
+        match self.get(field)? {
+            Value::Boolean(value) => Ok(*value),
+            _ => Err(self.invalid(field, "true or false"))
+        }
+    }
+
+    pub fn string(&self, field: &str) -> Result<String, SaveError> {
+        match self.get(field)? {
+            Value::String(value) => Ok(value.clone()),
+            _ => Err(self.invalid(field, "a string"))
+        }
+    }
+
+    pub fn array(&self, field: &str) -> Result<&'a Array, SaveError> {
+        match self.get(field)? {
+            Value::Array(values) => Ok(values),
+            _ => Err(self.invalid(field, "an array"))
+        }
+    }
+
+    /// Reads a map cell stored as a column and row, if there is one
+    pub fn optional_cell(&self, field: &str) -> Result<Option<(i32, i32)>, SaveError> {
+        let cell = match self.table.get(field) {
+            Some(Value::Array(cell)) => cell,
+            Some(_) => return Err(self.invalid(field, "a column and row")),
+            None => return Ok(None)
+        };
+
+        let coordinate = |value: &Value| match value {
+            Value::Integer(value) => i32::try_from(*value).ok(),
+            _ => None
+        };
+        match cell.as_slice() {
+            [column, row] => coordinate(column).zip(coordinate(row)),
+            _ => None
+        }
+        .map(Some)
+        .ok_or_else(|| self.invalid(field, "a column and row"))
+    }
+
+    /// Reads a 64 bit number, stored as a string since TOML integers are signed
+    pub fn seed(&self, field: &str) -> Result<u64, SaveError> {
+        self.string(field)?
+            .parse()
+            .map_err(|_| self.invalid(field, "a string of digits"))
+    }
+
+    /// Reads a duration stored in nanoseconds
+    pub fn duration(&self, field: &str) -> Result<Duration, SaveError> {
+        match self.get(field)? {
+            Value::Integer(value) if *value >= 0 => Ok(Duration::from_nanos(*value as u64)),
+            _ => Err(self.invalid(field, "a positive number of nanoseconds"))
+        }
+    }
+}
+
+/// A component written to and read from save files
+/// Components without an implementation are left out of saves.
+pub trait Persist: Component + Send + Sync + Sized {
+    /// Name of the component's entry in an entity's table
+    const KEY: &'static str;
+
+    fn save(&self) -> Value;
+
+    /// Reads the component from its entry
+    /// # Arguments
+    /// * `fields` - The component's entry
+    /// * `assets` - Loads assets the component refers to
+    fn load(fields: &Fields, assets: &mut AssetManager) -> Result<Self, SaveError>;
+}
+
+/// Builds a table from field names and values
+fn table(entries: Vec<(&str, Value)>) -> Value {
+    Value::Table(
+        entries
+            .into_iter()
+            .map(|(name, value)| (String::from(name), value))
+            .collect()
+    )
+}
+
+fn floats(values: &[f32]) -> Value {
+    Value::Array(
+        values
+            .iter()
+            .map(|value| Value::Float(*value as f64))
+            .collect()
+    )
+}
+
+fn duration(duration: Duration) -> Value {
+    Value::Integer(duration.as_nanos() as i64)
+}
+
+impl Persist for Position {
+    const KEY: &'static str = "position";
+
+    fn save(&self) -> Value {
+        floats(&[self.x, self.y, self.z])
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<Position, SaveError> {
+        match vector(fields)?.as_slice() {
+            [x, y, z] => Ok(Position::new_xyz(*x, *y, *z)),
+            _ => Err(SaveError::InvalidField {
+                field: String::from(fields.name),
+                expected: "an array of three numbers"
+            })
+        }
+    }
+}
+
+impl Persist for Velocity {
+    const KEY: &'static str = "velocity";
+
+    fn save(&self) -> Value {
+        floats(&[self.x, self.y, self.z])
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<Velocity, SaveError> {
+        match vector(fields)?.as_slice() {
+            [x, y, z] => Ok(Velocity {
+                x: *x,
+                y: *y,
+                z: *z
+            }),
+            _ => Err(SaveError::InvalidField {
+                field: String::from(fields.name),
+                expected: "an array of three numbers"
+            })
+        }
+    }
+}
+
+impl Persist for Size {
+    const KEY: &'static str = "size";
+
+    fn save(&self) -> Value {
+        floats(&[self.width, self.height])
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<Size, SaveError> {
+        match vector(fields)?.as_slice() {
+            [width, height] => Ok(Size::new(*width, *height)),
+            _ => Err(SaveError::InvalidField {
+                field: String::from(fields.name),
+                expected: "an array of two numbers"
+            })
+        }
+    }
+}
+
+/// Reads an entry that is itself an array of numbers
+fn vector(fields: &Fields) -> Result<Vec<f32>, SaveError> {
+    match fields.table {
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Float(value) => Ok(*value as f32),
+                Value::Integer(value) => Ok(*value as f32),
+                _ => Err(SaveError::InvalidField {
+                    field: String::from(fields.name),
+                    expected: "an array of numbers"
+                })
+            })
+            .collect(),
+        _ => Err(SaveError::InvalidField {
+            field: String::from(fields.name),
+            expected: "an array of numbers"
+        })
+    }
+}
+
+impl Persist for Collider {
+    const KEY: &'static str = "collider";
+
+    fn save(&self) -> Value {
+        match self.size {
+            Some((width, height)) => table(vec![("size", floats(&[width, height]))]),
+            None => table(vec![])
+        }
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<Collider, SaveError> {
+        match fields.table.get("size") {
+            Some(_) => {
+                let (width, height) = fields.pair("size")?;
+                Ok(Collider::new(width, height))
+            },
+            None => Ok(Collider::from_size())
+        }
+    }
+}
+
+impl Persist for Controlled {
+    const KEY: &'static str = "controlled";
+
+    fn save(&self) -> Value {
+        Value::Boolean(true)
+    }
+
+    fn load(_: &Fields, _: &mut AssetManager) -> Result<Controlled, SaveError> {
+        Ok(Controlled)
+    }
+}
+
+impl Persist for Actor {
+    const KEY: &'static str = "actor";
+
+    fn save(&self) -> Value {
+        table(vec![("speed", Value::Integer(self.speed as i64))])
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<Actor, SaveError> {
+        let speed = fields.unsigned("speed")?;
+        if speed > turn::MAX_SPEED {
+            return Err(fields.invalid("speed", "a speed from 0 to 10000"));
+        }
+        Ok(Actor::new(speed))
+    }
+}
+
+impl Persist for Drawn {
+    const KEY: &'static str = "drawn";
+
+    fn save(&self) -> Value {
+        table(vec![
+            ("texture", Value::String(String::from(self.texture.path()))),
+            ("extent", floats(&[self.extent.0, self.extent.1])),
+            ("uv", floats(&self.uv)),
+        ])
+    }
+
+    fn load(fields: &Fields, assets: &mut AssetManager) -> Result<Drawn, SaveError> {
+        // Textures are uploaded again from the image they were loaded from
+        let texture = assets
+            .load_texture(&fields.string("texture")?)
+            .map_err(SaveError::Asset)?;
+
+        // Sprites are built from exactly one set of texture coordinates
+        let uv = fields.floats("uv")?;
+        if uv.len() != UV_FLOATS {
+            return Err(fields.invalid("uv", "12 texture coordinates"));
+        }
+
+        Ok(Drawn {
+            texture,
+            extent: fields.pair("extent")?,
+            uv
+        })
+    }
+}
+
+impl Persist for Animate {
+    const KEY: &'static str = "animate";
+
+    fn save(&self) -> Value {
+        let clips = self
+            .animator
+            .clips()
+            .iter()
+            .map(|clip| {
+                let keyframes = clip
+                    .keyframes
+                    .iter()
+                    .map(|keyframe| {
+                        table(vec![
+                            ("index", Value::Integer(keyframe.index as i64)),
+                            ("duration", duration(keyframe.duration)),
+                        ])
+                    })
+                    .collect();
+
+                table(vec![
+                    ("name", Value::String(clip.name.clone())),
+                    ("mode", Value::String(String::from(clip.mode.name()))),
+                    ("keyframe", Value::Array(keyframes)),
+                ])
+            })
+            .collect();
+
+        let playback = self.animator.playback();
+        table(vec![
+            (
+                "frame_uvs",
+                Value::Array(self.frame_uvs.iter().map(|uv| floats(uv)).collect())
+            ),
+            ("clip", Value::Array(clips)),
+            ("playing", Value::Integer(playback.clip as i64)),
+            ("keyframe", Value::Integer(playback.keyframe as i64)),
+            ("elapsed", duration(playback.elapsed)),
+            ("forward", Value::Boolean(playback.forward)),
+            ("finished", Value::Boolean(playback.finished)),
+        ])
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<Animate, SaveError> {
+        let frame_uvs = fields
+            .array("frame_uvs")?
+            .iter()
+            .map(|uv| match vector(&Fields::new("animate.frame_uvs", uv))? {
+                uv if uv.len() == UV_FLOATS => Ok(uv),
+                _ => Err(fields.invalid("frame_uvs", "12 texture coordinates for each frame"))
+            })
+            .collect::<Result<Vec<Vec<f32>>, SaveError>>()?;
+
+        let clips = fields
+            .array("clip")?
+            .iter()
+            .map(|clip| load_clip(&Fields::new("animate.clip", clip)))
+            .collect::<Result<Vec<Clip>, SaveError>>()?;
+
+        let playback = Playback {
+            clip: fields.unsigned("playing")? as usize,
+            keyframe: fields.unsigned("keyframe")? as usize,
+            elapsed: fields.duration("elapsed")?,
+            forward: fields.boolean("forward")?,
+            finished: fields.boolean("finished")?
+        };
+
+        let animator = Animator::resume(clips, playback)
+            .ok_or_else(|| fields.invalid("playing", "a clip and keyframe that exist"))?;
+        if animator
+            .clips()
+            .iter()
+            .flat_map(|clip| &clip.keyframes)
+            .any(|keyframe| keyframe.index >= frame_uvs.len())
+        {
+            return Err(fields.invalid("clip.keyframe.index", "the index of a frame"));
+        }
+
+        Ok(Animate {
+            animator,
+            frame_uvs
+        })
+    }
+}
+
+impl Persist for Health {
+    const KEY: &'static str = "health";
+
+    fn save(&self) -> Value {
+        table(vec![
+            ("current", Value::Integer(self.current as i64)),
+            ("max", Value::Integer(self.max as i64)),
+            ("corpse", Value::Boolean(self.corpse)),
+        ])
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<Health, SaveError> {
+        Ok(Health {
+            current: fields.unsigned("current")?,
+            max: fields.unsigned("max")?,
+            corpse: fields.boolean("corpse")?
+        })
+    }
+}
+
+impl Persist for AttackStats {
+    const KEY: &'static str = "attack";
+
+    fn save(&self) -> Value {
+        table(vec![
+            ("power", Value::Integer(self.power as i64)),
+            ("crit_chance", Value::Float(self.crit_chance as f64)),
+            ("crit_multiplier", Value::Float(self.crit_multiplier as f64)),
+            ("cooldown", duration(self.cooldown)),
+            ("recovering", duration(self.recovering)),
+        ])
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<AttackStats, SaveError> {
+        let crit_multiplier = fields.float("crit_multiplier")?;
+        if !crit_multiplier.is_finite() || crit_multiplier < 1.0 {
+            return Err(fields.invalid("crit_multiplier", "a number of at least 1"));
+        }
+
+        Ok(AttackStats {
+            power: fields.unsigned("power")?,
+            crit_chance: fields.float("crit_chance")?,
+            crit_multiplier,
+            cooldown: fields.duration("cooldown")?,
+            recovering: fields.duration("recovering")?
+        })
+    }
+}
+
+impl Persist for Defense {
+    const KEY: &'static str = "defense";
+
+    fn save(&self) -> Value {
+        table(vec![("armour", Value::Integer(self.armour as i64))])
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<Defense, SaveError> {
+        Ok(Defense {
+            armour: fields.unsigned("armour")?
+        })
+    }
+}
+
+impl Persist for Hostile {
+    const KEY: &'static str = "hostile";
+
+    fn save(&self) -> Value {
+        Value::Boolean(true)
+    }
+
+    fn load(_: &Fields, _: &mut AssetManager) -> Result<Hostile, SaveError> {
+        Ok(Hostile)
+    }
+}
+
+impl Persist for Corpse {
+    const KEY: &'static str = "corpse";
+
+    fn save(&self) -> Value {
+        Value::Boolean(true)
+    }
+
+    fn load(_: &Fields, _: &mut AssetManager) -> Result<Corpse, SaveError> {
+        Ok(Corpse)
+    }
+}
+
+impl Persist for Brain {
+    const KEY: &'static str = "brain";
+
+    fn save(&self) -> Value {
+        let mut entries = vec![
+            (
+                "behaviour",
+                Value::String(String::from(self.behaviour.name()))
+            ),
+            ("sight", Value::Integer(self.sight as i64)),
+            ("flee_below", Value::Float(self.flee_below as f64)),
+            ("wanders", Value::Boolean(self.wanders)),
+            ("speed", Value::Float(self.speed as f64)),
+        ];
+        if let Some((column, row)) = self.home {
+            entries.push(("home", Value::Array(vec![column.into(), row.into()])));
+        }
+        if let Some((column, row)) = self.target {
+            entries.push(("target", Value::Array(vec![column.into(), row.into()])));
+        }
+        table(entries)
+    }
+
+    fn load(fields: &Fields, _: &mut AssetManager) -> Result<Brain, SaveError> {
+        let behaviour = Behaviour::from_name(&fields.string("behaviour")?).ok_or_else(|| {
+            fields.invalid("behaviour", "idle, wander, chase, flee or return_home")
+        })?;
+
+        let sight = fields.unsigned("sight")?;
+        if sight > ai::MAX_SIGHT {
+            return Err(fields.invalid("sight", "a number of cells from 0 to 100"));
+        }
+
+        let flee_below = fields.float("flee_below")?;
+        if !(0.0..=1.0).contains(&flee_below) {
+            return Err(fields.invalid("flee_below", "a number from 0 to 1"));
+        }
+
+        let speed = fields.float("speed")?;
+        if !speed.is_finite() || speed < 0.0 {
+            return Err(fields.invalid("speed", "a non-negative number"));
+        }
+
+        Ok(Brain {
+            behaviour,
+            home: fields.optional_cell("home")?,
+            sight,
+            flee_below,
+            wanders: fields.boolean("wanders")?,
+            speed,
+            target: fields.optional_cell("target")?
+        })
+    }
+}
+
+fn load_clip(fields: &Fields) -> Result<Clip, SaveError> {
+    let mode = PlayMode::from_name(&fields.string("mode")?)
+        .ok_or_else(|| fields.invalid("mode", "loop, once or ping_pong"))?;
+
+    let keyframes = fields
+        .array("keyframe")?
+        .iter()
+        .map(|keyframe| {
+            let keyframe = Fields::new("animate.clip.keyframe", keyframe);
+            Ok(Keyframe {
+                index: keyframe.unsigned("index")? as usize,
+                duration: keyframe.duration("duration")?
+            })
+        })
+        .collect::<Result<Vec<Keyframe>, SaveError>>()?;
+
+    Ok(Clip {
+        name: fields.string("name")?,
+        keyframes,
+        mode
+    })
+}
+
+/// Adds a component of an entity to its table, if it has one
+fn save_component<C: Persist>(world: &World, entity: Entity, entry: &mut Table) {
+    if let Some(component) = world.read_storage::<C>().get(entity) {
+        entry.insert(String::from(C::KEY), component.save());
+    }
+}
+
+/// Adds a loaded component to an entity being built
+type Insert = Box<dyn for<'a> FnOnce(EntityBuilder<'a>) -> EntityBuilder<'a>>;
+
+/// Reads a component from an entity's table, if it has one
+fn load_component<C: Persist>(
+    entry: &Value,
+    assets: &mut AssetManager,
+    inserts: &mut Vec<Insert>
+) -> Result<(), SaveError> {
+    if let Some(value) = entry.get(C::KEY) {
+        let component = C::load(&Fields::new(C::KEY, value), assets)?;
+        inserts.push(Box::new(move |builder: EntityBuilder| {
+            builder.with(component)
+        }));
+    }
+    Ok(())
+}
+
+/// Writes the saved components of an entity
+/// New components are saved by implementing `Persist` and adding them here and in
+/// `load_entity`.
+fn save_entity(world: &World, entity: Entity) -> Value {
+    let mut entry = Table::new();
+    save_component::<Position>(world, entity, &mut entry);
+    save_component::<Velocity>(world, entity, &mut entry);
+    save_component::<Size>(world, entity, &mut entry);
+    save_component::<Collider>(world, entity, &mut entry);
+    save_component::<Controlled>(world, entity, &mut entry);
+    save_component::<Actor>(world, entity, &mut entry);
+    save_component::<Drawn>(world, entity, &mut entry);
+    save_component::<Animate>(world, entity, &mut entry);
+    save_component::<Health>(world, entity, &mut entry);
+    save_component::<AttackStats>(world, entity, &mut entry);
+    save_component::<Defense>(world, entity, &mut entry);
+    save_component::<Hostile>(world, entity, &mut entry);
+    save_component::<Corpse>(world, entity, &mut entry);
+    save_component::<Brain>(world, entity, &mut entry);
+    Value::Table(entry)
+}
+
+/// Reads the saved components of an entity
+fn load_entity(entry: &Value, assets: &mut AssetManager) -> Result<Vec<Insert>, SaveError> {
+    let mut inserts = vec![];
+    load_component::<Position>(entry, assets, &mut inserts)?;
+    load_component::<Velocity>(entry, assets, &mut inserts)?;
+    load_component::<Size>(entry, assets, &mut inserts)?;
+    load_component::<Collider>(entry, assets, &mut inserts)?;
+    load_component::<Controlled>(entry, assets, &mut inserts)?;
+    load_component::<Actor>(entry, assets, &mut inserts)?;
+    load_component::<Drawn>(entry, assets, &mut inserts)?;
+    load_component::<Animate>(entry, assets, &mut inserts)?;
+    load_component::<Health>(entry, assets, &mut inserts)?;
+    load_component::<AttackStats>(entry, assets, &mut inserts)?;
+    load_component::<Defense>(entry, assets, &mut inserts)?;
+    load_component::<Hostile>(entry, assets, &mut inserts)?;
+    load_component::<Corpse>(entry, assets, &mut inserts)?;
+    load_component::<Brain>(entry, assets, &mut inserts)?;
+    Ok(inserts)
+}
+
+fn save_map(map: &TileMap) -> Value {
+    let frames = map
+        .tileset
+        .frames
+        .iter()
+        .map(|frame| {
+            table(vec![
+                ("name", Value::String(frame.name.clone())),
+                ("x", Value::Integer(frame.x as i64)),
+                ("y", Value::Integer(frame.y as i64)),
+                ("width", Value::Integer(frame.width as i64)),
+                ("height", Value::Integer(frame.height as i64)),
+            ])
+        })
+        .collect();
+
+    let layers = map
+        .layers
+        .iter()
+        .map(|layer| {
+            let tiles = layer
+                .tiles
+                .iter()
+                .map(|tile| Value::Integer(*tile as i64))
+                .collect();
+            table(vec![
+                ("name", Value::String(layer.name.clone())),
+                ("tiles", Value::Array(tiles)),
+            ])
+        })
+        .collect();
+
+    table(vec![
+        ("width", Value::Integer(map.width as i64)),
+        ("height", Value::Integer(map.height as i64)),
+        ("tile_size", Value::Float(map.tile_size as f64)),
+        ("origin", floats(&[map.origin.0, map.origin.1])),
+        ("image", Value::String(map.tileset.image.clone())),
+        ("image_width", Value::Integer(map.tileset.width as i64)),
+        ("image_height", Value::Integer(map.tileset.height as i64)),
+        // Laid out like a sprite sheet descriptor so the tileset can be parsed the same way
+        ("frame", Value::Array(frames)),
+        ("layer", Value::Array(layers)),
+        (
+            "solid",
+            Value::Array(
+                map.solid
+                    .iter()
+                    .map(|solid| Value::Boolean(*solid))
+                    .collect()
+            )
+        ),
+    ])
+}
+
+fn load_map(value: &Value) -> Result<TileMap, SaveError> {
+    let fields = Fields::new("map", value);
+    let width = fields.unsigned("width")?;
+    let height = fields.unsigned("height")?;
+
+    let tileset = Atlas::from_descriptor(
+        value,
+        fields.string("image")?,
+        fields.unsigned("image_width")?,
+        fields.unsigned("image_height")?
+    )
+    .map_err(SaveError::Atlas)?;
+
+    let layers = fields
+        .array("layer")?
+        .iter()
+        .map(|layer| {
+            let layer = Fields::new("map.layer", layer);
+            let tiles = layer
+                .array("tiles")?
+                .iter()
+                .map(|tile| match tile {
+                    Value::Integer(tile) if *tile >= 0 && *tile <= tileset.frames.len() as i64 => {
+                        Ok(*tile as u32)
+                    },
+                    _ => Err(layer.invalid("tiles", "tile ids of the tileset"))
+                })
+                .collect::<Result<Vec<u32>, SaveError>>()?;
+
+            let cells = width.checked_mul(height).map(|cells| cells as usize);
+            if cells != Some(tiles.len()) {
+                return Err(layer.invalid("tiles", "a tile for every cell"));
+            }
+
+            Ok(map::Layer {
+                name: layer.string("name")?,
+                tiles
+            })
+        })
+        .collect::<Result<Vec<map::Layer>, SaveError>>()?;
+
+    let solid = fields
+        .array("solid")?
+        .iter()
+        .map(|solid| match solid {
+            Value::Boolean(solid) => Ok(*solid),
+            _ => Err(fields.invalid("solid", "an array of true or false"))
+        })
+        .collect::<Result<Vec<bool>, SaveError>>()?;
+    if solid.len() != tileset.frames.len() + 1 {
+        return Err(fields.invalid("solid", "a value for each tile id"));
+    }
+
+    Ok(TileMap {
+        width,
+        height,
+        tile_size: fields.float("tile_size")?,
+        origin: fields.pair("origin")?,
+        tileset,
+        layers,
+        solid
+    })
+}
+
+/// Writes the entities, map and random number state of a world
+/// # Arguments
+/// * `world` - The world to save
+pub fn save(world: &World) -> Result<String, SaveError> {
+    let mut root = Table::new();
+    root.insert(String::from("version"), Value::Integer(VERSION));
+
+    // Seeds do not fit in a TOML integer
+    root.insert(
+        String::from("resources"),
+        table(vec![
+            (
+                "rng",
+                Value::String(world.read_resource::<Rng>().state().to_string())
+            ),
+            (
+                "level_seed",
+                Value::String(world.read_resource::<LevelSeed>().0.to_string())
+            ),
+            (
+                "hud_text",
+                Value::String(world.read_resource::<HudText>().0.clone())
+            ),
+        ])
+    );
+
+    if let Some(map) = world.try_fetch::<TileMap>() {
+        root.insert(String::from("map"), save_map(&map));
+    }
+
+    // Sorted by their contents so the file does not depend on which entity ids were free
+    let mut entities: Vec<Value> = (&world.entities())
+        .join()
+        .map(|entity| save_entity(world, entity))
+        .collect();
+    entities.sort_by_cached_key(|entry| entry.to_string());
+    root.insert(String::from("entity"), Value::Array(entities));
+
+    toml::to_string(&Value::Table(root)).map_err(|error| SaveError::Toml(error.to_string()))
+}
+
+/// Replaces the entities, map and random number state of a world with a saved game
+/// Nothing in the world is changed if the save can not be read.
+/// # Arguments
+/// * `world` - The world to load into
+/// * `source` - The contents of a save file
+pub fn load(world: &mut World, source: &str) -> Result<(), SaveError> {
+    let root = source
+        .parse::<Value>()
+        .map_err(|error| SaveError::Toml(error.to_string()))?;
+    let fields = Fields::new("save", &root);
+
+    let version = match fields.get("version")? {
+        Value::Integer(version) => *version,
+        _ => return Err(fields.invalid("version", "an integer"))
+    };
+    if version != VERSION {
+        return Err(SaveError::Version(version));
+    }
+
+    // Read everything before changing the world
+    let resources = Fields::new("resources", fields.get("resources")?);
+    let rng = resources.seed("rng")?;
+    let level_seed = resources.seed("level_seed")?;
+    let hud_text = resources.string("hud_text")?;
+
+    let map = match root.get("map") {
+        Some(map) => Some(load_map(map)?),
+        None => None
+    };
+
+    let (tileset, entities) = {
+        let mut assets = world.write_resource::<AssetManager>();
+        let tileset = match &map {
+            Some(map) => Some(
+                assets
+                    .load_texture(&map.tileset.image)
+                    .map_err(SaveError::Asset)?
+            ),
+            None => None
+        };
+        let entities = fields
+            .array("entity")?
+            .iter()
+            .map(|entry| load_entity(entry, &mut assets))
+            .collect::<Result<Vec<Vec<Insert>>, SaveError>>()?;
+        (tileset, entities)
+    };
+
+    // Swap out the current game
+    world.delete_all();
+    world.maintain();
+
+    for inserts in entities {
+        inserts
+            .into_iter()
+            .fold(world.create_entity(), |builder, insert| insert(builder))
+            .build();
+    }
+
+    world.insert(Rng::from_state(rng));
+    world.insert(LevelSeed(level_seed));
+    world.insert(HudText(hud_text));
+    world.insert(PendingAction::default());
+    world.insert(MeleeAttacks::default());
+
+    if let (Some(mut renderer), Some(tileset)) =
+        (world.try_fetch_mut::<Box<dyn Renderer>>(), tileset)
+    {
+        renderer.set_map(tileset);
+    }
+
+    // Start with the player in view
+    let bounds = map.as_ref().map(|map| map.bounds());
+    match map {
+        Some(map) => world.insert(map),
+        None => {
+            world.remove::<TileMap>();
+        }
+    }
+    let player = {
+        let controlled = world.read_storage::<Controlled>();
+        let position = world.read_storage::<Position>();
+        (&controlled, &position)
+            .join()
+            .next()
+            .map(|(_, position)| (position.x, position.y))
+    };
+    let mut camera = world.write_resource::<Camera>();
+    camera.bounds = bounds;
+    if let Some((x, y)) = player {
+        camera.snap_to(x, y);
+    }
+
+    Ok(())
+}
+
+/// Saves a world to a file
+/// # Arguments
+/// * `world` - The world to save
+/// * `path` - The file to write
+pub fn save_file(world: &World, path: &str) -> Result<(), SaveError> {
+    fs::write(path, save(world)?).map_err(|error| SaveError::Io {
+        path: String::from(path),
+        message: error.to_string()
+    })
+}
+
+/// Loads a world from a file
+/// # Arguments
+/// * `world` - The world to load into
+/// * `path` - The file to read
+pub fn load_file(world: &mut World, path: &str) -> Result<(), SaveError> {
+    let source = fs::read_to_string(path).map_err(|error| SaveError::Io {
+        path: String::from(path),
+        message: error.to_string()
+    })?;
+    load(world, &source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    /// A game part way through, with a player mid animation, a wounded ogre chasing them and a
+    /// chest on a small map
+    fn game() -> World {
+        let mut world = fixture::world();
+
+        let mut map = TileMap::new(4, 3, 0.25, Atlas::load("./src/tiles/dungeon.toml").unwrap());
+        map.origin = (-0.5, -0.5);
+        for column in 0..4 {
+            map.set_tile(0, column, 0, 2);
+            map.set_tile(0, column, 1, 1);
+        }
+        map.solid[2] = true;
+        world.insert(map);
+
+        let mut rng = Rng::new(99);
+        rng.next_u64();
+        world.insert(rng);
+        world.insert(LevelSeed(u64::MAX - 1));
+        world.insert(HudText(String::from("Health 7/10")));
+
+        let player = fixture::spawn(&mut world, "player", 0.1, 0.2);
+        let ogre = fixture::spawn(&mut world, "ogre", -0.3, 0.2);
+        fixture::spawn(&mut world, "chest", 0.4, -0.1);
+
+        world
+            .write_storage::<Velocity>()
+            .insert(
+                player,
+                Velocity {
+                    x: 0.5,
+                    y: -0.25,
+                    z: 0.0
+                }
+            )
+            .unwrap();
+        {
+            let mut animate = world.write_storage::<Animate>();
+            let animator = &mut animate.get_mut(player).unwrap().animator;
+            animator.play("walk");
+            animator.advance(Duration::from_millis(150));
+        }
+        world
+            .write_storage::<Health>()
+            .get_mut(player)
+            .unwrap()
+            .current = 7;
+        world
+            .write_storage::<Health>()
+            .get_mut(ogre)
+            .unwrap()
+            .current = 2;
+        {
+            let mut brains = world.write_storage::<Brain>();
+            let brain = brains.get_mut(ogre).unwrap();
+            brain.behaviour = Behaviour::Chase;
+            brain.home = Some((0, 1));
+            brain.target = Some((1, 1));
+        }
+
+        world
+    }
+
+    #[test]
+    fn loading_a_save_restores_it_exactly() {
+        let saved = save(&game()).unwrap();
+
+        let mut world = fixture::world();
+        load(&mut world, &saved).unwrap();
+
+        assert_eq!(save(&world).unwrap(), saved);
+    }
+
+    #[test]
+    fn loading_restores_components_and_resources() {
+        let game = game();
+        let mut world = fixture::world();
+        load(&mut world, &save(&game).unwrap()).unwrap();
+
+        assert_eq!(
+            world.read_resource::<Rng>().state(),
+            game.read_resource::<Rng>().state()
+        );
+        assert_eq!(world.read_resource::<LevelSeed>().0, u64::MAX - 1);
+        assert_eq!(world.read_resource::<HudText>().0, "Health 7/10");
+        assert!(*world.read_resource::<TileMap>() == *game.read_resource::<TileMap>());
+
+        let brains = world.read_storage::<Brain>();
+        let health = world.read_storage::<Health>();
+        let (brain, health) = (&brains, &health).join().next().unwrap();
+        assert_eq!(brain.behaviour, Behaviour::Chase);
+        assert_eq!(brain.home, Some((0, 1)));
+        assert_eq!(health.current, 2);
+
+        let playback = |world: &World| {
+            let animate = world.read_storage::<Animate>();
+            let controlled = world.read_storage::<Controlled>();
+            let (animate, _) = (&animate, &controlled).join().next().unwrap();
+            let playback = animate.animator.playback();
+            (
+                animate.animator.clips()[playback.clip].name.clone(),
+                playback
+            )
+        };
+        let (clip, loaded) = playback(&world);
+        assert_eq!(clip, "walk");
+        assert_eq!(loaded.keyframe, 1);
+        assert_eq!(loaded, playback(&game).1);
+    }
+
+    #[test]
+    fn saves_do_not_depend_on_entity_ids() {
+        // Spawning and deleting an entity first shifts the ids of the rest
+        let mut world = fixture::world();
+        let extra = world.create_entity().build();
+        world.delete_entity(extra).unwrap();
+        world.maintain();
+        load(&mut world, &save(&game()).unwrap()).unwrap();
+
+        assert_eq!(save(&world).unwrap(), save(&game()).unwrap());
+    }
+
+    #[test]
+    fn other_versions_are_rejected_without_changing_the_world() {
+        let mut world = game();
+        let before = save(&world).unwrap();
+        let other = save(&fixture::world())
+            .unwrap()
+            .replace(&format!("version = {}", VERSION), "version = 2");
+
+        match load(&mut world, &other) {
+            Err(SaveError::Version(2)) => {},
+            other => panic!("Expected a version error, got {:?}", other)
+        }
+        assert_eq!(save(&world).unwrap(), before);
+    }
+
+    #[test]
+    fn missing_textures_are_rejected_without_changing_the_world() {
+        let mut world = game();
+        let before = save(&world).unwrap();
+        let broken = before.replace("dungeon.png", "missing.png");
+
+        match load(&mut world, &broken) {
+            Err(SaveError::Asset(AssetError::NotFound(_))) => {},
+            other => panic!("Expected a missing asset, got {:?}", other)
+        }
+        assert_eq!(save(&world).unwrap(), before);
+    }
+
+    /// Changes a saved game and writes it out again
+    fn edited<F: FnOnce(&mut Value)>(source: &str, edit: F) -> String {
+        let mut root = source.parse::<Value>().unwrap();
+        edit(&mut root);
+        toml::to_string(&root).unwrap()
+    }
+
+    /// The component of the first saved entity that has it
+    fn component<'a>(root: &'a mut Value, key: &str) -> &'a mut Value {
+        root["entity"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .find_map(|entity| entity.get_mut(key))
+            .unwrap()
+    }
+
+    /// Checks a changed save is rejected for a field and leaves the world as it was
+    fn assert_rejected<F: FnOnce(&mut Value)>(field: &str, edit: F) {
+        let mut world = game();
+        let before = save(&world).unwrap();
+
+        match load(&mut world, &edited(&before, edit)) {
+            Err(SaveError::InvalidField { field: found, .. }) => assert_eq!(found, field),
+            other => panic!("Expected {} to be invalid, got {:?}", field, other)
+        }
+        assert_eq!(save(&world).unwrap(), before);
+    }
+
+    #[test]
+    fn rejects_short_texture_coordinates() {
+        assert_rejected("drawn.uv", |root| {
+            component(root, "drawn")["uv"] = Value::Array(vec![Value::Float(0.0); 4]);
+        });
+        assert_rejected("animate.frame_uvs", |root| {
+            let frame_uvs = component(root, "animate")["frame_uvs"]
+                .as_array_mut()
+                .unwrap();
+            frame_uvs[0].as_array_mut().unwrap().truncate(11);
+        });
+    }
+
+    #[test]
+    fn rejects_maps_with_more_cells_than_fit() {
+        assert_rejected("map.layer.tiles", |root| {
+            root["map"]["width"] = Value::Integer(1 << 16);
+            root["map"]["height"] = Value::Integer(1 << 16);
+        });
+    }
+
+    #[test]
+    fn rejects_cells_out_of_range() {
+        assert_rejected("brain.home", |root| {
+            component(root, "brain")["home"] =
+                Value::Array(vec![Value::Integer(i32::MAX as i64 + 1), Value::Integer(0)]);
+        });
+    }
+
+    #[test]
+    fn rejects_speeds_too_fast_to_schedule() {
+        assert_rejected("actor.speed", |root| {
+            component(root, "actor")["speed"] = Value::Integer(u32::MAX as i64);
+        });
+    }
+
+    #[test]
+    fn rejects_crit_multipliers_below_one() {
+        for multiplier in &[0.5, f64::NAN, f64::INFINITY] {
+            assert_rejected("attack.crit_multiplier", |root| {
+                component(root, "attack")["crit_multiplier"] = Value::Float(*multiplier);
+            });
+        }
+    }
+
+    #[test]
+    fn rejects_brains_that_can_not_think() {
+        assert_rejected("brain.sight", |root| {
+            component(root, "brain")["sight"] = Value::Integer(u32::MAX as i64);
+        });
+        assert_rejected("brain.flee_below", |root| {
+            component(root, "brain")["flee_below"] = Value::Float(1.5);
+        });
+        for speed in &[-0.5, f64::NAN, f64::INFINITY] {
+            assert_rejected("brain.speed", |root| {
+                component(root, "brain")["speed"] = Value::Float(*speed);
+            });
+        }
+    }
+
+    #[test]
+    fn invalid_saves_are_rejected() {
+        let mut world = fixture::world();
+
+        assert!(matches!(
+            load(&mut world, "version = "),
+            Err(SaveError::Toml(_))
+        ));
+        assert!(matches!(
+            load(&mut world, "entity = []"),
+            Err(SaveError::MissingField(_))
+        ));
+        assert!(matches!(
+            load(&mut world, "version = \"1\""),
+            Err(SaveError::InvalidField { .. })
+        ));
+    }
+}
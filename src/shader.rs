@@ -1,13 +1,83 @@
 extern crate gl;
-use gl::types::{GLchar, GLenum, GLint, GLuint};
+use gl::types::{GLchar, GLenum, GLint, GLsizei, GLuint};
 
+use std::collections::HashMap;
 use std::ffi::CString;
+use std::fmt;
 use std::fs;
+use std::time::SystemTime;
+
+use crate::error::Error;
+use crate::preprocessor;
+
+/// Name reported for shaders compiled from a string rather than a file
+const SOURCE_NAME: &str = "<source>";
+
+/// Errors raised when setting the uniforms of a shader program
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniformError {
+    /// The program has no active uniform with this name
+    Unknown(String),
+    /// The uniform is declared with a different type than the value written to it
+    Type {
+        name: String,
+        expected: &'static str,
+        found: GLenum
+    },
+    /// The value has the wrong number of elements for the uniform type
+    Length {
+        name: String,
+        expected: usize,
+        found: usize
+    }
+}
+
+impl fmt::Display for UniformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UniformError::Unknown(name) => write!(f, "No active uniform named '{}'", name),
+            UniformError::Type {
+                name,
+                expected,
+                found
+            } => write!(
+                f,
+                "Uniform '{}' is a {}, not a {}",
+                name,
+                type_name(*found),
+                expected
+            ),
+            UniformError::Length {
+                name,
+                expected,
+                found
+            } => write!(
+                f,
+                "Uniform '{}' needs {} values, got {}",
+                name, expected, found
+            )
+        }
+    }
+}
+
+impl std::error::Error for UniformError {}
+
+/// An active uniform or vertex attribute of a linked program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Variable {
+    pub location: GLint,
+    /// The GLSL type, e.g. `gl::FLOAT_VEC3`
+    pub kind: GLenum,
+    /// Number of elements, more than 1 for arrays
+    pub size: GLint
+}
 
 /// Represents a compiled shader object
 pub struct Shader {
     id: GLuint,
-    kind: GLenum
+    kind: GLenum,
+    /// Files the source was read from, the shader file followed by its includes
+    files: Vec<String>
 }
 
 impl Drop for Shader {
@@ -24,11 +94,54 @@ impl Shader {
     /// # Arguments
     /// * `path` - A string slice that holds the file path
     /// * `kind` - The type of shader to create, i.e. vertext, fragment, compute, etc...
-    pub fn new_from_file(path: &str, kind: GLenum) -> Result<Shader, String> {
-        // Read the source file in as a string
-        let source = match fs::read_to_string(path) {
-            Ok(string) => string,
-            Err(message) => panic!(format!("Shader creation failed: {}", message))
+    pub fn new_from_file(path: &str, kind: GLenum) -> Result<Shader, Error> {
+        Shader::new_from_file_with_defines(path, kind, &[])
+    }
+
+    /// Returns a new shader object from a file, expanding its includes and adding defines
+    /// Errors from the compiler refer to the lines of the original files.
+    /// # Arguments
+    /// * `path` - A string slice that holds the file path
+    /// * `kind` - The type of shader to create, i.e. vertext, fragment, compute, etc...
+    /// * `defines` - Names and values to `#define` after the version line
+    pub fn new_from_file_with_defines(
+        path: &str,
+        kind: GLenum,
+        defines: &[(&str, &str)]
+    ) -> Result<Shader, Error> {
+        // Read the source file in and expand its includes
+        let processed = preprocessor::preprocess_file(path, defines)?;
+
+        let mut shader =
+            Shader::new_from_source(&processed.code, kind).map_err(|error| match error {
+                Error::ShaderCompile { log, .. } => Error::ShaderCompile {
+                    path: String::from(path),
+                    log: processed.remap_log(&log)
+                },
+                error => error
+            })?;
+        shader.files = processed.files;
+        Ok(shader)
+    }
+
+    /// Files the shader was read from, the shader file followed by its includes
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// Returns a new shader object compiled from a string
+    /// # Arguments
+    /// * `source` - The GLSL source code
+    /// * `kind` - The type of shader to create, i.e. vertext, fragment, compute, etc...
+    pub fn new_from_source(source: &str, kind: GLenum) -> Result<Shader, Error> {
+        let source = match CString::new(source) {
+            Ok(source) => source,
+            Err(_) => {
+                return Err(Error::ShaderCompile {
+                    path: String::from(SOURCE_NAME),
+                    log: String::from("Shader source contains a nul byte")
+                })
+            },
         };
 
         // Create a shader object on the GPU
@@ -36,12 +149,7 @@ impl Shader {
 
         // Compile the shader
         unsafe {
-            gl::ShaderSource(
-                id,
-                1,
-                &CString::new(source).unwrap().as_ptr(),
-                std::ptr::null()
-            );
+            gl::ShaderSource(id, 1, &source.as_ptr(), std::ptr::null());
             gl::CompileShader(id);
         };
 
@@ -53,7 +161,11 @@ impl Shader {
 
         // Return the shader if it compiled
         if compile_status == 1 {
-            Ok(Shader { id, kind })
+            Ok(Shader {
+                id,
+                kind,
+                files: vec![]
+            })
         } else {
             // Get the length of the error log
             let mut len: i32 = 0;
@@ -65,18 +177,44 @@ impl Shader {
             let error = create_cstring(len as u32);
             unsafe {
                 gl::GetShaderInfoLog(id, len, std::ptr::null_mut(), error.as_ptr() as *mut GLchar);
+                gl::DeleteShader(id);
             };
 
             // Return the error
-            Err(error.to_string_lossy().into_owned())
+            Err(Error::ShaderCompile {
+                path: String::from(SOURCE_NAME),
+                log: error.to_string_lossy().into_owned()
+            })
         }
     }
 }
 
+/// A shader file a program was built from
+struct ShaderSource {
+    path: String,
+    kind: GLenum,
+    /// Each file read when the shader was last compiled and its modification time then
+    dependencies: Vec<(String, Option<SystemTime>)>
+}
+
+/// Returns when a file was last modified, if it can be read
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
 /// Represents a complete shader program
+/// Uniform and attribute locations are looked up once when the program is linked.
 pub struct Program {
     pub id: GLuint,
-    shaders: Vec<GLuint>
+    shaders: Vec<GLuint>,
+    uniforms: HashMap<String, Variable>,
+    attributes: HashMap<String, Variable>,
+    /// Files to rebuild the program from when reloading
+    sources: Vec<ShaderSource>,
+    /// Names and values defined in every shader of the program
+    defines: Vec<(String, String)>
 }
 
 impl Program {
@@ -85,7 +223,104 @@ impl Program {
         let id = unsafe { gl::CreateProgram() };
         Program {
             id,
-            shaders: vec![]
+            shaders: vec![],
+            uniforms: HashMap::new(),
+            attributes: HashMap::new(),
+            sources: vec![],
+            defines: vec![]
+        }
+    }
+
+    /// Compiles and links a program from shader files, remembering them so it can be reloaded
+    /// # Arguments
+    /// * `files` - Path and type of each shader in the program
+    /// * `defines` - Names and values to `#define` in every shader
+    pub fn new_from_files(
+        files: &[(&str, GLenum)],
+        defines: &[(&str, &str)]
+    ) -> Result<Program, Error> {
+        let mut sources: Vec<ShaderSource> = files
+            .iter()
+            .map(|(path, kind)| ShaderSource {
+                path: String::from(*path),
+                kind: *kind,
+                dependencies: vec![(String::from(*path), modified(path))]
+            })
+            .collect();
+        let defines: Vec<(String, String)> = defines
+            .iter()
+            .map(|(name, value)| (String::from(*name), String::from(*value)))
+            .collect();
+
+        let mut program = Program::link_sources(&mut sources, &defines)?;
+        program.sources = sources;
+        program.defines = defines;
+        Ok(program)
+    }
+
+    /// Compiles every shader file and links them into a new program
+    /// The files each shader read are recorded so changes to its includes are noticed.
+    fn link_sources(
+        sources: &mut [ShaderSource],
+        defines: &[(String, String)]
+    ) -> Result<Program, Error> {
+        let defines: Vec<(&str, &str)> = defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+
+        let mut shaders = vec![];
+        for source in sources.iter_mut() {
+            let shader = Shader::new_from_file_with_defines(&source.path, source.kind, &defines)?;
+            source.dependencies = shader
+                .files()
+                .iter()
+                .map(|file| (file.clone(), modified(file)))
+                .collect();
+            shaders.push(shader);
+        }
+
+        shaders
+            .iter()
+            .fold(Program::new(), |program, shader| {
+                program.attach_shader(shader)
+            })
+            .link()
+    }
+
+    /// Rebuilds the program from its shader files
+    /// The program is only replaced if every shader compiles and the program links, otherwise
+    /// the old program is kept. Uniform values are not carried over to the new program.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        let mut program = Program::link_sources(&mut self.sources, &self.defines)?;
+
+        // The replaced program is deleted when it is dropped
+        std::mem::swap(&mut self.id, &mut program.id);
+        std::mem::swap(&mut self.uniforms, &mut program.uniforms);
+        std::mem::swap(&mut self.attributes, &mut program.attributes);
+        Ok(())
+    }
+
+    /// Reloads the program if any of its shader files or their includes changed since they were
+    /// last compiled
+    /// Returns whether the program was replaced. A file that fails to compile is not retried
+    /// until it changes again.
+    pub fn reload_if_changed(&mut self) -> Result<bool, Error> {
+        let mut changed = false;
+        for source in &mut self.sources {
+            for (path, last_modified) in &mut source.dependencies {
+                let modified = modified(path);
+                if modified != *last_modified {
+                    *last_modified = modified;
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            self.reload().map(|_| true)
+        } else {
+            Ok(false)
         }
     }
 
@@ -100,7 +335,7 @@ impl Program {
     }
 
     /// Links the shader program
-    pub fn link(mut self) -> Result<Program, String> {
+    pub fn link(mut self) -> Result<Program, Error> {
         // Link the program
         unsafe {
             gl::LinkProgram(self.id);
@@ -120,6 +355,19 @@ impl Program {
                 };
             }
 
+            self.uniforms = self.active_variables(
+                gl::ACTIVE_UNIFORMS,
+                gl::ACTIVE_UNIFORM_MAX_LENGTH,
+                gl::GetActiveUniform,
+                gl::GetUniformLocation
+            );
+            self.attributes = self.active_variables(
+                gl::ACTIVE_ATTRIBUTES,
+                gl::ACTIVE_ATTRIBUTE_MAX_LENGTH,
+                gl::GetActiveAttrib,
+                gl::GetAttribLocation
+            );
+
             Ok(self)
         } else {
             // Get the length of the error log
@@ -140,16 +388,174 @@ impl Program {
             };
 
             // Return the error
-            Err(error.to_string_lossy().into_owned())
+            Err(Error::ShaderLink(error.to_string_lossy().into_owned()))
         }
     }
 
+    /// Reads the names, types and locations of the active uniforms or attributes
+    /// # Arguments
+    /// * `count` - Parameter holding the number of active variables
+    /// * `max_length` - Parameter holding the longest variable name
+    /// * `get_active` - `glGetActiveUniform` or `glGetActiveAttrib`
+    /// * `get_location` - `glGetUniformLocation` or `glGetAttribLocation`
+    fn active_variables(
+        &self,
+        count: GLenum,
+        max_length: GLenum,
+        get_active: unsafe fn(
+            GLuint,
+            GLuint,
+            GLsizei,
+            *mut GLsizei,
+            *mut GLint,
+            *mut GLenum,
+            *mut GLchar
+        ),
+        get_location: unsafe fn(GLuint, *const GLchar) -> GLint
+    ) -> HashMap<String, Variable> {
+        let mut variables = HashMap::new();
+
+        let mut total: GLint = 0;
+        let mut buffer_length: GLint = 0;
+        unsafe {
+            gl::GetProgramiv(self.id, count, &mut total);
+            gl::GetProgramiv(self.id, max_length, &mut buffer_length);
+        };
+
+        for index in 0..total.max(0) as GLuint {
+            let mut buffer = vec![0u8; buffer_length.max(1) as usize];
+            let mut length: GLsizei = 0;
+            let mut size: GLint = 0;
+            let mut kind: GLenum = 0;
+            unsafe {
+                get_active(
+                    self.id,
+                    index,
+                    buffer_length,
+                    &mut length,
+                    &mut size,
+                    &mut kind,
+                    buffer.as_mut_ptr() as *mut GLchar
+                );
+            };
+            buffer.truncate(length.max(0) as usize);
+
+            let name = match CString::new(buffer) {
+                Ok(name) => name,
+                Err(_) => continue
+            };
+            let location = unsafe { get_location(self.id, name.as_ptr()) };
+
+            // Arrays are reported as their first element
+            let name = name.to_string_lossy();
+            let name = name.trim_end_matches("[0]");
+
+            variables.insert(
+                String::from(name),
+                Variable {
+                    location,
+                    kind,
+                    size
+                }
+            );
+        }
+
+        variables
+    }
+
     /// Sets this as the active shader program
     pub fn set_used(&self) {
         unsafe {
             gl::UseProgram(self.id);
         };
     }
+
+    /// Finds an active uniform by name
+    /// Uniforms the compiler optimized away are not active.
+    pub fn uniform(&self, name: &str) -> Option<&Variable> {
+        self.uniforms.get(name)
+    }
+
+    /// Finds an active vertex attribute by name
+    pub fn attribute(&self, name: &str) -> Option<&Variable> {
+        self.attributes.get(name)
+    }
+
+    /// Looks up a uniform and checks it has one of the expected types
+    /// # Arguments
+    /// * `name` - The name of the uniform
+    /// * `expected` - Description of the value being written, used in errors
+    /// * `kinds` - GLSL types the value can be written to
+    fn location(
+        &self,
+        name: &str,
+        expected: &'static str,
+        kinds: &[GLenum]
+    ) -> Result<GLint, UniformError> {
+        let uniform = self
+            .uniforms
+            .get(name)
+            .ok_or_else(|| UniformError::Unknown(String::from(name)))?;
+
+        if kinds.contains(&uniform.kind) {
+            Ok(uniform.location)
+        } else {
+            Err(UniformError::Type {
+                name: String::from(name),
+                expected,
+                found: uniform.kind
+            })
+        }
+    }
+
+    /// Sets a vec2 uniform, the program must be in use
+    pub fn set_vec2(&self, name: &str, value: [f32; 2]) -> Result<(), UniformError> {
+        let location = self.location(name, "vec2", &[gl::FLOAT_VEC2])?;
+        unsafe { gl::Uniform2fv(location, 1, value.as_ptr()) };
+        Ok(())
+    }
+
+    /// Sets a vec3 uniform, the program must be in use
+    pub fn set_vec3(&self, name: &str, value: [f32; 3]) -> Result<(), UniformError> {
+        let location = self.location(name, "vec3", &[gl::FLOAT_VEC3])?;
+        unsafe { gl::Uniform3fv(location, 1, value.as_ptr()) };
+        Ok(())
+    }
+
+    /// Sets a mat4 uniform, the program must be in use
+    /// Fails with `UniformError::Length` if `value` does not hold 16 values.
+    /// # Arguments
+    /// * `name` - The name of the uniform
+    /// * `value` - Column major matrix, e.g. `Matrix4::as_slice`
+    pub fn set_mat4(&self, name: &str, value: &[f32]) -> Result<(), UniformError> {
+        let location = self.location(name, "mat4", &[gl::FLOAT_MAT4])?;
+        if value.len() != 16 {
+            return Err(UniformError::Length {
+                name: String::from(name),
+                expected: 16,
+                found: value.len()
+            });
+        }
+        unsafe { gl::UniformMatrix4fv(location, 1, gl::FALSE, value.as_ptr()) };
+        Ok(())
+    }
+
+    /// Sets an int or bool uniform, the program must be in use
+    pub fn set_i32(&self, name: &str, value: i32) -> Result<(), UniformError> {
+        let location = self.location(name, "int", &[gl::INT, gl::BOOL])?;
+        unsafe { gl::Uniform1i(location, value) };
+        Ok(())
+    }
+
+    /// Points a sampler uniform at a texture unit, the program must be in use
+    /// # Arguments
+    /// * `name` - The name of the uniform
+    /// * `unit` - Texture unit, 0 for `gl::TEXTURE0`
+    pub fn set_sampler(&self, name: &str, unit: u32) -> Result<(), UniformError> {
+        let location = self.location(name, "sampler", SAMPLER_TYPES)?;
+        unsafe { gl::Uniform1i(location, unit as i32) };
+        Ok(())
+    }
 }
 
 impl Drop for Program {
@@ -161,6 +567,46 @@ impl Drop for Program {
     }
 }
 
+/// GLSL types a texture unit can be written to
+const SAMPLER_TYPES: &[GLenum] = &[
+    gl::SAMPLER_1D,
+    gl::SAMPLER_2D,
+    gl::SAMPLER_3D,
+    gl::SAMPLER_CUBE,
+    gl::SAMPLER_2D_ARRAY,
+    gl::SAMPLER_2D_SHADOW,
+    gl::INT_SAMPLER_2D,
+    gl::UNSIGNED_INT_SAMPLER_2D
+];
+
+/// Returns the GLSL name of a uniform type
+pub fn type_name(kind: GLenum) -> &'static str {
+    match kind {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::BOOL => "bool",
+        gl::SAMPLER_1D => "sampler1D",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        gl::INT_SAMPLER_2D => "isampler2D",
+        gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        _ => "unknown type"
+    }
+}
+
 /// Creates an empty Cstring
 /// # Arguments
 /// * `len` - The length of the string to create
@@ -0,0 +1,742 @@
+use crate::camera::Camera;
+use crate::component_system::components::Controlled;
+use crate::component_system::resources::FrameTime;
+use crate::error::Error;
+use crate::input::{InputAction, InputState};
+use crate::render::Renderer;
+use crate::save;
+use crate::timestep::Timestep;
+use specs::prelude::*;
+
+/// Space between text and the edges of the screen
+const MARGIN: f32 = 0.03;
+
+/// File written by a quick save and read by a quick load
+pub const SAVE_PATH: &str = "./save.toml";
+
+/// The screens the game can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateId {
+    Title,
+    Playing,
+    Paused,
+    Inventory,
+    GameOver
+}
+
+/// A change to the state stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// Suspends the current state and shows a new one above it
+    Push(StateId),
+    /// Returns to the state below the current one
+    Pop,
+    /// Swaps the current state for a new one
+    Replace(StateId),
+    /// Removes every state, ending the game
+    Quit
+}
+
+/// Transitions requested during a frame, applied by the `StateStack` in order once the current
+/// state has finished updating
+#[derive(Debug, Default)]
+pub struct Transitions(Vec<Transition>);
+
+impl Transitions {
+    pub fn push(&mut self, state: StateId) {
+        self.0.push(Transition::Push(state));
+    }
+
+    pub fn pop(&mut self) {
+        self.0.push(Transition::Pop);
+    }
+
+    pub fn replace(&mut self, state: StateId) {
+        self.0.push(Transition::Replace(state));
+    }
+
+    pub fn quit(&mut self) {
+        self.0.push(Transition::Quit);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Removes and returns the requested transitions
+    pub fn drain(&mut self) -> Vec<Transition> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+/// A screen of the game with its own systems and input handling
+/// Only the state on top of the stack is updated. States below it are drawn as well while
+/// every state above them is an overlay.
+pub trait State {
+    fn id(&self) -> StateId;
+
+    /// Called when the state is added to the stack
+    fn on_enter(&mut self, _world: &mut World) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called when the state is removed from the stack
+    fn on_exit(&mut self, _world: &mut World) {}
+
+    /// Called when another state is pushed above this one
+    fn on_pause(&mut self, _world: &mut World) {}
+
+    /// Called when the state above this one is popped
+    fn on_resume(&mut self, _world: &mut World) {}
+
+    /// Runs the state's systems and requests transitions through the `Transitions` resource
+    fn update(&mut self, world: &mut World);
+
+    /// Submits the state's drawing to the renderer, between the start and end of the frame
+    fn draw(&mut self, _world: &mut World) {}
+
+    /// Whether the states below this one are still drawn
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
+
+/// Creates the state for an id
+pub type StateFactory = Box<dyn FnMut(StateId) -> Box<dyn State>>;
+
+/// The states of the game, with the one being played on top
+pub struct StateStack {
+    states: Vec<Box<dyn State>>,
+    factory: StateFactory
+}
+
+impl StateStack {
+    /// Creates an empty stack
+    /// # Arguments
+    /// * `factory` - Creates the states that transitions ask for
+    pub fn new(factory: StateFactory) -> StateStack {
+        StateStack {
+            states: vec![],
+            factory
+        }
+    }
+
+    /// The ids of the states from the bottom of the stack to the top
+    pub fn ids(&self) -> Vec<StateId> {
+        self.states.iter().map(|state| state.id()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Suspends the top state and enters a new one above it
+    pub fn push(&mut self, id: StateId, world: &mut World) -> Result<(), Error> {
+        if let Some(top) = self.states.last_mut() {
+            top.on_pause(world);
+        }
+
+        let mut state = (self.factory)(id);
+        state.on_enter(world)?;
+        self.states.push(state);
+        Ok(())
+    }
+
+    /// Exits the top state and resumes the one below it
+    pub fn pop(&mut self, world: &mut World) {
+        if let Some(mut state) = self.states.pop() {
+            state.on_exit(world);
+        }
+        if let Some(top) = self.states.last_mut() {
+            top.on_resume(world);
+        }
+    }
+
+    /// Exits the top state and enters a new one in its place
+    pub fn replace(&mut self, id: StateId, world: &mut World) -> Result<(), Error> {
+        if let Some(mut state) = self.states.pop() {
+            state.on_exit(world);
+        }
+
+        let mut state = (self.factory)(id);
+        state.on_enter(world)?;
+        self.states.push(state);
+        Ok(())
+    }
+
+    /// Exits every state from the top down
+    pub fn clear(&mut self, world: &mut World) {
+        while let Some(mut state) = self.states.pop() {
+            state.on_exit(world);
+        }
+    }
+
+    /// Updates the top state, then applies the transitions it requested
+    pub fn update(&mut self, world: &mut World) -> Result<(), Error> {
+        if let Some(top) = self.states.last_mut() {
+            top.update(world);
+        }
+        self.apply_transitions(world)
+    }
+
+    /// Applies the transitions in the `Transitions` resource
+    pub fn apply_transitions(&mut self, world: &mut World) -> Result<(), Error> {
+        world
+            .entry::<Transitions>()
+            .or_insert_with(Transitions::default);
+        loop {
+            // Entering a state may request further transitions
+            let transitions = world.write_resource::<Transitions>().drain();
+            if transitions.is_empty() {
+                return Ok(());
+            }
+
+            for transition in transitions {
+                match transition {
+                    Transition::Push(id) => self.push(id, world)?,
+                    Transition::Pop => self.pop(world),
+                    Transition::Replace(id) => self.replace(id, world)?,
+                    Transition::Quit => self.clear(world)
+                }
+            }
+        }
+    }
+
+    /// Draws the top state and the states showing through it, from the bottom up
+    pub fn draw(&mut self, world: &mut World) {
+        let first = self
+            .states
+            .iter()
+            .rposition(|state| !state.is_overlay())
+            .unwrap_or(0);
+
+        if let Some(mut renderer) = world.try_fetch_mut::<Box<dyn Renderer>>() {
+            renderer.begin_frame();
+        }
+        for state in &mut self.states[first..] {
+            state.draw(world);
+        }
+        if let Some(mut renderer) = world.try_fetch_mut::<Box<dyn Renderer>>() {
+            renderer.end_frame();
+        }
+    }
+}
+
+/// Draws lines of text in the top left corner of the screen, below any HUD text
+fn draw_menu(world: &World, text: &str) {
+    let aspect = world.read_resource::<Camera>().aspect;
+    if let Some(mut renderer) = world.try_fetch_mut::<Box<dyn Renderer>>() {
+        renderer.draw_text(text, -aspect + MARGIN, 0.5);
+    }
+}
+
+/// Returns the value paired with the first of the actions that was just pressed
+fn first_pressed<T: Copy>(input: &InputState, actions: &[(InputAction, T)]) -> Option<T> {
+    actions
+        .iter()
+        .find(|(action, _)| input.just_pressed(*action))
+        .map(|(_, value)| *value)
+}
+
+/// Requests the transitions paired with the first of the actions that was just pressed
+/// Menus handle every press, so all are cleared and none are left for the next state.
+fn menu_input(world: &World, actions: &[(InputAction, &[Transition])]) {
+    let mut input = world.write_resource::<InputState>();
+    if let Some(transitions) = first_pressed(&input, actions) {
+        world
+            .write_resource::<Transitions>()
+            .0
+            .extend_from_slice(transitions);
+    }
+    input.clear_presses();
+}
+
+/// The menu shown when the game starts
+#[derive(Default)]
+pub struct TitleState;
+
+impl State for TitleState {
+    fn id(&self) -> StateId {
+        StateId::Title
+    }
+
+    fn update(&mut self, world: &mut World) {
+        menu_input(
+            world,
+            &[
+                (
+                    InputAction::Confirm,
+                    &[Transition::Replace(StateId::Playing)]
+                ),
+                (InputAction::Cancel, &[Transition::Quit])
+            ]
+        );
+    }
+
+    fn draw(&mut self, world: &mut World) {
+        draw_menu(world, "Rust Rogue\n\nConfirm to start\nCancel to quit");
+    }
+}
+
+/// Starts a new level
+pub type LevelBuilder = fn(&mut World) -> Result<(), Error>;
+
+/// Simulates the level in fixed steps and draws it
+pub struct PlayingState {
+    /// Systems run once per simulation step
+    update: Dispatcher<'static, 'static>,
+    /// Systems run once per frame to draw the level
+    render: Dispatcher<'static, 'static>,
+    new_level: LevelBuilder
+}
+
+impl PlayingState {
+    /// Creates the state
+    /// # Arguments
+    /// * `update` - Systems run once per simulation step
+    /// * `render` - Systems run once per frame to draw the level
+    /// * `new_level` - Starts a new level when the state is entered
+    pub fn new(
+        update: Dispatcher<'static, 'static>,
+        render: Dispatcher<'static, 'static>,
+        new_level: LevelBuilder
+    ) -> PlayingState {
+        PlayingState {
+            update,
+            render,
+            new_level
+        }
+    }
+}
+
+impl State for PlayingState {
+    fn id(&self) -> StateId {
+        StateId::Playing
+    }
+
+    fn on_enter(&mut self, world: &mut World) -> Result<(), Error> {
+        (self.new_level)(world)?;
+
+        // Do not catch up on the time spent in menus
+        world.write_resource::<Timestep>().skip();
+        Ok(())
+    }
+
+    fn on_resume(&mut self, world: &mut World) {
+        world.write_resource::<Timestep>().skip();
+    }
+
+    fn update(&mut self, world: &mut World) {
+        // Save between steps so the whole step is kept
+        let quick_save = first_pressed(
+            &world.read_resource::<InputState>(),
+            &[
+                (InputAction::QuickSave, true),
+                (InputAction::QuickLoad, false)
+            ]
+        );
+        if let Some(saving) = quick_save {
+            world.write_resource::<InputState>().clear_presses();
+            let result = if saving {
+                save::save_file(world, SAVE_PATH)
+            } else {
+                save::load_file(world, SAVE_PATH)
+            };
+            if let Err(error) = result {
+                eprintln!(
+                    "Quick {} failed\n{}",
+                    if saving { "save" } else { "load" },
+                    error
+                );
+            }
+
+            world.write_resource::<Timestep>().skip();
+            return;
+        }
+
+        // Menus open before the simulation sees the press
+        let menu = first_pressed(
+            &world.read_resource::<InputState>(),
+            &[
+                (InputAction::Pause, StateId::Paused),
+                (InputAction::Inventory, StateId::Inventory)
+            ]
+        );
+        if let Some(menu) = menu {
+            world.write_resource::<InputState>().clear_presses();
+            world.write_resource::<Transitions>().push(menu);
+            return;
+        }
+
+        // Update Game States in as many fixed steps as have passed
+        let steps = world.write_resource::<Timestep>().tick();
+        for step in 0..steps {
+            self.update.dispatch(world);
+
+            // Remove deleted entities before the next step
+            world.maintain();
+
+            // Presses are handled by the first step after them, not every step of the frame
+            if step == 0 {
+                world.write_resource::<InputState>().clear_presses();
+            }
+        }
+
+        // The game ends when nothing is left to control
+        if (&world.read_storage::<Controlled>())
+            .join()
+            .next()
+            .is_none()
+        {
+            world
+                .write_resource::<Transitions>()
+                .push(StateId::GameOver);
+        }
+    }
+
+    fn draw(&mut self, world: &mut World) {
+        // Draw between the last two steps
+        let frame_time = {
+            let timestep = world.read_resource::<Timestep>();
+            FrameTime {
+                delta: timestep.frame_delta(),
+                alpha: timestep.alpha()
+            }
+        };
+        world.insert(frame_time);
+        self.render.dispatch(world);
+    }
+}
+
+/// Shown over the level while the game is paused
+#[derive(Default)]
+pub struct PausedState;
+
+impl State for PausedState {
+    fn id(&self) -> StateId {
+        StateId::Paused
+    }
+
+    fn update(&mut self, world: &mut World) {
+        menu_input(
+            world,
+            &[
+                (InputAction::Pause, &[Transition::Pop]),
+                (InputAction::Cancel, &[Transition::Pop]),
+                (
+                    InputAction::Confirm,
+                    &[Transition::Pop, Transition::Replace(StateId::Title)]
+                )
+            ]
+        );
+    }
+
+    fn draw(&mut self, world: &mut World) {
+        draw_menu(
+            world,
+            "Paused\n\nPause to resume\nConfirm to quit to the title"
+        );
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+/// Lists the player's items over the level
+#[derive(Default)]
+pub struct InventoryState;
+
+impl State for InventoryState {
+    fn id(&self) -> StateId {
+        StateId::Inventory
+    }
+
+    fn update(&mut self, world: &mut World) {
+        menu_input(
+            world,
+            &[
+                (InputAction::Inventory, &[Transition::Pop]),
+                (InputAction::Cancel, &[Transition::Pop])
+            ]
+        );
+    }
+
+    fn draw(&mut self, world: &mut World) {
+        draw_menu(world, "Inventory\n\nNothing carried");
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+/// Shown over the level once the player has died
+#[derive(Default)]
+pub struct GameOverState;
+
+impl State for GameOverState {
+    fn id(&self) -> StateId {
+        StateId::GameOver
+    }
+
+    fn update(&mut self, world: &mut World) {
+        menu_input(
+            world,
+            &[(
+                InputAction::Confirm,
+                &[Transition::Pop, Transition::Replace(StateId::Title)]
+            )]
+        );
+    }
+
+    fn draw(&mut self, world: &mut World) {
+        draw_menu(world, "You died\n\nConfirm to return to the title");
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// Records the calls made to it, with paused and inventory as overlays
+    struct Stub {
+        id: StateId,
+        log: Log,
+        /// Transitions requested on the next update
+        requests: Vec<Transition>
+    }
+
+    impl Stub {
+        fn record(&self, event: &str) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} {:?}", event, self.id));
+        }
+    }
+
+    impl State for Stub {
+        fn id(&self) -> StateId {
+            self.id
+        }
+
+        fn on_enter(&mut self, _world: &mut World) -> Result<(), Error> {
+            self.record("enter");
+            Ok(())
+        }
+
+        fn on_exit(&mut self, _world: &mut World) {
+            self.record("exit");
+        }
+
+        fn on_pause(&mut self, _world: &mut World) {
+            self.record("pause");
+        }
+
+        fn on_resume(&mut self, _world: &mut World) {
+            self.record("resume");
+        }
+
+        fn update(&mut self, world: &mut World) {
+            self.record("update");
+            let mut transitions = world.write_resource::<Transitions>();
+            for transition in self.requests.drain(..) {
+                transitions.0.push(transition);
+            }
+        }
+
+        fn draw(&mut self, _world: &mut World) {
+            self.record("draw");
+        }
+
+        fn is_overlay(&self) -> bool {
+            self.id == StateId::Paused || self.id == StateId::Inventory
+        }
+    }
+
+    /// A stack of stubs where the title asks to start playing when updated
+    fn stack() -> (StateStack, World, Log) {
+        let log = Log::default();
+        let factory_log = Arc::clone(&log);
+        let factory: StateFactory = Box::new(move |id| {
+            let requests = match id {
+                StateId::Title => vec![Transition::Replace(StateId::Playing)],
+                _ => vec![]
+            };
+            Box::new(Stub {
+                id,
+                log: Arc::clone(&factory_log),
+                requests
+            })
+        });
+
+        let mut world = World::new();
+        world.insert(Transitions::default());
+        (StateStack::new(factory), world, log)
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    #[test]
+    fn push_pauses_and_pop_resumes() {
+        let (mut states, mut world, log) = stack();
+
+        states.push(StateId::Playing, &mut world).unwrap();
+        states.push(StateId::Paused, &mut world).unwrap();
+        assert_eq!(states.ids(), [StateId::Playing, StateId::Paused]);
+        assert_eq!(
+            take(&log),
+            ["enter Playing", "pause Playing", "enter Paused"]
+        );
+
+        states.pop(&mut world);
+        assert_eq!(states.ids(), [StateId::Playing]);
+        assert_eq!(take(&log), ["exit Paused", "resume Playing"]);
+    }
+
+    #[test]
+    fn replace_exits_the_top_without_resuming_below() {
+        let (mut states, mut world, log) = stack();
+        states.push(StateId::Playing, &mut world).unwrap();
+        states.push(StateId::Paused, &mut world).unwrap();
+        take(&log);
+
+        states.replace(StateId::Inventory, &mut world).unwrap();
+
+        assert_eq!(states.ids(), [StateId::Playing, StateId::Inventory]);
+        assert_eq!(take(&log), ["exit Paused", "enter Inventory"]);
+    }
+
+    #[test]
+    fn clear_exits_from_the_top_down() {
+        let (mut states, mut world, log) = stack();
+        states.push(StateId::Playing, &mut world).unwrap();
+        states.push(StateId::Inventory, &mut world).unwrap();
+        take(&log);
+
+        states.clear(&mut world);
+
+        assert!(states.is_empty());
+        assert_eq!(take(&log), ["exit Inventory", "exit Playing"]);
+    }
+
+    #[test]
+    fn popping_an_empty_stack_does_nothing() {
+        let (mut states, mut world, log) = stack();
+
+        states.pop(&mut world);
+
+        assert!(states.is_empty());
+        assert!(take(&log).is_empty());
+    }
+
+    #[test]
+    fn only_the_top_state_updates() {
+        let (mut states, mut world, log) = stack();
+        states.push(StateId::Playing, &mut world).unwrap();
+        states.push(StateId::Paused, &mut world).unwrap();
+        take(&log);
+
+        states.update(&mut world).unwrap();
+
+        assert_eq!(take(&log), ["update Paused"]);
+    }
+
+    #[test]
+    fn transitions_apply_in_order_after_the_update() {
+        let (mut states, mut world, log) = stack();
+        states.push(StateId::Title, &mut world).unwrap();
+        take(&log);
+
+        states.update(&mut world).unwrap();
+
+        assert_eq!(states.ids(), [StateId::Playing]);
+        assert_eq!(take(&log), ["update Title", "exit Title", "enter Playing"]);
+
+        {
+            let mut transitions = world.write_resource::<Transitions>();
+            transitions.push(StateId::GameOver);
+            transitions.pop();
+            transitions.replace(StateId::Title);
+        }
+        states.apply_transitions(&mut world).unwrap();
+
+        assert_eq!(states.ids(), [StateId::Title]);
+        assert_eq!(
+            take(&log),
+            [
+                "pause Playing",
+                "enter GameOver",
+                "exit GameOver",
+                "resume Playing",
+                "exit Playing",
+                "enter Title"
+            ]
+        );
+        assert!(world.read_resource::<Transitions>().is_empty());
+    }
+
+    #[test]
+    fn quit_empties_the_stack() {
+        let (mut states, mut world, _) = stack();
+        states.push(StateId::Playing, &mut world).unwrap();
+        states.push(StateId::Paused, &mut world).unwrap();
+
+        world.write_resource::<Transitions>().quit();
+        states.apply_transitions(&mut world).unwrap();
+
+        assert!(states.is_empty());
+    }
+
+    #[test]
+    fn overlays_draw_over_the_states_below() {
+        let (mut states, mut world, log) = stack();
+        states.push(StateId::Title, &mut world).unwrap();
+        states.push(StateId::Playing, &mut world).unwrap();
+        states.push(StateId::Paused, &mut world).unwrap();
+        states.push(StateId::Inventory, &mut world).unwrap();
+        take(&log);
+
+        states.draw(&mut world);
+
+        // The title is hidden by the level, which shows through both overlays
+        assert_eq!(
+            take(&log),
+            ["draw Playing", "draw Paused", "draw Inventory"]
+        );
+    }
+
+    #[test]
+    fn opaque_states_hide_everything_below() {
+        let (mut states, mut world, log) = stack();
+        states.push(StateId::Playing, &mut world).unwrap();
+        states.push(StateId::Paused, &mut world).unwrap();
+        states.push(StateId::Title, &mut world).unwrap();
+        take(&log);
+
+        states.draw(&mut world);
+
+        assert_eq!(take(&log), ["draw Title"]);
+    }
+
+    #[test]
+    fn a_lone_overlay_still_draws() {
+        let (mut states, mut world, log) = stack();
+        states.push(StateId::Paused, &mut world).unwrap();
+        take(&log);
+
+        states.draw(&mut world);
+
+        assert_eq!(take(&log), ["draw Paused"]);
+    }
+}
@@ -1,12 +1,323 @@
 extern crate image;
-use image::RgbaImage;
+use image::{Rgba, RgbaImage};
 
 extern crate freetype;
-use freetype::Bitmap;
+use freetype::face::{KerningMode, LoadFlag};
+
+use crate::atlas;
+use crate::gl_util::{self, Buffer, Filter, Texture, Usage, VertexArray};
+use crate::shader::{Program, UniformError};
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Characters rasterized into a glyph atlas by default
+pub const PRINTABLE_ASCII: &str =
+    " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Width of the glyph atlas image in pixels, the height grows to fit
+const ATLAS_WIDTH: u32 = 512;
+
+/// Empty pixels left around each glyph so neighbours do not bleed in when filtered
+const PADDING: u32 = 1;
+
+/// Errors that can occur while rasterizing a font
+#[derive(Debug)]
+pub enum TextError {
+    /// The font file could not be opened or configured
+    Font { path: String, message: String },
+    /// A character could not be rendered
+    Glyph { character: char, message: String }
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextError::Font { path, message } => {
+                write!(f, "Failed to load font '{}': {}", path, message)
+            },
+            TextError::Glyph { character, message } => {
+                write!(f, "Failed to render '{}': {}", character, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+/// How the lines of a block of text line up with each other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right
+}
+
+/// Size and placement of a character, in pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glyph {
+    pub width: f32,
+    pub height: f32,
+    /// Offset from the pen position to the left edge of the bitmap
+    pub bearing_x: f32,
+    /// Height of the top edge of the bitmap above the baseline
+    pub bearing_y: f32,
+    /// Distance the pen moves after the character
+    pub advance: f32,
+    /// Texture coordinates of the glyph in the atlas, for the two triangles of a quad
+    pub uv: Vec<f32>
+}
+
+/// The metrics of a font at one size, everything needed to lay out text
+#[derive(Debug, Clone, Default)]
+pub struct Font {
+    pub glyphs: HashMap<char, Glyph>,
+    /// Extra advance between pairs of characters, usually negative
+    pub kerning: HashMap<(char, char), f32>,
+    /// Distance between the baselines of two lines
+    pub line_height: f32,
+    /// Height of the tallest characters above the baseline
+    pub ascent: f32
+}
+
+impl Font {
+    /// Returns the glyph for a character, falling back to `?` for missing characters
+    pub fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&character)
+            .or_else(|| self.glyphs.get(&'?'))
+    }
+
+    /// Returns the kerning between two characters
+    pub fn kerning(&self, left: char, right: char) -> f32 {
+        self.kerning.get(&(left, right)).copied().unwrap_or(0.0)
+    }
+
+    /// Returns the width of a single line of text
+    /// # Arguments
+    /// * `text` - The line, without line breaks
+    pub fn measure(&self, text: &str) -> f32 {
+        let mut width = 0.0;
+        let mut previous = None;
+
+        for character in text.chars() {
+            if let Some(previous) = previous {
+                width += self.kerning(previous, character);
+            }
+            if let Some(glyph) = self.glyph(character) {
+                width += glyph.advance;
+            }
+            previous = Some(character);
+        }
+
+        width
+    }
+}
+
+/// Settings for laying out a block of text
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutOptions {
+    pub align: Align,
+    /// Lines longer than this are wrapped between words, in pixels
+    pub max_width: Option<f32>,
+    /// Multiplier of the font's line height
+    pub line_spacing: f32
+}
+
+impl Default for LayoutOptions {
+    fn default() -> LayoutOptions {
+        LayoutOptions {
+            align: Align::Left,
+            max_width: None,
+            line_spacing: 1.0
+        }
+    }
+}
+
+/// A character placed in a block of text
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedGlyph {
+    pub character: char,
+    /// Left edge in pixels from the left of the block
+    pub x: f32,
+    /// Top edge in pixels down from the top of the block
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+/// The glyphs of a block of text and the size of the block
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Layout {
+    pub glyphs: Vec<PlacedGlyph>,
+    pub width: f32,
+    pub height: f32,
+    pub lines: usize
+}
+
+/// Positions the characters of a block of text
+/// Lines are broken at `\n` and, when a maximum width is set, between words.
+/// Characters with no visible pixels, like spaces, take up room but are not placed.
+/// # Arguments
+/// * `font` - Metrics of the font to use
+/// * `text` - The text to lay out
+/// * `options` - Alignment, wrapping and line spacing
+pub fn layout(font: &Font, text: &str, options: &LayoutOptions) -> Layout {
+    let lines: Vec<String> = text
+        .split('\n')
+        .flat_map(|paragraph| wrap(font, paragraph, options.max_width))
+        .collect();
+
+    let widths: Vec<f32> = lines.iter().map(|line| font.measure(line)).collect();
+    let width = match options.max_width {
+        Some(max_width) => max_width,
+        None => widths.iter().cloned().fold(0.0, f32::max)
+    };
+    let line_height = font.line_height * options.line_spacing;
+
+    let mut glyphs = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let mut pen = match options.align {
+            Align::Left => 0.0,
+            Align::Center => (width - widths[index]) / 2.0,
+            Align::Right => width - widths[index]
+        };
+        let baseline = font.ascent + index as f32 * line_height;
+
+        let mut previous = None;
+        for character in line.chars() {
+            if let Some(previous) = previous {
+                pen += font.kerning(previous, character);
+            }
+            previous = Some(character);
+
+            let glyph = match font.glyph(character) {
+                Some(glyph) => glyph,
+                None => continue
+            };
+
+            if glyph.width > 0.0 && glyph.height > 0.0 {
+                glyphs.push(PlacedGlyph {
+                    character,
+                    x: pen + glyph.bearing_x,
+                    y: baseline - glyph.bearing_y,
+                    width: glyph.width,
+                    height: glyph.height
+                });
+            }
+
+            pen += glyph.advance;
+        }
+    }
+
+    Layout {
+        glyphs,
+        width,
+        height: lines.len() as f32 * line_height,
+        lines: lines.len()
+    }
+}
+
+/// Splits a paragraph into lines no wider than a maximum, breaking at spaces
+/// A single word wider than the maximum is left on a line of its own.
+fn wrap(font: &Font, paragraph: &str, max_width: Option<f32>) -> Vec<String> {
+    let max_width = match max_width {
+        Some(max_width) => max_width,
+        None => return vec![String::from(paragraph)]
+    };
+
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in paragraph.split(' ') {
+        let candidate = if line.is_empty() {
+            String::from(word)
+        } else {
+            format!("{} {}", line, word)
+        };
+
+        if font.measure(&candidate) > max_width && !line.is_empty() {
+            lines.push(line);
+            line = String::from(word);
+        } else {
+            line = candidate;
+        }
+    }
+    lines.push(line);
+
+    lines
+}
+
+/// Builds the vertices and texture coordinates for the quads of a layout
+/// The top left of the block is placed at the origin with y pointing up, matching world
+/// coordinates.
+/// # Arguments
+/// * `font` - The font the layout was made with
+/// * `layout` - The placed glyphs
+/// * `scale` - World units per pixel
+pub fn mesh(font: &Font, layout: &Layout, scale: f32) -> (Vec<f32>, Vec<f32>) {
+    let mut vertices = Vec::with_capacity(layout.glyphs.len() * 18);
+    let mut texture_vertices = Vec::with_capacity(layout.glyphs.len() * 12);
+
+    for placed in &layout.glyphs {
+        let glyph = match font.glyph(placed.character) {
+            Some(glyph) => glyph,
+            None => continue
+        };
+
+        let left = placed.x * scale;
+        let right = (placed.x + placed.width) * scale;
+        let top = -placed.y * scale;
+        let bottom = -(placed.y + placed.height) * scale;
+
+        // Same winding as gl_util::create_rectangle
+        vertices.extend_from_slice(&[
+            left, bottom, 0.0, right, top, 0.0, left, top, 0.0, left, bottom, 0.0, right, bottom,
+            0.0, right, top, 0.0
+        ]);
+        texture_vertices.extend_from_slice(&glyph.uv);
+    }
+
+    (vertices, texture_vertices)
+}
+
+/// A font rasterized into a single image
+pub struct GlyphAtlas {
+    pub font: Font,
+    /// White pixels with the glyph coverage in the alpha channel
+    pub image: RgbaImage
+}
+
+impl GlyphAtlas {
+    /// Uploads the atlas image to the GPU
+    pub fn create_texture(&self) -> Texture {
+        let texture = image::imageops::flip_vertical(&self.image);
+
+        Texture::from_rgba(
+            texture.width(),
+            texture.height(),
+            &texture,
+            Filter::Linear,
+            Filter::Linear
+        )
+    }
+}
+
+/// A rendered character before it is packed into an atlas
+struct Bitmap {
+    character: char,
+    width: u32,
+    height: u32,
+    /// Coverage of each pixel, row by row
+    coverage: Vec<u8>,
+    bearing_x: f32,
+    bearing_y: f32,
+    advance: f32
+}
 
 /// An object that can be used to generate bitmaps of text
 pub struct TextGenerator {
-    face: freetype::Face,
+    path: String,
+    face: freetype::Face
 }
 
 impl TextGenerator {
@@ -14,35 +325,429 @@ impl TextGenerator {
     /// # Arguments
     /// * `path` - A string that holds the file path of the font face to use
     /// * `index` - Face index to load
-    pub fn new_from_font(path: &str, index: isize) -> TextGenerator {
-        // Create a freetype library
-        let ft_library = freetype::Library::init().unwrap();
+    /// * `pixel_size` - Height of the characters in pixels
+    pub fn new_from_font(
+        path: &str,
+        index: isize,
+        pixel_size: u32
+    ) -> Result<TextGenerator, TextError> {
+        let font_error = |message: freetype::Error| TextError::Font {
+            path: String::from(path),
+            message: message.to_string()
+        };
+
+        // Create a freetype library, the face keeps it alive
+        let ft_library = freetype::Library::init().map_err(font_error)?;
 
         // Create a font face from file
-        let face = ft_library.new_face(path, index).unwrap();
+        let face = ft_library.new_face(path, index).map_err(font_error)?;
 
         // Configure the character size
-        face.set_char_size(5000, 0, 50, 0);
+        face.set_pixel_sizes(0, pixel_size).map_err(font_error)?;
 
-        TextGenerator { face }
+        Ok(TextGenerator {
+            path: String::from(path),
+            face
+        })
     }
 
-    /// Generates an image of the text using this objects font
+    /// Rasterizes characters into a glyph atlas
     /// # Arguments
-    /// * `text` - The text to render into the image
-    pub fn generate(text: &str) -> RgbaImage {
-        RgbaImage {}
+    /// * `characters` - The characters to include, e.g. `PRINTABLE_ASCII`
+    pub fn generate(&self, characters: &str) -> Result<GlyphAtlas, TextError> {
+        let mut characters: Vec<char> = characters.chars().collect();
+        characters.sort_unstable();
+        characters.dedup();
+
+        let bitmaps = characters
+            .iter()
+            .map(|character| self.get_glyph(*character))
+            .collect::<Result<Vec<Bitmap>, TextError>>()?;
+
+        // Pack the glyphs into rows, tallest first so rows waste little space
+        let mut order: Vec<usize> = (0..bitmaps.len()).collect();
+        order.sort_by_key(|index| std::cmp::Reverse(bitmaps[*index].height));
+
+        let mut offsets = vec![(0, 0); bitmaps.len()];
+        let (mut x, mut y, mut row_height) = (PADDING, PADDING, 0);
+        for index in order {
+            let bitmap = &bitmaps[index];
+            if x + bitmap.width + PADDING > ATLAS_WIDTH {
+                x = PADDING;
+                y += row_height + PADDING;
+                row_height = 0;
+            }
+
+            offsets[index] = (x, y);
+            x += bitmap.width + PADDING;
+            row_height = row_height.max(bitmap.height);
+        }
+        let height = (y + row_height + PADDING).next_power_of_two();
+
+        let mut image = RgbaImage::from_pixel(ATLAS_WIDTH, height, Rgba([255, 255, 255, 0]));
+        let mut font = Font::default();
+
+        for (bitmap, (x, y)) in bitmaps.iter().zip(offsets) {
+            for row in 0..bitmap.height {
+                for column in 0..bitmap.width {
+                    let coverage = bitmap.coverage[(row * bitmap.width + column) as usize];
+                    image.put_pixel(x + column, y + row, Rgba([255, 255, 255, coverage]));
+                }
+            }
+
+            font.glyphs.insert(
+                bitmap.character,
+                Glyph {
+                    width: bitmap.width as f32,
+                    height: bitmap.height as f32,
+                    bearing_x: bitmap.bearing_x,
+                    bearing_y: bitmap.bearing_y,
+                    advance: bitmap.advance,
+                    uv: atlas::quad_coordinates(
+                        x,
+                        y,
+                        bitmap.width,
+                        bitmap.height,
+                        ATLAS_WIDTH,
+                        height
+                    )
+                }
+            );
+        }
+
+        // Metrics are in 26.6 fixed point
+        if let Some(metrics) = self.face.size_metrics() {
+            font.line_height = metrics.height as f32 / 64.0;
+            font.ascent = metrics.ascender as f32 / 64.0;
+        }
+
+        if self.face.has_kerning() {
+            for left in &characters {
+                for right in &characters {
+                    let kerning = self.face.get_kerning(
+                        self.face.get_char_index(*left as usize),
+                        self.face.get_char_index(*right as usize),
+                        KerningMode::KerningDefault
+                    );
+
+                    match kerning {
+                        Ok(vector) if vector.x != 0 => {
+                            font.kerning.insert((*left, *right), vector.x as f32 / 64.0);
+                        },
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Ok(GlyphAtlas { font, image })
     }
 
     /// Creates a bitmap of a character from the font face
     /// # Arguments
     /// * `character` - The character to get the bitmap for
-    fn get_glyph(&self, character: char) -> Bitmap {
+    fn get_glyph(&self, character: char) -> Result<Bitmap, TextError> {
         // Load the character
         self.face
-            .load_char(character as usize, freetype::face::LoadFlag::RENDER);
+            .load_char(character as usize, LoadFlag::RENDER)
+            .map_err(|message| TextError::Glyph {
+                character,
+                message: format!("{} in '{}'", message, self.path)
+            })?;
+
+        let glyph = self.face.glyph();
+        let bitmap = glyph.bitmap();
+        let width = bitmap.width() as u32;
+        let height = bitmap.rows() as u32;
+
+        Ok(Bitmap {
+            character,
+            width,
+            height,
+            coverage: coverage_rows(
+                bitmap.buffer(),
+                width as usize,
+                height as usize,
+                bitmap.pitch()
+            ),
+            bearing_x: glyph.bitmap_left() as f32,
+            bearing_y: glyph.bitmap_top() as f32,
+            advance: glyph.advance().x as f32 / 64.0
+        })
+    }
+}
+
+/// Copies the coverage of a FreeType bitmap into rows from the top of the glyph down
+/// Rows may be padded, the padding is left out. A negative pitch means the rows are stored
+/// from the bottom of the glyph up.
+/// # Arguments
+/// * `buffer` - The bitmap's pixels as stored by FreeType
+/// * `width`, `height` - Size of the bitmap in pixels
+/// * `pitch` - Bytes from the start of one stored row to the next
+fn coverage_rows(buffer: &[u8], width: usize, height: usize, pitch: i32) -> Vec<u8> {
+    let stride = pitch.unsigned_abs() as usize;
+    let mut coverage = Vec::with_capacity(width * height);
+
+    for row in 0..height {
+        let stored = if pitch < 0 { height - 1 - row } else { row };
+        let start = stored * stride;
+        coverage.extend_from_slice(&buffer[start..start + width]);
+    }
+
+    coverage
+}
+
+/// Draws a block of text in a single draw call
+pub struct TextRenderer {
+    pub attribute_array: VertexArray,
+    pub vertex_buffer: Buffer,
+    pub texture_coord_buffer: Buffer,
+    pub texture: Texture,
+    pub vertex_count: u32,
+    /// World units per pixel of the font
+    scale: f32,
+    font: Font,
+    text: String,
+    options: LayoutOptions,
+    layout: Layout,
+    /// Set when the buffers need rebuilding
+    stale: bool
+}
+
+impl TextRenderer {
+    /// Uploads a glyph atlas and creates the buffers for its text
+    /// # Arguments
+    /// * `atlas` - The rasterized font
+    /// * `scale` - World units per pixel of the font
+    pub fn new(atlas: &GlyphAtlas, scale: f32) -> TextRenderer {
+        let texture = atlas.create_texture();
+
+        let attribute_array = VertexArray::new();
+        let vertex_buffer = Buffer::new();
+        let texture_coord_buffer = Buffer::new();
+
+        attribute_array.set_attribute(0, &vertex_buffer, 3);
+        attribute_array.set_attribute(1, &texture_coord_buffer, 2);
+
+        TextRenderer {
+            attribute_array,
+            vertex_buffer,
+            texture_coord_buffer,
+            texture,
+            vertex_count: 0,
+            scale,
+            font: atlas.font.clone(),
+            text: String::new(),
+            options: LayoutOptions::default(),
+            layout: Layout::default(),
+            stale: true
+        }
+    }
+
+    /// Lays out new text, only rebuilding the buffers if it changed
+    /// # Arguments
+    /// * `text` - The text to show
+    /// * `options` - Alignment, wrapping and line spacing
+    pub fn set_text(&mut self, text: &str, options: &LayoutOptions) {
+        if self.text == text && &self.options == options && !self.stale {
+            return;
+        }
+
+        self.layout = layout(&self.font, text, options);
+        let (vertices, texture_vertices) = mesh(&self.font, &self.layout, self.scale);
+        self.vertex_buffer.set_data(&vertices, Usage::Dynamic);
+        self.texture_coord_buffer
+            .set_data(&texture_vertices, Usage::Dynamic);
+
+        self.vertex_count = vertices.len() as u32;
+        self.text = String::from(text);
+        self.options = options.clone();
+        self.stale = false;
+    }
+
+    /// Changes the size of the text, e.g. when the window is resized
+    /// # Arguments
+    /// * `scale` - World units per pixel of the font
+    pub fn set_scale(&mut self, scale: f32) {
+        if self.scale != scale {
+            self.scale = scale;
+            self.stale = true;
+        }
+    }
+
+    /// Size of the current text block in world units
+    pub fn size(&self) -> (f32, f32) {
+        (
+            self.layout.width * self.scale,
+            self.layout.height * self.scale
+        )
+    }
+
+    /// Issues the draw call for the text
+    /// # Arguments
+    /// * `program` - The shader program to draw with
+    /// * `x`, `y` - World position of the top left corner of the text block
+    pub fn draw(&self, program: &Program, x: f32, y: f32) -> Result<(), UniformError> {
+        program.set_used();
+        self.attribute_array.bind();
+        self.texture.bind();
+
+        // Vertices are already in world units, a size of 2 leaves them unscaled
+        program.set_vec3("position", [x, y, 0.0])?;
+        program.set_vec2("size", [2.0, 2.0])?;
+        program.set_sampler("texture_sampler", 0)?;
+
+        gl_util::draw_triangles(self.vertex_count / 3);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(width: f32, advance: f32) -> Glyph {
+        Glyph {
+            width,
+            height: if width > 0.0 { 8.0 } else { 0.0 },
+            bearing_x: 1.0,
+            bearing_y: 8.0,
+            advance,
+            uv: vec![0.0; 12]
+        }
+    }
+
+    /// A font where A and V are 10 pixels wide and pulled 2 pixels together when paired
+    fn font() -> Font {
+        let mut font = Font {
+            line_height: 12.0,
+            ascent: 9.0,
+            ..Font::default()
+        };
+        font.glyphs.insert('A', glyph(8.0, 10.0));
+        font.glyphs.insert('V', glyph(8.0, 10.0));
+        font.glyphs.insert('?', glyph(6.0, 7.0));
+        font.glyphs.insert(' ', glyph(0.0, 4.0));
+        font.kerning.insert(('A', 'V'), -2.0);
+        font
+    }
+
+    fn positions(layout: &Layout) -> Vec<(char, f32, f32)> {
+        layout
+            .glyphs
+            .iter()
+            .map(|glyph| (glyph.character, glyph.x, glyph.y))
+            .collect()
+    }
+
+    fn aligned(align: Align) -> LayoutOptions {
+        LayoutOptions {
+            align,
+            ..LayoutOptions::default()
+        }
+    }
+
+    #[test]
+    fn kerning_moves_following_glyphs() {
+        let font = font();
+        let layout = layout(&font, "AVA", &LayoutOptions::default());
+
+        assert_eq!(
+            positions(&layout),
+            [('A', 1.0, 1.0), ('V', 9.0, 1.0), ('A', 19.0, 1.0)]
+        );
+        assert_eq!(font.measure("AVA"), 28.0);
+        assert_eq!(layout.width, 28.0);
+    }
+
+    #[test]
+    fn spaces_take_room_without_a_glyph() {
+        let layout = layout(&font(), "A A", &LayoutOptions::default());
+        assert_eq!(positions(&layout), [('A', 1.0, 1.0), ('A', 15.0, 1.0)]);
+    }
+
+    #[test]
+    fn missing_characters_show_a_question_mark() {
+        let layout = layout(&font(), "Z", &LayoutOptions::default());
+        assert_eq!(positions(&layout), [('Z', 1.0, 1.0)]);
+        assert_eq!(layout.glyphs[0].width, 6.0);
+    }
+
+    #[test]
+    fn line_breaks_start_a_new_baseline() {
+        let layout = layout(&font(), "AV\nA", &LayoutOptions::default());
+
+        assert_eq!(
+            positions(&layout),
+            [('A', 1.0, 1.0), ('V', 9.0, 1.0), ('A', 1.0, 13.0)]
+        );
+        assert_eq!(layout.lines, 2);
+        assert_eq!(layout.height, 24.0);
+
+        let spaced = LayoutOptions {
+            line_spacing: 1.5,
+            ..LayoutOptions::default()
+        };
+        assert_eq!(super::layout(&font(), "A\nA", &spaced).glyphs[1].y, 19.0);
+    }
+
+    #[test]
+    fn lines_are_aligned_within_the_widest() {
+        // The first line is 18 pixels wide and the second 10
+        let left = layout(&font(), "AV\nA", &aligned(Align::Left));
+        let center = layout(&font(), "AV\nA", &aligned(Align::Center));
+        let right = layout(&font(), "AV\nA", &aligned(Align::Right));
+
+        assert_eq!(left.glyphs[2].x, 1.0);
+        assert_eq!(center.glyphs[2].x, 5.0);
+        assert_eq!(right.glyphs[2].x, 9.0);
+
+        // The widest line is the same however it is aligned
+        for layout in [&left, &center, &right] {
+            assert_eq!(layout.width, 18.0);
+            assert_eq!(layout.glyphs[0].x, 1.0);
+        }
+    }
+
+    #[test]
+    fn long_lines_wrap_between_words() {
+        let options = LayoutOptions {
+            max_width: Some(25.0),
+            ..LayoutOptions::default()
+        };
+        let layout = layout(&font(), "AA AA AVAVAV", &options);
+
+        assert_eq!(layout.lines, 3);
+        assert_eq!(layout.width, 25.0);
+        let rows: Vec<f32> = layout.glyphs.iter().map(|glyph| glyph.y).collect();
+        assert_eq!(
+            rows,
+            [1.0, 1.0, 13.0, 13.0, 25.0, 25.0, 25.0, 25.0, 25.0, 25.0]
+        );
+    }
+
+    #[test]
+    fn mesh_puts_the_block_below_the_origin() {
+        let font = font();
+        let layout = layout(&font, "A", &LayoutOptions::default());
+        let (vertices, texture_vertices) = mesh(&font, &layout, 0.5);
+
+        assert_eq!(vertices.len(), 18);
+        assert_eq!(texture_vertices.len(), 12);
+        // Bottom left then top right corner
+        assert_eq!(&vertices[..6], [0.5, -4.5, 0.0, 4.5, -0.5, 0.0]);
+    }
+
+    #[test]
+    fn copies_rows_without_padding() {
+        let buffer = [1, 2, 0, 3, 4, 0];
+        assert_eq!(coverage_rows(&buffer, 2, 2, 3), [1, 2, 3, 4]);
+    }
 
-        // Return the glyph bitmap
-        self.face.glyph().bitmap()
+    #[test]
+    fn negative_pitch_rows_are_flipped() {
+        // Stored from the bottom row up
+        let buffer = [3, 4, 0, 1, 2, 0];
+        assert_eq!(coverage_rows(&buffer, 2, 2, -3), [1, 2, 3, 4]);
     }
 }
@@ -0,0 +1,327 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Length of a simulation step, 60 steps a second
+pub const DEFAULT_STEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Most steps simulated in one frame before the remaining time is dropped
+pub const DEFAULT_MAX_STEPS: u32 = 5;
+
+/// Fastest the simulation can be sped up to
+pub const MAX_TIME_SCALE: f32 = 100.0;
+
+/// A source of the current time
+pub trait Clock: Send + Sync {
+    /// Time since some fixed point, which must never go backwards
+    fn now(&self) -> Duration;
+}
+
+/// Reads the time from the system's monotonic clock
+pub struct SystemClock {
+    start: Instant
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock {
+            start: Instant::now()
+        }
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock::default()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when told to, shared so it can be advanced after being boxed
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    time: Arc<Mutex<Duration>>
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    /// Moves the clock forward
+    pub fn advance(&self, duration: Duration) {
+        *self.time.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.time.lock().unwrap()
+    }
+}
+
+/// Splits the time between frames into simulation steps of a fixed length
+/// Time left over after the last whole step is carried into the next frame, and how far it is
+/// through the next step is used to draw between the previous and current step.
+pub struct Timestep {
+    clock: Box<dyn Clock>,
+    step: Duration,
+    max_steps: u32,
+    /// Speed of the simulation, 1 for real time and 0 when paused
+    time_scale: f32,
+    /// Time of the last tick
+    last: Duration,
+    /// Scaled time not yet simulated
+    accumulator: Duration,
+    /// Scaled time between the last two ticks
+    frame_delta: Duration
+}
+
+impl Timestep {
+    /// Creates a timestep starting at the clock's current time
+    /// # Arguments
+    /// * `clock` - Where the time is read from
+    /// * `step` - Length of each simulation step
+    /// * `max_steps` - Most steps run in one frame, so a slow frame does not cause slower ones
+    pub fn new(clock: Box<dyn Clock>, step: Duration, max_steps: u32) -> Timestep {
+        let last = clock.now();
+        Timestep {
+            clock,
+            step,
+            max_steps,
+            time_scale: 1.0,
+            last,
+            accumulator: Duration::new(0, 0),
+            frame_delta: Duration::new(0, 0)
+        }
+    }
+
+    /// Reads the clock and returns the number of steps to simulate this frame
+    pub fn tick(&mut self) -> u32 {
+        let now = self.clock.now();
+        let elapsed = now.checked_sub(self.last).unwrap_or_default();
+        self.last = now;
+
+        self.frame_delta = elapsed.mul_f32(self.time_scale);
+        self.accumulator += self.frame_delta;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < self.max_steps {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        // Drop whole steps that could not be caught up so a hitch does not snowball
+        if self.accumulator >= self.step {
+            let remainder = self.accumulator.as_nanos() % self.step.as_nanos();
+            self.accumulator = Duration::from_nanos(remainder as u64);
+        }
+
+        steps
+    }
+
+    /// Forgets the time since the last tick, so time spent suspended is not simulated
+    pub fn skip(&mut self) {
+        self.last = self.clock.now();
+        self.frame_delta = Duration::new(0, 0);
+    }
+
+    /// Length of each simulation step
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Scaled time between the last two ticks
+    pub fn frame_delta(&self) -> Duration {
+        self.frame_delta
+    }
+
+    /// How far the current time is between the last step and the next, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Changes the speed of the simulation, taking effect from the next tick
+    /// # Arguments
+    /// * `time_scale` - 1 for real time, less for slow motion and 0 to pause, clamped to
+    ///   `MAX_TIME_SCALE` and treated as 0 if it is not a number
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = if time_scale.is_nan() {
+            0.0
+        } else {
+            time_scale.clamp(0.0, MAX_TIME_SCALE)
+        };
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.time_scale == 0.0
+    }
+}
+
+/// Blends a value between the previous and current simulation step
+/// # Arguments
+/// * `previous` - The value after the previous step
+/// * `current` - The value after the current step
+/// * `alpha` - How far to blend towards the current value, from 0 to 1
+pub fn interpolate(previous: f32, current: f32, alpha: f32) -> f32 {
+    previous + (current - previous) * alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 64th of a second, so fractions of a step are exact as f32 seconds
+    const STEP: Duration = Duration::from_nanos(15_625_000);
+
+    fn steps(count: u32) -> Duration {
+        STEP * count
+    }
+
+    fn half_step() -> Duration {
+        STEP / 2
+    }
+
+    fn timestep() -> (Timestep, ManualClock) {
+        let clock = ManualClock::new();
+        (Timestep::new(Box::new(clock.clone()), STEP, 5), clock)
+    }
+
+    #[test]
+    fn runs_every_whole_step() {
+        let (mut timestep, clock) = timestep();
+
+        clock.advance(steps(3));
+
+        assert_eq!(timestep.tick(), 3);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.frame_delta(), steps(3));
+    }
+
+    #[test]
+    fn carries_the_remainder_into_alpha() {
+        let (mut timestep, clock) = timestep();
+
+        clock.advance(steps(2) + half_step());
+        assert_eq!(timestep.tick(), 2);
+        assert_eq!(timestep.alpha(), 0.5);
+
+        clock.advance(half_step());
+        assert_eq!(timestep.tick(), 1);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn no_time_means_no_steps() {
+        let (mut timestep, _) = timestep();
+
+        assert_eq!(timestep.tick(), 0);
+        assert_eq!(timestep.frame_delta(), Duration::new(0, 0));
+    }
+
+    #[test]
+    fn drops_whole_steps_after_a_hitch() {
+        let (mut timestep, clock) = timestep();
+
+        clock.advance(steps(64) + half_step());
+        assert_eq!(timestep.tick(), 5);
+        // The fraction of a step is kept, the 59 steps that could not run are not
+        assert_eq!(timestep.alpha(), 0.5);
+
+        clock.advance(half_step());
+        assert_eq!(timestep.tick(), 1);
+    }
+
+    #[test]
+    fn pausing_stops_the_steps() {
+        let (mut timestep, clock) = timestep();
+        clock.advance(half_step());
+        timestep.tick();
+
+        timestep.set_time_scale(0.0);
+        clock.advance(steps(10));
+
+        assert!(timestep.is_paused());
+        assert_eq!(timestep.tick(), 0);
+        assert_eq!(timestep.frame_delta(), Duration::new(0, 0));
+        assert_eq!(timestep.alpha(), 0.5);
+
+        // Time spent paused is not made up for
+        timestep.set_time_scale(1.0);
+        clock.advance(half_step());
+        assert_eq!(timestep.tick(), 1);
+    }
+
+    #[test]
+    fn slow_motion_scales_time() {
+        let (mut timestep, clock) = timestep();
+        timestep.set_time_scale(0.5);
+
+        clock.advance(steps(4) + half_step());
+
+        assert_eq!(timestep.tick(), 2);
+        let expected = steps(2) + STEP / 4;
+        assert!((timestep.frame_delta().as_secs_f32() - expected.as_secs_f32()).abs() < 1e-6);
+        assert!((timestep.alpha() - 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn negative_time_scales_pause() {
+        let (mut timestep, _) = timestep();
+
+        timestep.set_time_scale(-1.0);
+
+        assert_eq!(timestep.time_scale(), 0.0);
+        assert!(timestep.is_paused());
+    }
+
+    #[test]
+    fn time_scales_are_kept_in_range() {
+        let (mut timestep, clock) = timestep();
+
+        for (scale, expected) in [
+            (f32::INFINITY, MAX_TIME_SCALE),
+            (1e30, MAX_TIME_SCALE),
+            (f32::NAN, 0.0),
+            (f32::NEG_INFINITY, 0.0)
+        ] {
+            timestep.set_time_scale(scale);
+            assert_eq!(timestep.time_scale(), expected, "{}", scale);
+
+            // Ticking at the clamped scale does not panic
+            clock.advance(steps(1));
+            timestep.tick();
+        }
+    }
+
+    #[test]
+    fn skip_forgets_time_since_the_last_tick() {
+        let (mut timestep, clock) = timestep();
+        clock.advance(steps(1) + half_step());
+        timestep.tick();
+
+        clock.advance(steps(100));
+        timestep.skip();
+
+        assert_eq!(timestep.frame_delta(), Duration::new(0, 0));
+        assert_eq!(timestep.tick(), 0);
+        // Time already accumulated is still simulated
+        clock.advance(half_step());
+        assert_eq!(timestep.tick(), 1);
+    }
+
+    #[test]
+    fn interpolates_between_steps() {
+        assert_eq!(interpolate(1.0, 3.0, 0.0), 1.0);
+        assert_eq!(interpolate(1.0, 3.0, 0.5), 2.0);
+        assert_eq!(interpolate(1.0, 3.0, 1.0), 3.0);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::input::{InputAction, InputState};
+use crate::map::TileMap;
+
+/// Energy an actor spends to take an action
+pub const ACTION_COST: i32 = 100;
+
+/// Fastest an actor can be, so a tick's worth of energy always fits in an `i32`
+pub const MAX_SPEED: u32 = 10_000;
+
+/// Selects how the world advances
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GameMode {
+    /// The world advances every frame
+    #[default]
+    RealTime,
+    /// The world advances only when the player takes an action
+    TurnBased
+}
+
+/// Something an actor does with its turn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Step one tile in a direction, x to the right and y up
+    Move(i32, i32),
+    /// Do nothing for a turn
+    Wait
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry<K> {
+    id: K,
+    speed: u32,
+    energy: i32
+}
+
+/// Decides which actor takes the next turn
+/// Every actor gains energy equal to its speed each tick and acts once it has `ACTION_COST`,
+/// so an actor with twice the speed acts twice as often. Ties go to the actor added first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheduler<K> {
+    entries: Vec<Entry<K>>
+}
+
+impl<K: Copy + PartialEq> Default for Scheduler<K> {
+    fn default() -> Scheduler<K> {
+        Scheduler { entries: vec![] }
+    }
+}
+
+impl<K: Copy + PartialEq> Scheduler<K> {
+    pub fn new() -> Scheduler<K> {
+        Scheduler::default()
+    }
+
+    /// Adds an actor with no energy, or updates its speed if it is already scheduled
+    /// # Arguments
+    /// * `id` - The actor
+    /// * `speed` - Energy gained per tick
+    pub fn add(&mut self, id: K, speed: u32) {
+        match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => entry.speed = speed,
+            None => self.entries.push(Entry {
+                id,
+                speed,
+                energy: 0
+            })
+        }
+    }
+
+    /// Removes an actor from the schedule
+    pub fn remove(&mut self, id: K) {
+        self.entries.retain(|entry| entry.id != id);
+    }
+
+    /// Removes every actor rejected by a filter
+    pub fn retain<F: FnMut(K) -> bool>(&mut self, mut filter: F) {
+        self.entries.retain(|entry| filter(entry.id));
+    }
+
+    /// Returns true if the actor is scheduled
+    pub fn contains(&self, id: K) -> bool {
+        self.entries.iter().any(|entry| entry.id == id)
+    }
+
+    /// Returns the energy of an actor
+    pub fn energy(&self, id: K) -> Option<i32> {
+        self.entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| entry.energy)
+    }
+
+    /// Returns the actor whose turn it is, advancing time until one has enough energy
+    /// Returns `None` if no actor can ever act.
+    pub fn next_actor(&mut self) -> Option<K> {
+        if let Some(id) = self.ready() {
+            return Some(id);
+        }
+
+        // Skip straight to the first tick where someone can act
+        let ticks = self
+            .entries
+            .iter()
+            .filter(|entry| entry.speed > 0)
+            .map(|entry| {
+                let needed = (ACTION_COST - entry.energy) as u32;
+                needed.div_ceil(entry.speed)
+            })
+            .min()?;
+
+        for entry in self.entries.iter_mut() {
+            entry.energy += (entry.speed * ticks) as i32;
+        }
+
+        self.ready()
+    }
+
+    /// Takes energy from an actor after it acts
+    /// # Arguments
+    /// * `id` - The actor
+    /// * `cost` - Energy used by the action
+    pub fn spend(&mut self, id: K, cost: i32) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) {
+            entry.energy -= cost;
+        }
+    }
+
+    /// The ready actor with the most energy, the earliest added on a tie
+    fn ready(&self) -> Option<K> {
+        let mut best: Option<&Entry<K>> = None;
+        for entry in &self.entries {
+            let better = match best {
+                Some(best) => entry.energy > best.energy,
+                None => true
+            };
+
+            if entry.energy >= ACTION_COST && better {
+                best = Some(entry);
+            }
+        }
+
+        best.map(|entry| entry.id)
+    }
+}
+
+/// Returns the cell a step leads to, or `None` if it is blocked
+/// # Arguments
+/// * `map` - The map whose solid tiles block movement
+/// * `from` - The cell the step starts from
+/// * `direction` - The step, x to the right and y up
+/// * `occupied` - Cells holding something that blocks movement
+pub fn step(
+    map: &TileMap,
+    from: (i32, i32),
+    direction: (i32, i32),
+    occupied: &[(i32, i32)]
+) -> Option<(i32, i32)> {
+    let to = target(from, direction);
+
+    if map.is_solid(to.0, to.1) || occupied.contains(&to) {
+        None
+    } else {
+        Some(to)
+    }
+}
+
+/// Returns the cell a step in a direction leads to
+/// # Arguments
+/// * `from` - The cell the step starts from
+/// * `direction` - The step, x to the right and y up
+pub fn target(from: (i32, i32), direction: (i32, i32)) -> (i32, i32) {
+    // Rows count down from the top of the map
+    (from.0 + direction.0, from.1 - direction.1)
+}
+
+/// Returns the action chosen by the input pressed this frame, if any
+/// # Arguments
+/// * `input` - The state of the input actions
+pub fn action_from_input(input: &InputState) -> Option<Action> {
+    let choices = [
+        (InputAction::MoveUp, Action::Move(0, 1)),
+        (InputAction::MoveDown, Action::Move(0, -1)),
+        (InputAction::MoveLeft, Action::Move(-1, 0)),
+        (InputAction::MoveRight, Action::Move(1, 0)),
+        (InputAction::Wait, Action::Wait)
+    ];
+
+    choices
+        .iter()
+        .find(|(input_action, _)| input.just_pressed(*input_action))
+        .map(|(_, action)| *action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The actors that take the next turns, each spending a full action
+    fn turns(scheduler: &mut Scheduler<char>, count: usize) -> Vec<char> {
+        (0..count)
+            .map(|_| {
+                let actor = scheduler.next_actor().unwrap();
+                scheduler.spend(actor, ACTION_COST);
+                actor
+            })
+            .collect()
+    }
+
+    #[test]
+    fn faster_actors_act_more_often() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add('s', 100);
+        scheduler.add('f', 200);
+
+        let turns = turns(&mut scheduler, 30);
+        let fast = turns.iter().filter(|actor| **actor == 'f').count();
+        let slow = turns.iter().filter(|actor| **actor == 's').count();
+        assert_eq!((fast, slow), (20, 10));
+
+        // The actor with more energy goes first, then the one added first on a tie
+        assert_eq!(&turns[..6], ['f', 's', 'f', 'f', 's', 'f']);
+    }
+
+    #[test]
+    fn the_fastest_actors_can_be_scheduled() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add('f', MAX_SPEED);
+        scheduler.add('s', 100);
+
+        // Every tick the fast actor acts a hundred times for each slow action
+        let turns = turns(&mut scheduler, 303);
+
+        assert_eq!(turns.iter().filter(|actor| **actor == 's').count(), 3);
+        assert!(scheduler.energy('f').unwrap() <= MAX_SPEED as i32);
+    }
+
+    #[test]
+    fn ties_go_to_the_actor_added_first() {
+        let schedule = || {
+            let mut scheduler = Scheduler::new();
+            scheduler.add('a', 100);
+            scheduler.add('b', 100);
+            scheduler.add('c', 50);
+            turns(&mut scheduler, 10)
+        };
+
+        let first = schedule();
+        assert_eq!(first, ['a', 'b', 'a', 'b', 'c', 'a', 'b', 'a', 'b', 'c']);
+        assert_eq!(schedule(), first);
+    }
+
+    #[test]
+    fn actors_that_can_never_act_are_skipped() {
+        let mut scheduler = Scheduler::new();
+        assert_eq!(scheduler.next_actor(), None);
+
+        scheduler.add('x', 0);
+        assert_eq!(scheduler.next_actor(), None);
+
+        scheduler.add('y', 100);
+        assert_eq!(turns(&mut scheduler, 3), ['y', 'y', 'y']);
+    }
+
+    #[test]
+    fn removed_actors_stop_acting() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add('a', 100);
+        scheduler.add('b', 100);
+        scheduler.remove('a');
+
+        assert!(!scheduler.contains('a'));
+        assert_eq!(turns(&mut scheduler, 2), ['b', 'b']);
+    }
+
+    #[test]
+    fn adding_again_changes_speed_but_keeps_energy() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add('a', 100);
+        scheduler.next_actor();
+        assert_eq!(scheduler.energy('a'), Some(100));
+
+        scheduler.add('a', 50);
+        assert_eq!(scheduler.energy('a'), Some(100));
+        assert_eq!(scheduler, {
+            let mut expected = Scheduler::new();
+            expected.add('a', 50);
+            expected.entries[0].energy = 100;
+            expected
+        });
+    }
+
+    #[test]
+    fn steps_go_up_the_screen() {
+        assert_eq!(target((3, 3), (0, 1)), (3, 2));
+        assert_eq!(target((3, 3), (1, 0)), (4, 3));
+    }
+}